@@ -1,6 +1,6 @@
 use anyhow::Result;
 use axum::{
-    extract::Json,
+    extract::{Json, State},
     http::StatusCode,
     response::{Html, IntoResponse},
     routing::{get, post},
@@ -16,6 +16,7 @@ use crossterm::terminal::{
 use crossterm::ExecutableCommand;
 use ddp_rs::connection::DDPConnection;
 use ddp_rs::protocol::{PixelConfig, ID};
+use md5::Md5;
 use notify::{Config, Event as NotifyEvent, RecommendedWatcher, RecursiveMode, Watcher};
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::{Constraint, Direction, Layout};
@@ -23,9 +24,13 @@ use ratatui::text::Line;
 use ratatui::widgets::{Block, Borders, Paragraph};
 use ratatui::Terminal;
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::VecDeque;
+use std::future::Future;
 use std::io::{self, Write};
 use std::net::UdpSocket;
 use std::path::PathBuf;
+use std::pin::Pin;
 use std::process::{Command as StdCommand, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc, Mutex};
@@ -97,16 +102,51 @@ struct Args {
 #[serde(default)]
 struct BandwidthConfig {
     max_gbps: f64,
+    scale: String,
+    scale_log_floor_kbps: f64,
+    scale_exponent: f64,
+    detect_link_speed: bool,
+    auto_range: bool,
+    auto_range_window_secs: f64,
+    auto_range_min_mbps: f64,
+    fill_metric: String,
+    max_pps: f64,
     color: String,
     tx_color: String,
     rx_color: String,
     direction: String,
     swap: bool,
     rx_split_percent: f64,
+    segments: String,
+    matrix_width: usize,
+    matrix_height: usize,
+    matrix_serpentine: bool,
+    matrix_rotation: u16,
+    matrix_mode: String,
     strobe_on_max: bool,
     strobe_rate_hz: f64,
     strobe_duration_ms: f64,
     strobe_color: String,
+    peak_hold: bool,
+    peak_color: String,
+    tx_peak_color: String,
+    rx_peak_color: String,
+    peak_hold_ms: f64,
+    peak_fall_percent_per_sec: f64,
+    error_sparkle: bool,
+    error_color: String,
+    stale_timeout_ms: f64,
+    no_data_pattern: String,
+    no_data_color: String,
+    gamma: f64,
+    brightness_percent: f64,
+    power_limit_ma: f64,
+    milliamps_per_channel: f64,
+    schedule: String,
+    schedule_fade_minutes: f64,
+    night_start: String,
+    night_end: String,
+    night_min_utilization_percent: f64,
     animation_speed: f64,
     scale_animation_speed: bool,
     tx_animation_direction: String,
@@ -114,9 +154,31 @@ struct BandwidthConfig {
     interpolation_time_ms: f64,
     wled_ip: String,
     interface: String,
+    sample_interval_ms: f64,
+    source: String,
+    snmp_host: String,
+    snmp_port: u16,
+    snmp_version: String,
+    // Secrets are never sent to web clients; the config file is written by to_toml, not Serialize
+    #[serde(skip_serializing)]
+    snmp_community: String,
+    snmp_username: String,
+    snmp_auth_protocol: String,
+    #[serde(skip_serializing)]
+    snmp_auth_password: String,
+    prometheus_url: String,
+    prometheus_rx_metric: String,
+    prometheus_tx_metric: String,
+    prometheus_selector: String,
     total_leds: usize,
     use_gradient: bool,
     interpolation: String,
+    color_mode: String,
+    zone_colors: String,
+    interface_colors: String,
+    display_mode: String,
+    history_style: String,
+    history_leds_per_sample: usize,
     fps: f64,
     httpd_enabled: bool,
     httpd_ip: String,
@@ -125,22 +187,107 @@ struct BandwidthConfig {
     test_rx: bool,
     test_tx_percent: f64,
     test_rx_percent: f64,
+    protocol: String,
+    universe: u16,
+    start_channel: u16,
+    e131_multicast: bool,
+    opc_channel: u8,
+    pixel_format: String,
+    white_mode: String,
+    targets: Vec<TargetConfig>,
+}
+
+// An additional WLED strip showing the same bandwidth; unset fields use the top-level settings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct TargetConfig {
+    wled_ip: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    total_leds: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    direction: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    swap: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rx_split_percent: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    segments: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    power_limit_ma: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matrix_width: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matrix_height: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matrix_serpentine: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matrix_rotation: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matrix_mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protocol: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    universe: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start_channel: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    e131_multicast: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    opc_channel: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pixel_format: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    white_mode: Option<String>,
 }
 
 impl Default for BandwidthConfig {
     fn default() -> Self {
         BandwidthConfig {
             max_gbps: 10.0,
+            scale: "linear".to_string(),
+            scale_log_floor_kbps: 1.0,
+            scale_exponent: 0.5,
+            detect_link_speed: false,
+            auto_range: false,
+            auto_range_window_secs: 60.0,
+            auto_range_min_mbps: 10.0,
+            fill_metric: "bandwidth".to_string(),
+            max_pps: 100000.0,
             color: "0099FF".to_string(),
             tx_color: "".to_string(),
             rx_color: "".to_string(),
             direction: "mirrored".to_string(),
             swap: false,
             rx_split_percent: 50.0,
+            segments: "".to_string(),
+            matrix_width: 0,
+            matrix_height: 0,
+            matrix_serpentine: true,
+            matrix_rotation: 0,
+            matrix_mode: "bars".to_string(),
             strobe_on_max: false,
             strobe_rate_hz: 3.0,
             strobe_duration_ms: 166.0,
             strobe_color: "000000".to_string(),
+            peak_hold: false,
+            peak_color: "FFFFFF".to_string(),
+            tx_peak_color: "".to_string(),
+            rx_peak_color: "".to_string(),
+            peak_hold_ms: 1500.0,
+            peak_fall_percent_per_sec: 50.0,
+            error_sparkle: false,
+            error_color: "FF0000".to_string(),
+            stale_timeout_ms: 5000.0,
+            no_data_pattern: "breathe".to_string(),
+            no_data_color: "FF8000".to_string(),
+            gamma: 1.0,
+            brightness_percent: 100.0,
+            power_limit_ma: 0.0,
+            milliamps_per_channel: 20.0,
+            schedule: "".to_string(),
+            schedule_fade_minutes: 15.0,
+            night_start: "".to_string(),
+            night_end: "".to_string(),
+            night_min_utilization_percent: 0.0,
             animation_speed: 1.0,
             scale_animation_speed: false,
             tx_animation_direction: "right".to_string(),
@@ -148,9 +295,28 @@ impl Default for BandwidthConfig {
             interpolation_time_ms: 1000.0,
             wled_ip: "led.local".to_string(),
             interface: "en0".to_string(),
+            sample_interval_ms: 1000.0,
+            source: "auto".to_string(),
+            snmp_host: "".to_string(),
+            snmp_port: 161,
+            snmp_version: "2c".to_string(),
+            snmp_community: "public".to_string(),
+            snmp_username: "".to_string(),
+            snmp_auth_protocol: "none".to_string(),
+            snmp_auth_password: "".to_string(),
+            prometheus_url: "".to_string(),
+            prometheus_rx_metric: "node_network_receive_bytes_total".to_string(),
+            prometheus_tx_metric: "node_network_transmit_bytes_total".to_string(),
+            prometheus_selector: "".to_string(),
             total_leds: 1200,
             use_gradient: true,
             interpolation: "linear".to_string(),
+            color_mode: "position".to_string(),
+            zone_colors: "00FF00,FFBF00@60,FF0000@85".to_string(),
+            interface_colors: "00C0FF,FF8000,C000FF,00FF80".to_string(),
+            display_mode: "bar".to_string(),
+            history_style: "brightness".to_string(),
+            history_leds_per_sample: 1,
             fps: 60.0,
             httpd_enabled: true,
             httpd_ip: "localhost".to_string(),
@@ -159,12 +325,115 @@ impl Default for BandwidthConfig {
             test_rx: false,
             test_tx_percent: 100.0,
             test_rx_percent: 100.0,
+            protocol: "ddp".to_string(),
+            universe: 1,
+            start_channel: 1,
+            e131_multicast: false,
+            opc_channel: 0,
+            pixel_format: "rgb".to_string(),
+            white_mode: "accurate".to_string(),
+            targets: Vec::new(),
         }
     }
 }
 
 impl BandwidthConfig {
 
+    // Every output to drive, the top-level wled_ip first, with unset target fields taken from the top level
+    fn target_outputs(&self) -> Vec<TargetOutput> {
+        let primary = TargetOutput {
+            address: self.wled_ip.clone(),
+            protocol: OutputProtocol::parse(&self.protocol),
+            universe: self.universe,
+            start_channel: self.start_channel,
+            e131_multicast: self.e131_multicast,
+            opc_channel: self.opc_channel,
+            encoding: PixelEncoding::parse(&self.pixel_format, &self.white_mode),
+        };
+        let extra = self.targets.iter().map(|t| TargetOutput {
+            address: t.wled_ip.clone(),
+            protocol: t
+                .protocol
+                .as_deref()
+                .map(OutputProtocol::parse)
+                .unwrap_or(primary.protocol),
+            universe: t.universe.unwrap_or(self.universe),
+            start_channel: t.start_channel.unwrap_or(self.start_channel),
+            e131_multicast: t.e131_multicast.unwrap_or(self.e131_multicast),
+            opc_channel: t.opc_channel.unwrap_or(self.opc_channel),
+            encoding: PixelEncoding::parse(
+                t.pixel_format.as_deref().unwrap_or(&self.pixel_format),
+                t.white_mode.as_deref().unwrap_or(&self.white_mode),
+            ),
+        });
+        std::iter::once(primary.clone()).chain(extra).collect()
+    }
+
+    // Layouts matching target_outputs, with unset target fields taken from the top level
+    fn target_layouts(&self) -> Vec<TargetLayout> {
+        let primary = TargetLayout {
+            total_leds: self.total_leds,
+            direction: parse_direction_mode(&self.direction),
+            swap: self.swap,
+            rx_split_percent: self.rx_split_percent,
+            segments: SegmentMap::parse(&self.segments, self.total_leds),
+            power_limit_ma: self.power_limit_ma,
+            matrix: MatrixLayout::parse(
+                self.matrix_width,
+                self.matrix_height,
+                self.matrix_serpentine,
+                self.matrix_rotation,
+                &self.matrix_mode,
+            ),
+        };
+        let extra = self.targets.iter().map(|t| TargetLayout {
+            total_leds: t.total_leds.unwrap_or(self.total_leds),
+            direction: t
+                .direction
+                .as_deref()
+                .map(parse_direction_mode)
+                .unwrap_or(primary.direction),
+            swap: t.swap.unwrap_or(self.swap),
+            rx_split_percent: t.rx_split_percent.unwrap_or(self.rx_split_percent),
+            segments: SegmentMap::parse(
+                t.segments.as_deref().unwrap_or(&self.segments),
+                t.total_leds.unwrap_or(self.total_leds),
+            ),
+            power_limit_ma: t.power_limit_ma.unwrap_or(self.power_limit_ma),
+            matrix: MatrixLayout::parse(
+                t.matrix_width.unwrap_or(self.matrix_width),
+                t.matrix_height.unwrap_or(self.matrix_height),
+                t.matrix_serpentine.unwrap_or(self.matrix_serpentine),
+                t.matrix_rotation.unwrap_or(self.matrix_rotation),
+                t.matrix_mode.as_deref().unwrap_or(&self.matrix_mode),
+            ),
+        });
+
+        // A matrix always has exactly width x height LEDs
+        std::iter::once(primary.clone())
+            .chain(extra)
+            .map(|mut layout| {
+                if let Some(matrix) = layout.matrix {
+                    layout.total_leds = matrix.width * matrix.height;
+                }
+                layout
+            })
+            .collect()
+    }
+
+    fn fills_by_packets(&self) -> bool {
+        self.fill_metric.eq_ignore_ascii_case("packets")
+    }
+
+    // The value that fills a bar completely: kbps, or packets per second with fill_metric = "packets"
+    fn fill_max(&self) -> f64 {
+        if self.fills_by_packets() {
+            self.max_pps.max(1.0)
+        } else {
+            self.max_gbps * 1000.0 * 1000.0
+        }
+    }
+
     fn load_or_default() -> Self {
         Self::load().unwrap_or_else(|_| Self::default())
     }
@@ -245,16 +514,67 @@ impl BandwidthConfig {
 
     fn save(&self) -> Result<()> {
         let path = Self::config_path()?;
+        std::fs::write(path, self.to_toml()?)?;
+        Ok(())
+    }
+
+    // The config file contents, with a comment above every setting
+    fn to_toml(&self) -> Result<String> {
+        // Targets are arrays of tables, which TOML requires after all plain keys
+        let targets_toml = if self.targets.is_empty() {
+            String::new()
+        } else {
+            #[derive(Serialize)]
+            struct Targets<'a> {
+                targets: &'a [TargetConfig],
+            }
+            format!("\n{}", toml::to_string(&Targets { targets: &self.targets })?)
+        };
 
         // Build TOML with comments manually for better documentation
         let contents = format!(
             r#"# Bandwidth Meter Configuration File
 # Edit this file while the program is running to change settings in real-time
-# Note: Changes to wled_ip and interface require restart
+# Note: Changes to wled_ip, the [[targets]] list and their protocol/pixel_format, interface,
+# sample_interval_ms, detect_link_speed, source and any snmp_*/prometheus_* setting require restart
 
 # Maximum bandwidth in Gbps for visualization scaling
 max_gbps = {}
 
+# How bandwidth maps onto the strip length
+# Options: "linear", "log" (scale_log_floor_kbps fills nothing, max_gbps fills everything),
+#          "sqrt", "power" (utilization raised to scale_exponent)
+# log and sqrt make light traffic visible on a strip sized for a fast link
+scale = "{}"
+
+# Bandwidth in kbps at which the log scale starts lighting LEDs
+scale_log_floor_kbps = {}
+
+# Exponent for the power scale (0.5 = sqrt, lower values expand low traffic further)
+scale_exponent = {}
+
+# Read the interface link speed at startup and use it instead of max_gbps (requires restart to change)
+# Uses /sys/class/net/<if>/speed on Linux and ifconfig media on macOS, locally or over SSH
+detect_link_speed = {}
+
+# Auto-range: scale to a decaying peak of recent traffic so the whole strip is used on quiet links
+# The max never goes above max_gbps (or the detected link speed) nor below auto_range_min_mbps
+auto_range = {}
+
+# How quickly the auto-range peak decays, in seconds (time constant)
+auto_range_window_secs = {}
+
+# Lowest max auto-range will scale to, in Mbps, so idle links don't light up on background noise
+auto_range_min_mbps = {}
+
+# What the bars measure
+# Options: "bandwidth" (bytes/s against max_gbps), "packets" (packets/s against max_pps)
+# Packet rates need the local, netstat or SSH source. auto_range only applies to bandwidth
+fill_metric = "{}"
+
+# Packets per second that fill the strip when fill_metric = "packets"
+max_pps = {}
+
 # Default LED color (hex, applies to both TX and RX if not overridden)
 # Can be single color: "FF0000" or gradient: "FF0000,00FF00,0000FF"
 color = "{}"
@@ -280,6 +600,28 @@ swap = {}
 # Example: 50.0 = 50/50 split, 70.0 = 70/30 split (RX/TX)
 rx_split_percent = {}
 
+# Segment map for strips wired in several runs, replaces direction and rx_split_percent when set
+# Comma-separated "first-last:role" entries, role is tx, rx, status or unused. Each segment lights
+# from first towards last, add ":reverse" to fill it the other way. Segments of the same role join
+# up in the order listed. Example: "0-59:rx, 60-61:unused, 119-62:tx, 120:status"
+segments = "{}"
+
+# 2D matrix panels (e.g. 16x16 or 32x8 WLED matrices). Set width and height to use a matrix,
+# total_leds is then width x height. 0 = normal strip
+matrix_width = {}
+matrix_height = {}
+
+# Every other row of the panel is wired right to left
+matrix_serpentine = {}
+
+# Clockwise rotation of the panel as mounted: 0, 90, 180 or 270
+matrix_rotation = {}
+
+# What a matrix shows
+# Options: "bars" (RX and TX as vertical bar graphs), "history" (scrolling columns, TX up and RX down),
+#          "spectrum" (scrolling rows, newest on top, RX left and TX right of the center)
+matrix_mode = "{}"
+
 # Strobe entire RX or TX segment when bandwidth exceeds max
 # When enabled, the entire segment will flash on/off when at max utilization
 strobe_on_max = {}
@@ -297,6 +639,67 @@ strobe_duration_ms = {}
 # Default is "000000" (black/off). Can be any hex color like "FF0000" for red
 strobe_color = "{}"
 
+# Peak-hold marker: one pixel per direction marking the highest recent utilization
+peak_hold = {}
+
+# Peak marker color in hex, with optional TX/RX overrides (empty = use peak_color)
+peak_color = "{}"
+tx_peak_color = "{}"
+rx_peak_color = "{}"
+
+# How long the marker stays at a new peak before falling, in milliseconds
+peak_hold_ms = {}
+
+# How fast the marker falls afterwards, in percent of the bar per second
+peak_fall_percent_per_sec = {}
+
+# Flash sparkle pixels over the RX or TX half for every interface error or dropped packet
+# Needs the local, netstat or SSH source
+error_sparkle = {}
+
+# Color of the error sparkles (hex)
+error_color = "{}"
+
+# Show the no data pattern after this long without a sample, in milliseconds (0 = never)
+# Never shorter than three sample intervals
+stale_timeout_ms = {}
+
+# What the strip shows while data is stale or the source is lost (e.g. a dropped SSH session)
+# Options: "breathe" (whole strip pulses dimly), "blink" (only the end pixel of each bar blinks)
+no_data_pattern = "{}"
+
+# Color of the no data pattern (hex, 000000 = dark)
+no_data_color = "{}"
+
+# Gamma curve applied to every frame (1.0 = off, 2.2-2.8 matches how LEDs look to the eye)
+gamma = {}
+
+# Global brightness in percent (0-100)
+brightness_percent = {}
+
+# Automatic brightness limiter: estimated current budget of the LED power supply in mA
+# Frames that would draw more are dimmed evenly, like WLED's ABL (0 = disabled)
+# Applies per target, so each [[targets]] entry can set its own power_limit_ma
+power_limit_ma = {}
+
+# Current of one LED channel at full brightness in mA (WS2812/SK6812 draw about 20 mA)
+milliamps_per_channel = {}
+
+# Time-of-day brightness schedule in local time, as comma-separated HH:MM=percent entries
+# Each level holds until the next entry, wrapping around midnight. "off" and "on" work as levels too
+# Example: "07:00=100, 22:00=30, 23:30=off" (empty = always full brightness)
+schedule = "{}"
+
+# Minutes to fade from the previous level when a schedule entry starts (0 = switch instantly)
+schedule_fade_minutes = {}
+
+# Night window in local time (HH:MM), may span midnight. Empty disables the night rule
+night_start = "{}"
+night_end = "{}"
+
+# At night, only light up while RX or TX utilization is above this percent of max (0 = always)
+night_min_utilization_percent = {}
+
 # Animation speed in LEDs per frame (0.0 = disabled, 1.0 = 60 LEDs/sec)
 # Controls how fast gradients travel along the strip
 animation_speed = {}
@@ -326,6 +729,44 @@ wled_ip = "{}"
 # Can be single interface "eth0" or combined with comma "eth0,eth1"
 interface = "{}"
 
+# Bandwidth sample interval in milliseconds (requires restart to change)
+# Applies to every source (local Linux, macOS netstat and remote SSH)
+# Sub-second values like 250 or 100 catch short bursts; lower interpolation_time_ms to match
+sample_interval_ms = {}
+
+# Bandwidth source (requires restart to change)
+# Options: "auto" (netstat on macOS, /proc/net/dev on Linux, SSH with -H), "snmp", "prometheus"
+source = "{}"
+
+# SNMP agent to poll when source = "snmp" (requires restart to change)
+# interface is matched against ifName/ifDescr, or used directly as an ifIndex if numeric
+snmp_host = "{}"
+snmp_port = {}
+
+# SNMP version: "2c" or "3"
+snmp_version = "{}"
+
+# SNMP v2c community string
+snmp_community = {}
+
+# SNMP v3 user and authentication (noAuthNoPriv or authNoPriv, privacy is not supported)
+# snmp_auth_protocol options: "none", "md5", "sha"
+snmp_username = {}
+snmp_auth_protocol = "{}"
+snmp_auth_password = {}
+
+# Prometheus endpoint to scrape when source = "prometheus" (requires restart to change)
+# e.g. "http://server:9100/metrics" for node_exporter
+prometheus_url = "{}"
+
+# Byte counter metrics for RX and TX
+prometheus_rx_metric = "{}"
+prometheus_tx_metric = "{}"
+
+# Label selector picking the series to use, e.g. device="eth0" or device="eth0",instance!="backup"
+# Only = and != matchers are supported. When empty, the device label is matched against interface
+prometheus_selector = {}
+
 # Total number of LEDs in the strip (can be changed while running)
 # TX uses first half (0-N/2), RX uses second half (N/2-N)
 total_leds = {}
@@ -338,6 +779,31 @@ use_gradient = {}
 # Options: "linear" (sharp), "basis" (smooth B-spline), "catmullrom" (smooth Catmull-Rom)
 interpolation = "{}"
 
+# How LEDs are colored
+# Options: "position" (colors above, by LED position with animation),
+#          "zones" (the whole bar takes the zone color for its current fill level),
+#          "interfaces" (with several interfaces, each one's share of the bar in its own color)
+color_mode = "{}"
+
+# Zone colors for color_mode = "zones", same syntax as color with an optional @percent stop per color
+# Each color applies from its stop up to the next one. Colors without a stop split the bar evenly
+zone_colors = "{}"
+
+# Band colors for color_mode = "interfaces", one per interface in the order of the interface setting
+interface_colors = "{}"
+
+# What the strip shows
+# Options: "bar" (current utilization as a filling bar),
+#          "history" (scrolling time series, one bandwidth sample per LED group, newest at the start of each half)
+display_mode = "{}"
+
+# How utilization is shown in history mode
+# Options: "brightness" (TX/RX color dimmed to utilization), "hue" (blue when idle to red at max)
+history_style = "{}"
+
+# LEDs per history sample (wider groups show a shorter time span)
+history_leds_per_sample = {}
+
 # Rendering frame rate (can be changed while running)
 # Try different values like 30, 60, 120, 144 to reduce stuttering
 fps = {}
@@ -368,18 +834,96 @@ test_tx_percent = {}
 # Test Mode - RX bandwidth utilization percentage (0-100)
 # Controls how much of max bandwidth to simulate for RX when test_rx is enabled
 test_rx_percent = {}
-"#,
+
+# Output protocol (requires restart to change)
+# Options: "ddp" (port 4048), "e131" (sACN, port 5568), "artnet" (port 6454),
+#          "wled" (WLED realtime UDP on port 21324: DRGB up to 490 LEDs, DNRGB beyond),
+#          "warls", "drgb", "dnrgb" (force one WLED UDP format), "json" (slow JSON API fallback),
+#          "opc" (Open Pixel Control over TCP, port 7890 unless wled_ip is "host:port")
+protocol = "{}"
+
+# First DMX universe and start channel (1-512) for e131 and artnet
+# Strips longer than one universe (170 RGB pixels) continue on the following universes at channel 1
+universe = {}
+start_channel = {}
+
+# Send e131 to the multicast group 239.255.<universe> instead of wled_ip
+e131_multicast = {}
+
+# OPC channel for opc (0 sends to every channel, Fadecandy uses 1-255 per board)
+opc_channel = {}
+
+# Channel order sent to the strip (requires restart to change)
+# Any order of r, g and b, plus w for RGBW strips: "rgb", "grb", "bgr", "rgbw", "grbw", ...
+# The WLED UDP and JSON protocols always send RGB, WLED applies its own color order. OPC is always RGB too
+pixel_format = "{}"
+
+# How the white channel is derived on RGBW formats
+# Options: "accurate" (move common RGB to white), "brighter" (add white, keep RGB), "none" (white off)
+white_mode = "{}"
+
+# Additional WLED strips showing the same bandwidth (requires restart to add or remove targets)
+# Each [[targets]] entry needs a wled_ip. total_leds, direction, swap, rx_split_percent, segments,
+# power_limit_ma, matrix_width, matrix_height, matrix_serpentine, matrix_rotation, matrix_mode, protocol, universe,
+# start_channel, e131_multicast, opc_channel, pixel_format and white_mode default to the settings above
+# when left out. Layout, matrix and power_limit_ma fields can be changed while running. Example:
+#
+# [[targets]]
+# wled_ip = "kitchen.local"
+# total_leds = 300
+# direction = "opposing"
+#
+# [[targets]]
+# wled_ip = "10.0.0.50"
+# protocol = "artnet"
+# universe = 0
+{}"#,
             self.max_gbps,
+            self.scale,
+            self.scale_log_floor_kbps,
+            self.scale_exponent,
+            self.detect_link_speed,
+            self.auto_range,
+            self.auto_range_window_secs,
+            self.auto_range_min_mbps,
+            self.fill_metric,
+            self.max_pps,
             self.color,
             self.tx_color,
             self.rx_color,
             self.direction,
             self.swap,
             self.rx_split_percent,
+            self.segments,
+            self.matrix_width,
+            self.matrix_height,
+            self.matrix_serpentine,
+            self.matrix_rotation,
+            self.matrix_mode,
             self.strobe_on_max,
             self.strobe_rate_hz,
             self.strobe_duration_ms,
             self.strobe_color,
+            self.peak_hold,
+            self.peak_color,
+            self.tx_peak_color,
+            self.rx_peak_color,
+            self.peak_hold_ms,
+            self.peak_fall_percent_per_sec,
+            self.error_sparkle,
+            self.error_color,
+            self.stale_timeout_ms,
+            self.no_data_pattern,
+            self.no_data_color,
+            self.gamma,
+            self.brightness_percent,
+            self.power_limit_ma,
+            self.milliamps_per_channel,
+            self.schedule,
+            self.schedule_fade_minutes,
+            self.night_start,
+            self.night_end,
+            self.night_min_utilization_percent,
             self.animation_speed,
             self.scale_animation_speed,
             self.tx_animation_direction,
@@ -387,9 +931,28 @@ test_rx_percent = {}
             self.interpolation_time_ms,
             self.wled_ip,
             self.interface,
+            self.sample_interval_ms,
+            self.source,
+            self.snmp_host,
+            self.snmp_port,
+            self.snmp_version,
+            toml::Value::String(self.snmp_community.clone()),
+            toml::Value::String(self.snmp_username.clone()),
+            self.snmp_auth_protocol,
+            toml::Value::String(self.snmp_auth_password.clone()),
+            self.prometheus_url,
+            self.prometheus_rx_metric,
+            self.prometheus_tx_metric,
+            toml::Value::String(self.prometheus_selector.clone()),
             self.total_leds,
             self.use_gradient,
             self.interpolation,
+            self.color_mode,
+            self.zone_colors,
+            self.interface_colors,
+            self.display_mode,
+            self.history_style,
+            self.history_leds_per_sample,
             self.fps,
             self.httpd_enabled,
             self.httpd_ip,
@@ -398,10 +961,17 @@ test_rx_percent = {}
             self.test_rx,
             self.test_tx_percent,
             self.test_rx_percent,
+            self.protocol,
+            self.universe,
+            self.start_channel,
+            self.e131_multicast,
+            self.opc_channel,
+            self.pixel_format,
+            self.white_mode,
+            targets_toml,
         );
 
-        std::fs::write(path, contents)?;
-        Ok(())
+        Ok(contents)
     }
 }
 
@@ -420,2490 +990,8705 @@ enum DirectionMode {
     Right,
 }
 
-// Shared state between main thread and render thread
-#[derive(Clone)]
-struct SharedRenderState {
-    current_rx_kbps: f64,
-    current_tx_kbps: f64,
-    start_rx_kbps: f64,
-    start_tx_kbps: f64,
-    last_bandwidth_update: Option<Instant>,
-    animation_speed: f64,
-    scale_animation_speed: bool,
-    tx_animation_direction: String,
-    rx_animation_direction: String,
-    interpolation_time_ms: f64,
-    max_bandwidth_kbps: f64,
-
-    // Color configuration (as strings, renderer will rebuild gradients when changed)
-    tx_color: String,
-    rx_color: String,
-    use_gradient: bool,
-    interpolation_mode: InterpolationMode,
+fn parse_direction_mode(direction: &str) -> DirectionMode {
+    match direction.to_lowercase().as_str() {
+        "mirrored" => DirectionMode::Mirrored,
+        "opposing" => DirectionMode::Opposing,
+        "left" => DirectionMode::Left,
+        "right" => DirectionMode::Right,
+        _ => DirectionMode::Mirrored,
+    }
+}
 
-    // Rendering configuration
+// How one strip lays out RX and TX; the first layout is always the top-level wled_ip strip
+#[derive(Debug, Clone)]
+struct TargetLayout {
+    total_leds: usize,
     direction: DirectionMode,
     swap: bool,
-    fps: f64,
-    total_leds: usize,
     rx_split_percent: f64,
-    strobe_on_max: bool,
-    strobe_rate_hz: f64,
-    strobe_duration_ms: f64,
-    strobe_color: String,
+    segments: Option<SegmentMap>,
+    power_limit_ma: f64,
+    matrix: Option<MatrixLayout>,
+}
 
-    // Generation counter to detect changes
-    generation: u64,
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SegmentRole {
+    Tx,
+    Rx,
+    Status,
+    Unused,
 }
 
-#[derive(Clone, Copy, Debug)]
-struct Rgb {
-    r: u8,
-    g: u8,
-    b: u8,
+// LEDs of a strip assigned by an explicit segment map instead of the two halves
+#[derive(Debug, Clone, PartialEq)]
+struct SegmentMap {
+    // LED indices in fill order, the bar grows from the first entry
+    tx: Vec<usize>,
+    rx: Vec<usize>,
+    status: Vec<usize>,
 }
 
-impl Rgb {
-    fn from_hex(hex: &str) -> Result<Self> {
-        let hex = hex.trim_start_matches('#');
-        if hex.len() != 6 {
-            anyhow::bail!("Invalid hex color: {}", hex);
+impl SegmentMap {
+    // Parses "0-59:rx, 60-61:unused, 119-62:tx, 120:status"; invalid entries and LEDs past total_leds are skipped.
+    // An LED listed in more than one segment belongs to the first of them
+    fn parse(spec: &str, total_leds: usize) -> Option<Self> {
+        let mut map = SegmentMap { tx: Vec::new(), rx: Vec::new(), status: Vec::new() };
+        let mut claimed = vec![false; total_leds];
+        let mut any = false;
+
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let mut parts = entry.split(':').map(str::trim);
+            let range = parts.next().unwrap_or_default();
+            let (first, last) = match range.split_once('-') {
+                Some((first, last)) => (first.trim().parse::<usize>(), last.trim().parse::<usize>()),
+                None => (range.parse::<usize>(), range.parse::<usize>()),
+            };
+            let (Ok(first), Ok(last)) = (first, last) else {
+                continue;
+            };
+            let role = match parts.next().map(str::to_lowercase).as_deref() {
+                Some("tx") => SegmentRole::Tx,
+                Some("rx") => SegmentRole::Rx,
+                Some("status") => SegmentRole::Status,
+                Some("unused") | Some("off") => SegmentRole::Unused,
+                _ => continue,
+            };
+            let reverse = parts.any(|flag| flag.eq_ignore_ascii_case("reverse"));
+            any = true;
+
+            // Clamp to the strip before building the run, so a huge range can't exhaust memory
+            if first.min(last) >= total_leds {
+                continue;
+            }
+            let (first, last) = (first.min(total_leds - 1), last.min(total_leds - 1));
+            let mut leds: Vec<usize> = if first <= last {
+                (first..=last).collect()
+            } else {
+                (last..=first).rev().collect()
+            };
+            if reverse {
+                leds.reverse();
+            }
+            leds.retain(|&led| led < total_leds && !std::mem::replace(&mut claimed[led], true));
+
+            match role {
+                SegmentRole::Tx => map.tx.extend(leds),
+                SegmentRole::Rx => map.rx.extend(leds),
+                SegmentRole::Status => map.status.extend(leds),
+                SegmentRole::Unused => {}
+            }
         }
-        Ok(Rgb {
-            r: u8::from_str_radix(&hex[0..2], 16)?,
-            g: u8::from_str_radix(&hex[2..4], 16)?,
-            b: u8::from_str_radix(&hex[4..6], 16)?,
-        })
+
+        any.then_some(map)
     }
 }
 
-// Helper function to build gradient from color string
-fn build_gradient_from_color(
-    color_str: &str,
-    use_gradient: bool,
-    interpolation_mode: InterpolationMode,
-) -> Result<(Option<colorgrad::Gradient>, Vec<Rgb>, Rgb)> {
-    let hex_colors: Vec<&str> = color_str.split(',').map(|s| s.trim()).collect();
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MatrixMode {
+    // RX and TX as two vertical bar graphs side by side
+    Bars,
+    // Scrolling columns, newest on the right, TX rising from the middle and RX falling from it
+    History,
+    // Scrolling rows, newest on top, RX extending left and TX right from the center
+    Spectrum,
+}
+
+// A 2D WLED panel: physical size, wiring and how it is mounted
+#[derive(Debug, Clone, Copy)]
+struct MatrixLayout {
+    width: usize,
+    height: usize,
+    // Every other row runs right to left
+    serpentine: bool,
+    // Clockwise rotation of the panel in degrees (0, 90, 180, 270)
+    rotation: u16,
+    mode: MatrixMode,
+}
 
-    // Parse all colors into RGB
-    let mut rgb_colors = Vec::new();
-    for hex in hex_colors.iter() {
-        rgb_colors.push(Rgb::from_hex(hex)?);
+impl MatrixLayout {
+    fn parse(width: usize, height: usize, serpentine: bool, rotation: u16, mode: &str) -> Option<Self> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        Some(MatrixLayout {
+            width,
+            height,
+            serpentine,
+            rotation: (rotation / 90 % 4) * 90,
+            mode: match mode.to_lowercase().as_str() {
+                "history" => MatrixMode::History,
+                "spectrum" | "waterfall" => MatrixMode::Spectrum,
+                _ => MatrixMode::Bars,
+            },
+        })
     }
 
-    // Build gradient only if we have multiple colors and use_gradient is enabled
-    let gradient = if rgb_colors.len() >= 2 && use_gradient {
-        let mut colorgrad_colors = Vec::new();
-        let mut domain = Vec::new();
+    // Size of the picture as seen on the wall, after rotation
+    fn display_size(&self) -> (usize, usize) {
+        if self.rotation % 180 == 90 {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        }
+    }
 
-        // Create gradient with color plateaus and smooth transitions
-        // Each color occupies ~80% of its segment as pure color, ~20% for smooth fade
-        let n = rgb_colors.len();
-        let segment_size = 1.0 / n as f64;
-        let transition_size = segment_size * 0.1; // 10% on each side for transition
+    // LED index of a display pixel, x from the left and y from the top
+    fn index(&self, x: usize, y: usize) -> usize {
+        let (px, py) = match self.rotation {
+            90 => (y, self.height - 1 - x),
+            180 => (self.width - 1 - x, self.height - 1 - y),
+            270 => (self.width - 1 - y, x),
+            _ => (x, y),
+        };
+        let px = if self.serpentine && py % 2 == 1 { self.width - 1 - px } else { px };
+        py * self.width + px
+    }
+}
 
-        for (i, rgb) in rgb_colors.iter().enumerate() {
-            let color = Color::from_rgba8(rgb.r, rgb.g, rgb.b, 255);
-            let segment_start = i as f64 * segment_size;
-            let segment_end = (i as f64 + 1.0) * segment_size;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScaleMode {
+    Linear,
+    Log,
+    Power,
+}
 
-            // Start of plateau (after transition from previous color)
-            let plateau_start = segment_start + transition_size;
-            // End of plateau (before transition to next color)
-            let plateau_end = segment_end - transition_size;
+// Maps a bandwidth reading to the fraction of the available LEDs to light
+#[derive(Debug, Clone, Copy)]
+struct BandwidthScale {
+    mode: ScaleMode,
+    log_floor_kbps: f64,
+    exponent: f64,
+}
 
-            if i == 0 {
-                // First color: add at position 0.0
-                colorgrad_colors.push(color.clone());
-                domain.push(0.0);
-            } else {
-                // Transition from previous color
-                colorgrad_colors.push(color.clone());
-                domain.push(plateau_start);
+impl BandwidthScale {
+    fn from_config(config: &BandwidthConfig) -> Self {
+        let (mode, exponent) = match config.scale.to_lowercase().as_str() {
+            "log" | "logarithmic" => (ScaleMode::Log, 1.0),
+            "sqrt" => (ScaleMode::Power, 0.5),
+            "power" | "pow" => (ScaleMode::Power, config.scale_exponent),
+            _ => (ScaleMode::Linear, 1.0),
+        };
+        BandwidthScale {
+            mode,
+            log_floor_kbps: config.scale_log_floor_kbps.max(0.001),
+            exponent: if exponent > 0.0 { exponent } else { 1.0 },
+        }
+    }
+
+    fn fraction(&self, bandwidth_kbps: f64, max_bandwidth_kbps: f64) -> f64 {
+        if bandwidth_kbps <= 0.0 || max_bandwidth_kbps <= 0.0 {
+            return 0.0;
+        }
+
+        let fraction = match self.mode {
+            ScaleMode::Linear => bandwidth_kbps / max_bandwidth_kbps,
+            ScaleMode::Power => (bandwidth_kbps / max_bandwidth_kbps).powf(self.exponent),
+            ScaleMode::Log => {
+                // Floor at or above max leaves no range to spread over, fall back to linear
+                if max_bandwidth_kbps <= self.log_floor_kbps {
+                    bandwidth_kbps / max_bandwidth_kbps
+                } else if bandwidth_kbps <= self.log_floor_kbps {
+                    0.0
+                } else {
+                    (bandwidth_kbps / self.log_floor_kbps).ln() / (max_bandwidth_kbps / self.log_floor_kbps).ln()
+                }
             }
+        };
+        fraction.clamp(0.0, 1.0)
+    }
+}
 
-            // End of this color's plateau
-            if i < n - 1 {
-                colorgrad_colors.push(color);
-                domain.push(plateau_end);
+// Per-frame color correction applied after composition: gamma, brightness and current limiting
+#[derive(Debug, Clone, Copy)]
+struct OutputCorrection {
+    // Gamma curve with brightness folded in
+    lut: [u8; 256],
+    milliamps_per_channel: f64,
+}
+
+impl OutputCorrection {
+    // Standby draw of a WS281x LED with all channels off, as assumed by WLED
+    const IDLE_MILLIAMPS_PER_LED: f64 = 1.0;
+
+    fn from_config(config: &BandwidthConfig) -> Self {
+        let gamma = if config.gamma > 0.0 { config.gamma } else { 1.0 };
+        let brightness = (config.brightness_percent / 100.0).clamp(0.0, 1.0);
+        let mut lut = [0u8; 256];
+        for (value, out) in lut.iter_mut().enumerate() {
+            *out = ((value as f64 / 255.0).powf(gamma) * brightness * 255.0).round() as u8;
+        }
+        OutputCorrection {
+            lut,
+            milliamps_per_channel: config.milliamps_per_channel.max(0.0),
+        }
+    }
+
+    // level is the schedule brightness (0.0-1.0) on top of the configured brightness.
+    // encoding is what the target sends, so a white channel counts towards the power limit
+    fn apply(&self, frame: &mut [u8], level: f64, power_limit_ma: f64, encoding: &PixelEncoding) {
+        if level >= 1.0 {
+            for value in frame.iter_mut() {
+                *value = self.lut[*value as usize];
+            }
+        } else {
+            let level = level.max(0.0);
+            for value in frame.iter_mut() {
+                *value = (self.lut[*value as usize] as f64 * level).round() as u8;
             }
         }
 
-        // Add first color at end to make it cyclic
-        if let Some(first_rgb) = rgb_colors.first() {
-            let first_color = Color::from_rgba8(first_rgb.r, first_rgb.g, first_rgb.b, 255);
-            colorgrad_colors.push(first_color.clone());
-            domain.push(1.0 - transition_size);
-            colorgrad_colors.push(first_color);
-            domain.push(1.0);
+        if power_limit_ma <= 0.0 || self.milliamps_per_channel <= 0.0 {
+            return;
         }
 
-        let cg_interpolation = match interpolation_mode {
-            InterpolationMode::Basis => colorgrad::Interpolation::Basis,
-            InterpolationMode::CatmullRom => colorgrad::Interpolation::CatmullRom,
-            _ => colorgrad::Interpolation::Linear,
-        };
+        // Estimate the draw like WLED's ABL and dim the whole frame evenly to fit the budget
+        let idle_ma = (frame.len() / 3) as f64 * Self::IDLE_MILLIAMPS_PER_LED;
+        let channel_ma = self.channel_milliamps(frame, encoding);
+        if idle_ma + channel_ma > power_limit_ma {
+            let factor = ((power_limit_ma - idle_ma) / channel_ma).clamp(0.0, 1.0);
+            for value in frame.iter_mut() {
+                *value = (*value as f64 * factor) as u8;
+            }
+        }
+    }
 
-        let gradient = colorgrad::CustomGradient::new()
-            .colors(&colorgrad_colors)
-            .domain(&domain)
-            .interpolation(cg_interpolation)
-            .build()?;
+    // Estimated draw of the lit channels of an RGB frame once encoded for the strip
+    fn channel_milliamps(&self, frame: &[u8], encoding: &PixelEncoding) -> f64 {
+        encoding.channel_total(frame) as f64 / 255.0 * self.milliamps_per_channel
+    }
+}
 
-        Some(gradient)
-    } else {
-        None
-    };
+// Time-of-day brightness levels, e.g. "07:00=100, 22:00=30, 23:30=off"
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScheduleEntry {
+    minute: u32,
+    level: f64,
+}
 
-    let solid_color = if !rgb_colors.is_empty() {
-        rgb_colors[0]
-    } else {
-        // Default fallback
-        Rgb::from_hex("0099FF")?
-    };
+fn parse_time_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let hours: u32 = hours.trim().parse().ok()?;
+    let minutes: u32 = minutes.trim().parse().ok()?;
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
 
-    Ok((gradient, rgb_colors, solid_color))
+fn parse_schedule(schedule: &str) -> Vec<ScheduleEntry> {
+    let mut entries: Vec<ScheduleEntry> = schedule
+        .split(',')
+        .filter_map(|entry| {
+            let (time, level) = entry.split_once('=')?;
+            let level = match level.trim().trim_end_matches('%').to_lowercase().as_str() {
+                "off" => 0.0,
+                "on" => 100.0,
+                percent => percent.parse::<f64>().ok()?,
+            };
+            Some(ScheduleEntry {
+                minute: parse_time_of_day(time)?,
+                level: (level / 100.0).clamp(0.0, 1.0),
+            })
+        })
+        .collect();
+    entries.sort_by_key(|e| e.minute);
+    entries
 }
 
-// Dedicated renderer that runs in its own thread at configurable FPS
-struct Renderer {
-    ddp_conn: DDPConnection,
-    shared_state: Arc<Mutex<SharedRenderState>>,
-    shutdown: Arc<AtomicBool>,
+#[derive(Debug, Clone, PartialEq)]
+struct Schedule {
+    entries: Vec<ScheduleEntry>,
+    fade_minutes: f64,
+    // Start and end minute of the night window, which may wrap past midnight
+    night: Option<(u32, u32)>,
+    night_min_utilization: f64,
+    // Whether night traffic is above the threshold, with hysteresis
+    night_lit: bool,
+    // Night rule multiplier (0.0-1.0) ramping toward lit or dark, None until the first frame
+    night_gate: Option<f64>,
+}
 
-    // Owned by renderer thread
-    tx_animation_offset: f64,
-    rx_animation_offset: f64,
+impl Schedule {
+    const MINUTES_PER_DAY: f64 = 1440.0;
+    // At night the strip turns back off only below this fraction of the threshold, so traffic
+    // hovering around night_min_utilization doesn't flash it on and off
+    const NIGHT_OFF_FRACTION: f64 = 0.8;
 
-    // Built from shared state
-    tx_gradient: Option<colorgrad::Gradient>,
-    rx_gradient: Option<colorgrad::Gradient>,
-    tx_colors: Vec<Rgb>,
-    rx_colors: Vec<Rgb>,
-    tx_solid_color: Rgb,
-    rx_solid_color: Rgb,
+    fn from_config(config: &BandwidthConfig) -> Self {
+        let night = match (parse_time_of_day(&config.night_start), parse_time_of_day(&config.night_end)) {
+            (Some(start), Some(end)) if start != end => Some((start, end)),
+            _ => None,
+        };
+        Schedule {
+            entries: parse_schedule(&config.schedule),
+            fade_minutes: config.schedule_fade_minutes.max(0.0),
+            night,
+            night_min_utilization: (config.night_min_utilization_percent / 100.0).max(0.0),
+            night_lit: false,
+            night_gate: None,
+        }
+    }
 
-    // Cache to detect when gradients need rebuilding
-    last_generation: u64,
-}
+    fn now_minutes() -> f64 {
+        use chrono::Timelike;
+        let now = chrono::Local::now();
+        now.hour() as f64 * 60.0 + now.minute() as f64 + now.second() as f64 / 60.0
+    }
 
-impl Renderer {
-    fn new(
-        ddp_conn: DDPConnection,
-        shared_state: Arc<Mutex<SharedRenderState>>,
-        shutdown: Arc<AtomicBool>,
-    ) -> Result<Self> {
-        // Lock shared state to get initial colors
-        let state = shared_state.lock().unwrap();
-        let (tx_gradient, tx_colors, tx_solid_color) =
-            build_gradient_from_color(&state.tx_color, state.use_gradient, state.interpolation_mode)?;
-        let (rx_gradient, rx_colors, rx_solid_color) =
-            build_gradient_from_color(&state.rx_color, state.use_gradient, state.interpolation_mode)?;
-        let last_generation = state.generation;
-        drop(state);
+    // Brightness level (0.0-1.0) at a minute of the day, fading in from the previous entry
+    fn level_at(&self, now: f64) -> f64 {
+        let Some(last) = self.entries.last() else {
+            return 1.0;
+        };
 
-        Ok(Renderer {
-            ddp_conn,
-            shared_state,
-            shutdown,
-            tx_animation_offset: 0.0,
-            rx_animation_offset: 0.0,
-            tx_gradient,
-            rx_gradient,
-            tx_colors,
-            rx_colors,
-            tx_solid_color,
-            rx_solid_color,
-            last_generation,
-        })
+        // The active entry is the latest one that has started, wrapping to yesterday's last entry
+        let index = self.entries.iter().rposition(|e| e.minute as f64 <= now);
+        let (active, previous) = match index {
+            Some(i) => (self.entries[i], self.entries[(i + self.entries.len() - 1) % self.entries.len()]),
+            None => (*last, self.entries[self.entries.len().saturating_sub(2)]),
+        };
+
+        let since_start = (now - active.minute as f64).rem_euclid(Self::MINUTES_PER_DAY);
+        if self.fade_minutes > 0.0 && since_start < self.fade_minutes {
+            let t = since_start / self.fade_minutes;
+            previous.level + (active.level - previous.level) * t
+        } else {
+            active.level
+        }
     }
 
-    fn rebuild_gradients_if_needed(&mut self) -> Result<()> {
-        let state = self.shared_state.lock().unwrap();
+    fn is_night(&self, now: f64) -> bool {
+        match self.night {
+            Some((start, end)) if start < end => now >= start as f64 && now < end as f64,
+            Some((start, end)) => now >= start as f64 || now < end as f64,
+            None => false,
+        }
+    }
 
-        // Check if generation changed (config updated)
-        if state.generation != self.last_generation {
-            let (tx_gradient, tx_colors, tx_solid_color) =
-                build_gradient_from_color(&state.tx_color, state.use_gradient, state.interpolation_mode)?;
-            let (rx_gradient, rx_colors, rx_solid_color) =
-                build_gradient_from_color(&state.rx_color, state.use_gradient, state.interpolation_mode)?;
+    // Level for the current frame, fading out at night while utilization stays below the threshold
+    fn level(&mut self, utilization: f64, delta_seconds: f64) -> f64 {
+        if self.entries.is_empty() && self.night.is_none() {
+            return 1.0;
+        }
+        self.level_with(Self::now_minutes(), utilization, delta_seconds)
+    }
 
-            self.tx_gradient = tx_gradient;
-            self.tx_colors = tx_colors;
-            self.tx_solid_color = tx_solid_color;
-            self.rx_gradient = rx_gradient;
-            self.rx_colors = rx_colors;
-            self.rx_solid_color = rx_solid_color;
-            self.last_generation = state.generation;
+    fn level_with(&mut self, now: f64, utilization: f64, delta_seconds: f64) -> f64 {
+        if utilization >= self.night_min_utilization {
+            self.night_lit = true;
+        } else if utilization < self.night_min_utilization * Self::NIGHT_OFF_FRACTION {
+            self.night_lit = false;
         }
 
-        Ok(())
-    }
+        // The night rule ramps over the same fade time as schedule entries
+        let target = if self.night_lit || !self.is_night(now) { 1.0 } else { 0.0 };
+        let gate = match self.night_gate {
+            Some(gate) if self.fade_minutes > 0.0 => {
+                let step = delta_seconds / (self.fade_minutes * 60.0);
+                if gate < target {
+                    (gate + step).min(target)
+                } else {
+                    (gate - step).max(target)
+                }
+            }
+            _ => target,
+        };
+        self.night_gate = Some(gate);
 
-    fn calculate_leds(&self, bandwidth_kbps: f64, max_bandwidth_kbps: f64, leds_per_direction: usize) -> usize {
-        let percentage = bandwidth_kbps / max_bandwidth_kbps;
-        let leds = (percentage * leds_per_direction as f64) as usize;
-        leds.min(leds_per_direction)
+        self.level_at(now) * gate
     }
+}
 
-    fn calculate_effective_speed(&self, rx_kbps: f64, tx_kbps: f64, state: &SharedRenderState) -> (f64, f64) {
-        if state.scale_animation_speed {
-            // Use the currently displayed (interpolated) bandwidth values, not the target values
-            // This ensures animation continues smoothly during the interpolation period
-            let tx_utilization = (tx_kbps / state.max_bandwidth_kbps).clamp(0.0, 1.0);
-            let rx_utilization = (rx_kbps / state.max_bandwidth_kbps).clamp(0.0, 1.0);
+// One bandwidth sample kept for the history display
+#[derive(Debug, Clone, Copy)]
+struct HistorySample {
+    rx_kbps: f64,
+    tx_kbps: f64,
+}
 
-            // Quantize to nice fractions to avoid aliasing/stuttering
-            // Use FPS for quantization to avoid stuttering at different frame rates
-            let tx_quantized = (tx_utilization * state.fps).round() / state.fps;
-            let rx_quantized = (rx_utilization * state.fps).round() / state.fps;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HistoryStyle {
+    // TX/RX color dimmed to the utilization of the sample
+    Brightness,
+    // Full brightness, hue from blue (idle) to red (max)
+    Hue,
+}
 
-            let tx_speed = state.animation_speed * tx_quantized;
-            let rx_speed = state.animation_speed * rx_quantized;
+// Settings for display_mode = "history"
+#[derive(Debug, Clone, Copy)]
+struct HistoryConfig {
+    enabled: bool,
+    style: HistoryStyle,
+    leds_per_sample: usize,
+}
 
-            (tx_speed, rx_speed)
-        } else {
-            (state.animation_speed, state.animation_speed)
+impl HistoryConfig {
+    fn from_config(config: &BandwidthConfig) -> Self {
+        HistoryConfig {
+            enabled: config.display_mode.eq_ignore_ascii_case("history"),
+            style: match config.history_style.to_lowercase().as_str() {
+                "hue" => HistoryStyle::Hue,
+                _ => HistoryStyle::Brightness,
+            },
+            leds_per_sample: config.history_leds_per_sample.max(1),
         }
     }
+}
 
-    fn calculate_led_positions(&self, tx_leds: usize, rx_leds: usize, direction: DirectionMode, swap: bool, total_leds: usize, leds_per_direction: usize) -> (Vec<usize>, Vec<usize>) {
-        let half = leds_per_direction;
+// Samples (newest first) and settings handed to every target for one frame
+struct HistoryFrame {
+    // Strips show history instead of the bar; matrices get samples for their own modes either way
+    enabled: bool,
+    samples: Vec<HistorySample>,
+    style: HistoryStyle,
+    leds_per_sample: usize,
+}
 
-        let (first_half_leds, second_half_leds) = if swap {
-            (tx_leds, rx_leds)
-        } else {
-            (rx_leds, tx_leds)
-        };
+// Peak-hold marker settings, like the peak indicator on an audio VU meter
+#[derive(Debug, Clone, Copy)]
+struct PeakHoldConfig {
+    enabled: bool,
+    tx_color: Rgb,
+    rx_color: Rgb,
+    hold_secs: f64,
+    // Fraction of the bar the marker falls per second once the hold time is over
+    fall_per_sec: f64,
+}
 
-        let (first_half_pos, second_half_pos) = match direction {
-            DirectionMode::Mirrored => {
-                let first: Vec<usize> = (0..first_half_leds).map(|i| half - 1 - i).collect();
-                let second: Vec<usize> = (0..second_half_leds).map(|i| half + i).collect();
-                (first, second)
-            }
-            DirectionMode::Opposing => {
-                let first: Vec<usize> = (0..first_half_leds).collect();
-                let second: Vec<usize> = (0..second_half_leds)
-                    .map(|i| total_leds - 1 - i)
-                    .collect();
-                (first, second)
-            }
-            DirectionMode::Left => {
-                let first: Vec<usize> = (0..first_half_leds).map(|i| half - 1 - i).collect();
-                let second: Vec<usize> = (0..second_half_leds)
-                    .map(|i| total_leds - 1 - i)
-                    .collect();
-                (first, second)
-            }
-            DirectionMode::Right => {
-                let first: Vec<usize> = (0..first_half_leds).collect();
-                let second: Vec<usize> = (0..second_half_leds).map(|i| half + i).collect();
-                (first, second)
+impl PeakHoldConfig {
+    fn from_config(config: &BandwidthConfig) -> Self {
+        let white = Rgb { r: 255, g: 255, b: 255 };
+        let default_color = Rgb::from_hex(&config.peak_color).unwrap_or(white);
+        let side_color = |color: &str| {
+            if color.is_empty() {
+                default_color
+            } else {
+                Rgb::from_hex(color).unwrap_or(default_color)
             }
         };
+        PeakHoldConfig {
+            enabled: config.peak_hold,
+            tx_color: side_color(&config.tx_peak_color),
+            rx_color: side_color(&config.rx_peak_color),
+            hold_secs: config.peak_hold_ms.max(0.0) / 1000.0,
+            fall_per_sec: config.peak_fall_percent_per_sec.max(0.0) / 100.0,
+        }
+    }
+}
 
-        if swap {
-            (first_half_pos, second_half_pos)
+// Highest recent utilization (as a bar fraction) for one direction
+#[derive(Debug, Clone, Copy, Default)]
+struct PeakHold {
+    fraction: f64,
+    held_secs: f64,
+}
+
+impl PeakHold {
+    fn update(&mut self, fraction: f64, delta_seconds: f64, config: &PeakHoldConfig) -> f64 {
+        if fraction >= self.fraction {
+            self.fraction = fraction;
+            self.held_secs = 0.0;
         } else {
-            (second_half_pos, first_half_pos)
+            self.held_secs += delta_seconds;
+            if self.held_secs > config.hold_secs {
+                self.fraction = (self.fraction - config.fall_per_sec * delta_seconds).max(fraction);
+            }
         }
+        self.fraction
     }
+}
 
-    fn render_frame(&mut self, delta_seconds: f64) -> Result<()> {
-        // Rebuild gradients if config changed (very quick check)
-        self.rebuild_gradients_if_needed()?;
-
-        // Lock shared state only long enough to read current values
-        let state = self.shared_state.lock().unwrap();
+// Peak positions and colors handed to every target for one frame
+#[derive(Debug, Clone, Copy)]
+struct PeakMarkers {
+    tx_fraction: f64,
+    rx_fraction: f64,
+    tx_color: Rgb,
+    rx_color: Rgb,
+}
 
-        // Interpolate bandwidth values for smooth transitions
-        let (rx_kbps, tx_kbps) = if let Some(last_update) = state.last_bandwidth_update {
-            let elapsed_ms = last_update.elapsed().as_secs_f64() * 1000.0;
-            let interpolation_time = state.interpolation_time_ms;
-            let t = (elapsed_ms / interpolation_time).min(1.0); // Interpolation factor (0.0 to 1.0)
+// Sparkle color for interface errors and drops, None when error_sparkle is off
+fn error_sparkle_color(config: &BandwidthConfig) -> Option<Rgb> {
+    config
+        .error_sparkle
+        .then(|| Rgb::from_hex(&config.error_color).unwrap_or(Rgb { r: 255, g: 0, b: 0 }))
+}
 
-            // Smoothly transition from start to current over interpolation_time_ms
-            let interpolated_rx = state.start_rx_kbps + (state.current_rx_kbps - state.start_rx_kbps) * t;
-            let interpolated_tx = state.start_tx_kbps + (state.current_tx_kbps - state.start_tx_kbps) * t;
+// How long without a sample before the readings count as stale, None when disabled or in test mode
+fn stale_timeout(config: &BandwidthConfig) -> Option<Duration> {
+    (config.stale_timeout_ms > 0.0 && !config.test_rx && !config.test_tx)
+        .then(|| Duration::from_secs_f64(config.stale_timeout_ms.max(config.sample_interval_ms * 3.0) / 1000.0))
+}
 
-            (interpolated_rx, interpolated_tx)
-        } else {
-            // No update yet, use current values
-            (state.current_rx_kbps, state.current_tx_kbps)
-        };
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NoDataPattern {
+    // The whole strip slowly breathes
+    Breathe,
+    // Everything dark except the end pixel of each bar, blinking once a second
+    Blink,
+}
 
-        let max_bandwidth_kbps = state.max_bandwidth_kbps;
-        let direction = state.direction;
-        let swap = state.swap;
-        let use_gradient = state.use_gradient;
-        let (tx_effective_speed, rx_effective_speed) = self.calculate_effective_speed(rx_kbps, tx_kbps, &state);
-        let fps = state.fps;
-        let tx_animation_direction = state.tx_animation_direction.clone();
-        let rx_animation_direction = state.rx_animation_direction.clone();
-        let total_leds = state.total_leds;
-        let rx_split_percent = state.rx_split_percent.clamp(0.0, 100.0);
-        let strobe_on_max = state.strobe_on_max;
-        let strobe_rate_hz = state.strobe_rate_hz;
-        let strobe_duration_ms = state.strobe_duration_ms;
-        let strobe_color_str = state.strobe_color.clone();
-        drop(state); // Release lock immediately
+// What the strip shows instead of a frozen bar while readings are stale or the source is lost
+#[derive(Debug, Clone, Copy)]
+struct NoDataConfig {
+    pattern: NoDataPattern,
+    color: Rgb,
+}
 
-        // Parse strobe color
-        let strobe_color = Rgb::from_hex(&strobe_color_str).unwrap_or(Rgb { r: 0, g: 0, b: 0 });
+impl NoDataConfig {
+    fn from_config(config: &BandwidthConfig) -> Self {
+        NoDataConfig {
+            pattern: match config.no_data_pattern.to_lowercase().as_str() {
+                "blink" => NoDataPattern::Blink,
+                _ => NoDataPattern::Breathe,
+            },
+            color: Rgb::from_hex(&config.no_data_color).unwrap_or(Rgb { r: 255, g: 128, b: 0 }),
+        }
+    }
+}
 
-        // Calculate LED split based on rx_split_percent
-        let rx_leds_available = ((total_leds as f64 * rx_split_percent) / 100.0) as usize;
-        let tx_leds_available = total_leds - rx_leds_available;
-        let leds_per_direction = total_leds / 2; // Keep for backward compatibility with position calculations
+// A fading flash for one interface error or drop, at 0.0-1.0 along the RX or TX half
+#[derive(Debug, Clone, Copy)]
+struct Sparkle {
+    is_tx: bool,
+    position: f64,
+    age_secs: f64,
+}
 
-        // Calculate LED counts using the configurable split
-        let rx_leds = self.calculate_leds(rx_kbps, max_bandwidth_kbps, rx_leds_available);
-        let tx_leds = self.calculate_leds(tx_kbps, max_bandwidth_kbps, tx_leds_available);
+impl Sparkle {
+    const LIFETIME_SECS: f64 = 0.5;
+    // An error storm keeps at most this many sparkles alive so the bar stays readable
+    const MAX_ACTIVE: usize = 32;
 
-        // Determine if we're in strobe mode for each segment
-        let mut rx_strobe_active = false;
-        let mut tx_strobe_active = false;
+    fn brightness(&self) -> f64 {
+        (1.0 - self.age_secs / Self::LIFETIME_SECS).max(0.0)
+    }
+}
 
-        if strobe_on_max && strobe_rate_hz > 0.0 {
-            let now = SystemTime::now();
-            let elapsed_millis = now.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
+// Decaying peak of recent traffic, used as the max when auto_range is enabled
+#[derive(Debug, Clone, Default)]
+struct AutoRange {
+    peak_kbps: f64,
+    last_update: Option<Instant>,
+}
 
-            // Calculate full cycle time in milliseconds
-            let cycle_ms = (1000.0 / strobe_rate_hz) as u128;
-            // Clamp strobe duration to not exceed the full cycle
-            let clamped_duration = (strobe_duration_ms as u128).min(cycle_ms);
+impl AutoRange {
+    fn update(&mut self, bandwidth_kbps: f64, now: Instant, window_secs: f64) -> f64 {
+        let decayed = match self.last_update {
+            Some(last) => {
+                let elapsed = now.saturating_duration_since(last).as_secs_f64();
+                self.peak_kbps * (-elapsed / window_secs.max(1.0)).exp()
+            }
+            None => 0.0,
+        };
+        self.peak_kbps = decayed.max(bandwidth_kbps);
+        self.last_update = Some(now);
+        self.peak_kbps
+    }
 
-            // Determine position within the current cycle
-            let position_in_cycle = elapsed_millis % cycle_ms;
-            // Strobe is active during the last 'duration' milliseconds of each cycle
-            let strobe_phase_active = position_in_cycle >= (cycle_ms - clamped_duration);
+    // The bar's max: the decaying peak, no lower than auto_range_min_mbps and no higher than the fill max
+    fn range_kbps(&mut self, bandwidth_kbps: f64, now: Instant, config: &BandwidthConfig) -> f64 {
+        self.update(bandwidth_kbps, now, config.auto_range_window_secs)
+            .max(config.auto_range_min_mbps * 1000.0)
+            .min(config.fill_max())
+    }
+}
 
-            // Activate strobe if at max and in strobe phase
-            if rx_leds >= rx_leds_available && strobe_phase_active {
-                rx_strobe_active = true;
-            }
+// How fresh the readings are: samples arriving, none for stale_timeout_ms, or the source ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DataState {
+    Live,
+    Stale,
+    Lost,
+}
 
-            if tx_leds >= tx_leds_available && strobe_phase_active {
-                tx_strobe_active = true;
-            }
+impl DataState {
+    // Lost while the source is down, stale once no sample arrived within the timeout, otherwise live
+    fn from_readings(source_lost: bool, last_sample_at: Instant, stale_timeout: Option<Duration>, now: Instant) -> Self {
+        if source_lost {
+            DataState::Lost
+        } else if stale_timeout.is_some_and(|timeout| now.saturating_duration_since(last_sample_at) > timeout) {
+            DataState::Stale
+        } else {
+            DataState::Live
         }
+    }
 
-        // Update animation offsets independently for TX and RX
-        if tx_effective_speed > 0.0 {
-            let leds_per_second = tx_effective_speed * fps;
-            let offset_delta = (leds_per_second * delta_seconds) / leds_per_direction as f64;
-            self.tx_animation_offset = (self.tx_animation_offset + offset_delta) % 1.0;
+    fn as_str(self) -> &'static str {
+        match self {
+            DataState::Live => "live",
+            DataState::Stale => "stale",
+            DataState::Lost => "lost",
         }
+    }
+}
 
-        if rx_effective_speed > 0.0 {
-            let leds_per_second = rx_effective_speed * fps;
-            let offset_delta = (leds_per_second * delta_seconds) / leds_per_direction as f64;
-            self.rx_animation_offset = (self.rx_animation_offset + offset_delta) % 1.0;
-        }
+// Runtime values reported by the HTTP API alongside the saved config
+#[derive(Debug, Clone, Serialize)]
+struct MeterStatus {
+    effective_max_gbps: f64,
+    max_gbps_source: &'static str,
+    link_speed_gbps: Option<f64>,
+    // Latest packet rates, and errors and drops counted since startup
+    rx_pps: f64,
+    tx_pps: f64,
+    errors: u64,
+    drops: u64,
+    // Latest reading of each monitored interface
+    interfaces: Vec<InterfaceReading>,
+    // "live", "stale" or "lost", and the age of the latest sample (worked out per request)
+    data_state: &'static str,
+    #[serde(skip)]
+    last_sample_at: Option<Instant>,
+    last_sample_age_secs: Option<f64>,
+}
 
-        // Prepare frame
-        let frame_size = total_leds * 3;
-        let mut frame = vec![0u8; frame_size];
+// Shared state between main thread and render thread
+#[derive(Clone)]
+struct SharedRenderState {
+    current_rx_kbps: f64,
+    current_tx_kbps: f64,
+    start_rx_kbps: f64,
+    start_tx_kbps: f64,
+    last_bandwidth_update: Option<Instant>,
+    animation_speed: f64,
+    scale_animation_speed: bool,
+    tx_animation_direction: String,
+    rx_animation_direction: String,
+    interpolation_time_ms: f64,
+    max_bandwidth_kbps: f64,
+    scale: BandwidthScale,
 
-        let (tx_positions, rx_positions) = self.calculate_led_positions(tx_leds, rx_leds, direction, swap, total_leds, leds_per_direction);
+    // Color configuration (as strings, renderer will rebuild gradients when changed)
+    tx_color: String,
+    rx_color: String,
+    use_gradient: bool,
+    interpolation_mode: InterpolationMode,
+    color_zones: bool,
+    zone_colors: String,
+    interface_bands: InterfaceBandConfig,
+    // Latest RX and TX of each interface (kbps or pps, like current_rx_kbps), for color_mode = "interfaces"
+    interface_levels: Vec<(f64, f64)>,
+    correction: OutputCorrection,
+    schedule: Schedule,
 
-        // Render TX positions
-        if tx_strobe_active {
-            // Strobe mode: fill all TX LEDs with strobe color
-            for &led_pos in tx_positions.iter() {
-                let offset = led_pos * 3;
-                frame[offset] = strobe_color.r;
-                frame[offset + 1] = strobe_color.g;
-                frame[offset + 2] = strobe_color.b;
-            }
-        } else if !use_gradient && self.tx_colors.len() >= 2 && !tx_positions.is_empty() {
-            let num_leds = tx_positions.len() as f64;
-            let pattern_offset = if tx_animation_direction == "right" {
-                -self.tx_animation_offset * num_leds
-            } else {
-                self.tx_animation_offset * num_leds
-            };
-            let segment_size = num_leds / self.tx_colors.len() as f64;
-
-            for (i, &led_pos) in tx_positions.iter().enumerate() {
-                let pattern_pos = ((i as f64 + pattern_offset) % num_leds + num_leds) % num_leds;
-                let segment_idx = (pattern_pos / segment_size).floor() as usize % self.tx_colors.len();
-                let color = &self.tx_colors[segment_idx];
-
-                let offset = led_pos * 3;
-                frame[offset] = color.r;
-                frame[offset + 1] = color.g;
-                frame[offset + 2] = color.b;
-            }
-        } else if let Some(ref tx_gradient) = self.tx_gradient {
-            for &led_pos in tx_positions.iter() {
-                // Map LED position to gradient position (0.0-1.0 across the full TX half)
-                let pos_ratio = (led_pos % leds_per_direction) as f64 / leds_per_direction as f64;
-                let animated_pos = if tx_animation_direction == "right" {
-                    (1.0 + pos_ratio - self.tx_animation_offset) % 1.0
-                } else {
-                    (pos_ratio + self.tx_animation_offset) % 1.0
-                };
+    // Rendering configuration
+    targets: Vec<TargetLayout>,
+    fps: f64,
+    strobe_on_max: bool,
+    strobe_rate_hz: f64,
+    strobe_duration_ms: f64,
+    strobe_color: String,
+    peak_hold: PeakHoldConfig,
 
-                let rgba = tx_gradient.at(animated_pos).to_rgba8();
-                let offset = led_pos * 3;
-                frame[offset] = rgba[0];
-                frame[offset + 1] = rgba[1];
-                frame[offset + 2] = rgba[2];
-            }
-        } else {
-            for &led_pos in &tx_positions {
-                let offset = led_pos * 3;
-                frame[offset] = self.tx_solid_color.r;
-                frame[offset + 1] = self.tx_solid_color.g;
-                frame[offset + 2] = self.tx_solid_color.b;
-            }
-        }
+    // Recent samples for display_mode = "history", newest first
+    history: HistoryConfig,
+    history_samples: VecDeque<HistorySample>,
 
-        // Render RX positions
-        if rx_strobe_active {
-            // Strobe mode: fill all RX LEDs with strobe color
-            for &led_pos in rx_positions.iter() {
-                let offset = led_pos * 3;
-                frame[offset] = strobe_color.r;
-                frame[offset + 1] = strobe_color.g;
-                frame[offset + 2] = strobe_color.b;
-            }
-        } else if !use_gradient && self.rx_colors.len() >= 2 && !rx_positions.is_empty() {
-            let num_leds = rx_positions.len() as f64;
-            let pattern_offset = if rx_animation_direction == "right" {
-                -self.rx_animation_offset * num_leds
-            } else {
-                self.rx_animation_offset * num_leds
-            };
-            let segment_size = num_leds / self.rx_colors.len() as f64;
+    // Errors and drops not yet shown, and the sparkle color (None when error_sparkle is off)
+    rx_error_events: u64,
+    tx_error_events: u64,
+    error_sparkle: Option<Rgb>,
 
-            for (i, &led_pos) in rx_positions.iter().enumerate() {
-                let pattern_pos = ((i as f64 + pattern_offset) % num_leds + num_leds) % num_leds;
-                let segment_idx = (pattern_pos / segment_size).floor() as usize % self.rx_colors.len();
-                let color = &self.rx_colors[segment_idx];
+    // Anything but live shows the no data pattern instead of freezing on the last sample
+    data_state: DataState,
+    no_data: NoDataConfig,
 
-                let offset = led_pos * 3;
-                frame[offset] = color.r;
-                frame[offset + 1] = color.g;
-                frame[offset + 2] = color.b;
-            }
-        } else if let Some(ref rx_gradient) = self.rx_gradient {
-            for &led_pos in rx_positions.iter() {
-                // Map LED position to gradient position (0.0-1.0 across the full RX half)
-                let pos_ratio = (led_pos % leds_per_direction) as f64 / leds_per_direction as f64;
-                let animated_pos = if rx_animation_direction == "right" {
-                    (1.0 + pos_ratio - self.rx_animation_offset) % 1.0
-                } else {
-                    (pos_ratio + self.rx_animation_offset) % 1.0
-                };
+    // Generation counter to detect changes
+    generation: u64,
+}
 
-                let rgba = rx_gradient.at(animated_pos).to_rgba8();
-                let offset = led_pos * 3;
-                frame[offset] = rgba[0];
-                frame[offset + 1] = rgba[1];
-                frame[offset + 2] = rgba[2];
-            }
+impl SharedRenderState {
+    // Renderer state at startup, before the first sample
+    fn from_config(config: &BandwidthConfig) -> Self {
+        let tx_color = if config.tx_color.is_empty() {
+            config.color.clone()
         } else {
-            for &led_pos in &rx_positions {
-                let offset = led_pos * 3;
-                frame[offset] = self.rx_solid_color.r;
-                frame[offset + 1] = self.rx_solid_color.g;
-                frame[offset + 2] = self.rx_solid_color.b;
-            }
-        }
+            config.tx_color.clone()
+        };
+        let rx_color = if config.rx_color.is_empty() {
+            config.color.clone()
+        } else {
+            config.rx_color.clone()
+        };
 
-        // Write to DDP connection
-        self.ddp_conn.write_offset(&frame, 0)?;
+        let interpolation_mode = match config.interpolation.to_lowercase().as_str() {
+            "basis" => InterpolationMode::Basis,
+            "catmullrom" | "catmull-rom" => InterpolationMode::CatmullRom,
+            _ => InterpolationMode::Linear,
+        };
 
-        Ok(())
+        SharedRenderState {
+            current_rx_kbps: 0.0,
+            current_tx_kbps: 0.0,
+            start_rx_kbps: 0.0,
+            start_tx_kbps: 0.0,
+            last_bandwidth_update: None,
+            animation_speed: config.animation_speed,
+            scale_animation_speed: config.scale_animation_speed,
+            tx_animation_direction: config.tx_animation_direction.clone(),
+            rx_animation_direction: config.rx_animation_direction.clone(),
+            interpolation_time_ms: config.interpolation_time_ms,
+            max_bandwidth_kbps: config.fill_max(),
+            scale: BandwidthScale::from_config(config),
+            tx_color,
+            rx_color,
+            use_gradient: config.use_gradient,
+            interpolation_mode,
+            color_zones: config.color_mode.eq_ignore_ascii_case("zones"),
+            interface_bands: InterfaceBandConfig::from_config(config),
+            interface_levels: Vec::new(),
+            zone_colors: config.zone_colors.clone(),
+            correction: OutputCorrection::from_config(config),
+            schedule: Schedule::from_config(config),
+            targets: config.target_layouts(),
+            fps: config.fps,
+            strobe_on_max: config.strobe_on_max,
+            strobe_rate_hz: config.strobe_rate_hz,
+            strobe_duration_ms: config.strobe_duration_ms,
+            strobe_color: config.strobe_color.clone(),
+            peak_hold: PeakHoldConfig::from_config(config),
+            history: HistoryConfig::from_config(config),
+            history_samples: VecDeque::new(),
+            rx_error_events: 0,
+            tx_error_events: 0,
+            error_sparkle: error_sparkle_color(config),
+            data_state: DataState::Live,
+            no_data: NoDataConfig::from_config(config),
+            generation: 0,
+        }
     }
 
-    // Main render loop that runs at configurable FPS
-    fn run(mut self) {
-        let mut last_frame = Instant::now();
-
-        loop {
-            // Check for shutdown signal
-            if self.shutdown.load(Ordering::Relaxed) {
-                break;
-            }
-
-            // Read FPS from shared state
-            let fps = {
-                let state = self.shared_state.lock().unwrap();
-                state.fps
-            };
-
-            // Calculate frame duration based on FPS
-            let frame_duration_micros = (1_000_000.0 / fps) as u64;
-            let frame_duration = Duration::from_micros(frame_duration_micros);
+    // Newest sample first, keeping enough history to fill the longest strip
+    fn record_history(&mut self, sample: HistorySample) {
+        let history_len = self.targets.iter().map(|t| t.total_leds).max().unwrap_or(0);
+        self.history_samples.push_front(sample);
+        self.history_samples.truncate(history_len);
+    }
+}
 
-            let now = Instant::now();
-            let elapsed = now.duration_since(last_frame);
+#[derive(Clone, Copy, Debug)]
+struct Rgb {
+    r: u8,
+    g: u8,
+    b: u8,
+}
 
-            if elapsed >= frame_duration {
-                let delta_seconds = elapsed.as_secs_f64();
-                last_frame = now;
+impl Rgb {
+    fn from_hex(hex: &str) -> Result<Self> {
+        let hex = hex.trim_start_matches('#');
+        if hex.len() != 6 {
+            anyhow::bail!("Invalid hex color: {}", hex);
+        }
+        Ok(Rgb {
+            r: u8::from_str_radix(&hex[0..2], 16)?,
+            g: u8::from_str_radix(&hex[2..4], 16)?,
+            b: u8::from_str_radix(&hex[4..6], 16)?,
+        })
+    }
 
-                // Render frame - this is the only thing happening in this thread
-                let _ = self.render_frame(delta_seconds);
-            }
+    // Fully saturated color at full brightness for a hue in degrees
+    fn from_hue(hue: f64) -> Self {
+        let h = hue.rem_euclid(360.0) / 60.0;
+        let x = ((1.0 - (h % 2.0 - 1.0).abs()) * 255.0).round() as u8;
+        let (r, g, b) = match h as u32 {
+            0 => (255, x, 0),
+            1 => (x, 255, 0),
+            2 => (0, 255, x),
+            3 => (0, x, 255),
+            4 => (x, 0, 255),
+            _ => (255, 0, x),
+        };
+        Rgb { r, g, b }
+    }
 
-            // Tiny sleep to avoid spinning CPU at 100%
-            thread::sleep(Duration::from_micros(100));
+    // The same color dimmed to a brightness of 0.0-1.0
+    fn scaled(self, brightness: f64) -> Self {
+        Rgb {
+            r: (self.r as f64 * brightness).round() as u8,
+            g: (self.g as f64 * brightness).round() as u8,
+            b: (self.b as f64 * brightness).round() as u8,
         }
     }
 }
 
-// Detect OS type (Darwin/Linux) via uname
-async fn detect_os(host: Option<&String>) -> Result<String> {
-    let output = if let Some(host) = host {
-        Command::new("ssh")
-            .arg(host)
-            .arg("uname")
-            .stdin(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .output()
-            .await?
-    } else {
-        Command::new("uname")
-            .output()
-            .await?
-    };
-
-    let os_name = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    Ok(os_name)
+// Parse a comma-separated color list. Each color may carry a "@percent" stop, used by color zones
+fn parse_color_list(color_str: &str) -> Result<Vec<(Rgb, Option<f64>)>> {
+    color_str
+        .split(',')
+        .map(|entry| {
+            let (hex, stop) = match entry.split_once('@') {
+                Some((hex, stop)) => {
+                    let stop: f64 = stop.trim().trim_end_matches('%').parse()?;
+                    (hex, Some(stop.clamp(0.0, 100.0)))
+                }
+                None => (entry, None),
+            };
+            Ok((Rgb::from_hex(hex.trim())?, stop))
+        })
+        .collect()
 }
 
-// Spawn bandwidth monitoring command based on OS
-async fn spawn_bandwidth_monitor(args: &Args, config: &BandwidthConfig) -> Result<tokio::process::Child> {
-    if args.host.is_some() {
-        // For remote hosts, use a single SSH connection that auto-detects OS and runs appropriate command
-        spawn_remote_monitor(args.host.as_ref().unwrap(), &config.interface).await
-    } else {
-        // Local monitoring - detect OS
-        let os = detect_os(None).await?;
-
-        let child = if os == "Darwin" {
-            // macOS: use netstat
-            spawn_netstat_monitor(None, &config.interface).await?
-        } else {
-            // Linux: use /proc/net/dev
-            spawn_procnet_monitor(None, &config.interface).await?
-        };
+// Color for fill levels from `start` (percent of the bar) up to the next zone
+#[derive(Debug, Clone, Copy)]
+struct ColorZone {
+    start: f64,
+    color: Rgb,
+}
 
-        Ok(child)
+// Build color zones from a color list like "00FF00,FFBF00@60,FF0000@85".
+// Colors without a stop split the bar evenly, the first zone always starts at 0%.
+fn build_color_zones(color_str: &str) -> Result<Vec<ColorZone>> {
+    let colors = parse_color_list(color_str)?;
+    let n = colors.len();
+    let mut zones: Vec<ColorZone> = colors
+        .into_iter()
+        .enumerate()
+        .map(|(i, (color, stop))| ColorZone {
+            start: stop.unwrap_or(i as f64 * 100.0 / n as f64),
+            color,
+        })
+        .collect();
+    zones.sort_by(|a, b| a.start.total_cmp(&b.start));
+    if let Some(first) = zones.first_mut() {
+        first.start = 0.0;
     }
+    Ok(zones)
 }
 
-// Remote monitoring with OS auto-detection in a single SSH session
-async fn spawn_remote_monitor(host: &String, interface: &str) -> Result<tokio::process::Child> {
-    // Parse comma-separated interfaces for egrep pattern (Linux)
-    let interfaces: Vec<&str> = interface.split(',').map(|s| s.trim()).collect();
-    let egrep_pattern = interfaces.join("|");
+fn zone_color(zones: &[ColorZone], fraction: f64) -> Option<Rgb> {
+    let percent = fraction * 100.0;
+    zones.iter().rev().find(|zone| zone.start <= percent).or(zones.first()).map(|zone| zone.color)
+}
 
-    // Create a script that detects OS and runs appropriate monitoring command
-    // This all runs in ONE SSH session, so only ONE password prompt
-    let script = format!(
-        r#"
-OS=$(uname)
-if [ "$OS" = "Darwin" ]; then
-    # macOS
-    netstat -w 1 -I {}
-else
-    # Linux
-    while true; do cat /proc/net/dev | egrep '({})'; sleep 1; done
-fi
-"#,
-        interface, egrep_pattern
-    );
+// color_mode = "interfaces": one color band per monitored interface
+#[derive(Debug, Clone)]
+struct InterfaceBandConfig {
+    enabled: bool,
+    colors: Vec<Rgb>,
+}
 
-    let child = Command::new("ssh")
-        .arg(host)
-        .arg(&script)
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
-        .spawn()?;
+impl InterfaceBandConfig {
+    fn from_config(config: &BandwidthConfig) -> Self {
+        let colors = parse_color_list(&config.interface_colors)
+            .map(|colors| colors.into_iter().map(|(rgb, _)| rgb).collect::<Vec<_>>())
+            .ok()
+            .filter(|colors| !colors.is_empty())
+            .unwrap_or_else(|| vec![Rgb { r: 0, g: 192, b: 255 }, Rgb { r: 255, g: 128, b: 0 }]);
+        InterfaceBandConfig {
+            enabled: config.color_mode.eq_ignore_ascii_case("interfaces"),
+            colors,
+        }
+    }
+}
 
-    Ok(child)
+// Each interface's share of the RX and TX bars for one frame, in interface order
+#[derive(Debug, Clone)]
+struct InterfaceBands {
+    colors: Vec<Rgb>,
+    rx_shares: Vec<f64>,
+    tx_shares: Vec<f64>,
 }
 
-// macOS: netstat -w 1 -I <interfaces>
-async fn spawn_netstat_monitor(host: Option<&String>, interface: &str) -> Result<tokio::process::Child> {
-    let netstat_cmd = format!("netstat -w 1 -I {}", interface);
+// Helper function to build gradient from color string
+fn build_gradient_from_color(
+    color_str: &str,
+    use_gradient: bool,
+    interpolation_mode: InterpolationMode,
+) -> Result<(Option<colorgrad::Gradient>, Vec<Rgb>, Rgb)> {
+    // Parse all colors into RGB, stops only matter for color zones
+    let rgb_colors: Vec<Rgb> = parse_color_list(color_str)?.into_iter().map(|(rgb, _)| rgb).collect();
 
-    let child = if let Some(host) = host {
-        // SSH without pseudo-terminal - allows password prompt via stdin/stderr
-        Command::new("ssh")
-            .arg(host)
-            .arg(&netstat_cmd)
-            .stdin(Stdio::inherit())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
-            .spawn()?
-    } else {
-        Command::new("sh")
-            .arg("-c")
-            .arg(&netstat_cmd)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()?
-    };
+    // Build gradient only if we have multiple colors and use_gradient is enabled
+    let gradient = if rgb_colors.len() >= 2 && use_gradient {
+        let mut colorgrad_colors = Vec::new();
+        let mut domain = Vec::new();
 
-    Ok(child)
-}
+        // Create gradient with color plateaus and smooth transitions
+        // Each color occupies ~80% of its segment as pure color, ~20% for smooth fade
+        let n = rgb_colors.len();
+        let segment_size = 1.0 / n as f64;
+        let transition_size = segment_size * 0.1; // 10% on each side for transition
 
-// Linux: poll /proc/net/dev and stream raw data
-async fn spawn_procnet_monitor(host: Option<&String>, interface: &str) -> Result<tokio::process::Child> {
-    // Parse comma-separated interfaces for egrep pattern
-    let interfaces: Vec<&str> = interface.split(',').map(|s| s.trim()).collect();
-    let egrep_pattern = interfaces.join("|");
+        for (i, rgb) in rgb_colors.iter().enumerate() {
+            let color = Color::from_rgba8(rgb.r, rgb.g, rgb.b, 255);
+            let segment_start = i as f64 * segment_size;
+            let segment_end = (i as f64 + 1.0) * segment_size;
 
-    // Simple script: just output raw /proc/net/dev lines every second
-    // All calculation will be done in Rust
-    let script = format!(
-        "while true; do cat /proc/net/dev | egrep '({})'; sleep 1; done",
-        egrep_pattern
-    );
+            // Start of plateau (after transition from previous color)
+            let plateau_start = segment_start + transition_size;
+            // End of plateau (before transition to next color)
+            let plateau_end = segment_end - transition_size;
 
-    let child = if let Some(host) = host {
-        // SSH without pseudo-terminal - allows password prompt via stdin/stderr
-        Command::new("ssh")
-            .arg(host)
-            .arg(&script)
-            .stdin(Stdio::inherit())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
-            .spawn()?
-    } else {
-        Command::new("sh")
-            .arg("-c")
-            .arg(&script)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()?
+            if i == 0 {
+                // First color: add at position 0.0
+                colorgrad_colors.push(color.clone());
+                domain.push(0.0);
+            } else {
+                // Transition from previous color
+                colorgrad_colors.push(color.clone());
+                domain.push(plateau_start);
+            }
+
+            // End of this color's plateau
+            if i < n - 1 {
+                colorgrad_colors.push(color);
+                domain.push(plateau_end);
+            }
+        }
+
+        // Add first color at end to make it cyclic
+        if let Some(first_rgb) = rgb_colors.first() {
+            let first_color = Color::from_rgba8(first_rgb.r, first_rgb.g, first_rgb.b, 255);
+            colorgrad_colors.push(first_color.clone());
+            domain.push(1.0 - transition_size);
+            colorgrad_colors.push(first_color);
+            domain.push(1.0);
+        }
+
+        let cg_interpolation = match interpolation_mode {
+            InterpolationMode::Basis => colorgrad::Interpolation::Basis,
+            InterpolationMode::CatmullRom => colorgrad::Interpolation::CatmullRom,
+            _ => colorgrad::Interpolation::Linear,
+        };
+
+        let gradient = colorgrad::CustomGradient::new()
+            .colors(&colorgrad_colors)
+            .domain(&domain)
+            .interpolation(cg_interpolation)
+            .build()?;
+
+        Some(gradient)
+    } else {
+        None
     };
 
-    Ok(child)
+    let solid_color = if !rgb_colors.is_empty() {
+        rgb_colors[0]
+    } else {
+        // Default fallback
+        Rgb::from_hex("0099FF")?
+    };
+
+    Ok((gradient, rgb_colors, solid_color))
 }
 
-fn get_timestamp() -> String {
-    let now = SystemTime::now();
-    let duration = now.duration_since(SystemTime::UNIX_EPOCH).unwrap();
-    let secs = duration.as_secs();
-    let millis = duration.subsec_millis();
+// Renderer-side handle for one output: its animation state and the channel to its sender thread
+struct RenderTarget {
+    tx_animation_offset: f64,
+    rx_animation_offset: f64,
+    frames: mpsc::SyncSender<Vec<u8>>,
+    // Channels the target's protocol actually sends, for the power limit
+    encoding: PixelEncoding,
+}
 
-    // Format as HH:MM:SS.mmm
-    let hours = (secs / 3600) % 24;
-    let minutes = (secs / 60) % 60;
-    let seconds = secs % 60;
+impl RenderTarget {
+    fn new(frames: mpsc::SyncSender<Vec<u8>>, encoding: PixelEncoding) -> Self {
+        RenderTarget {
+            tx_animation_offset: 0.0,
+            rx_animation_offset: 0.0,
+            frames,
+            encoding,
+        }
+    }
+}
 
-    format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, millis)
+// Per-frame values shared by every target
+struct FrameInputs {
+    rx_kbps: f64,
+    tx_kbps: f64,
+    max_bandwidth_kbps: f64,
+    scale: BandwidthScale,
+    use_gradient: bool,
+    color_zones: bool,
+    tx_effective_speed: f64,
+    rx_effective_speed: f64,
+    fps: f64,
+    tx_animation_direction: String,
+    rx_animation_direction: String,
+    strobe_phase_active: bool,
+    strobe_color: Rgb,
+    peak_markers: Option<PeakMarkers>,
+    history: Option<HistoryFrame>,
+    interface_bands: Option<InterfaceBands>,
+    sparkles: Vec<Sparkle>,
+    sparkle_color: Rgb,
+    // The no data pattern with this frame's color, while readings are stale or the source is lost
+    no_data: Option<NoDataConfig>,
 }
 
-// State for tracking bandwidth calculation per interface
-struct InterfaceState {
-    prev_rx_bytes: u64,
-    prev_tx_bytes: u64,
-    prev_time: Instant,
+// Owns the pixel sink for one target, so an unreachable or slow controller only loses its own frames.
+// Connection errors are reported to the TUI log once per failure, then retried in the background.
+fn spawn_target_sender(output: TargetOutput, socket: UdpSocket, log_tx: mpsc::Sender<String>) -> mpsc::SyncSender<Vec<u8>> {
+    let sink_output = output.clone();
+    spawn_sink_sender(output, move || connect_pixel_sink(&sink_output, socket.try_clone()?), log_tx)
 }
 
-struct BandwidthTracker {
-    interfaces: std::collections::HashMap<String, InterfaceState>,
+// The sender thread behind one target, with whatever connects its sink
+fn spawn_sink_sender<C>(output: TargetOutput, mut connect: C, log_tx: mpsc::Sender<String>) -> mpsc::SyncSender<Vec<u8>>
+where
+    C: FnMut() -> Result<Box<dyn PixelSink>> + Send + 'static,
+{
+    let (frame_tx, frame_rx) = mpsc::sync_channel::<Vec<u8>>(1);
+
+    thread::spawn(move || {
+        let address = output.describe();
+        let mut failing = false;
+
+        let mut sink = loop {
+            match connect() {
+                Ok(sink) => break sink,
+                Err(e) => {
+                    if !failing {
+                        let _ = log_tx.send(format!("WLED {} unreachable: {} (retrying)", address, e));
+                        failing = true;
+                    }
+                    // Keep draining frames while waiting so the renderer never blocks on us
+                    let retry_at = Instant::now() + Duration::from_secs(5);
+                    while let Some(wait) = retry_at.checked_duration_since(Instant::now()) {
+                        if let Err(mpsc::RecvTimeoutError::Disconnected) = frame_rx.recv_timeout(wait) {
+                            return;
+                        }
+                    }
+                }
+            }
+        };
+
+        // Ends when the renderer shuts down and drops its sender
+        for frame in frame_rx {
+            let frame = output.encode_frame(frame);
+            match sink.send_frame(&frame) {
+                Ok(_) => {
+                    if failing {
+                        let _ = log_tx.send(format!("WLED {} reachable again", address));
+                        failing = false;
+                    }
+                }
+                Err(e) => {
+                    if !failing {
+                        let _ = log_tx.send(format!("WLED {} send failed: {}", address, e));
+                        failing = true;
+                    }
+                }
+            }
+        }
+    });
+
+    frame_tx
 }
 
-impl BandwidthTracker {
-    fn new() -> Self {
-        BandwidthTracker {
-            interfaces: std::collections::HashMap::new(),
+// Pixel outputs: every target sends its frames through a PixelSink for its configured protocol
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputProtocol {
+    Ddp,
+    E131,
+    ArtNet,
+    WledUdp(WledUdpMode),
+    Json,
+    Opc,
+}
+
+impl OutputProtocol {
+    fn parse(protocol: &str) -> Self {
+        match protocol.to_lowercase().as_str() {
+            "e131" | "e1.31" | "sacn" => OutputProtocol::E131,
+            "artnet" | "art-net" => OutputProtocol::ArtNet,
+            "wled" | "wled-udp" | "udp" => OutputProtocol::WledUdp(WledUdpMode::Auto),
+            "warls" => OutputProtocol::WledUdp(WledUdpMode::Warls),
+            "drgb" => OutputProtocol::WledUdp(WledUdpMode::Drgb),
+            "dnrgb" => OutputProtocol::WledUdp(WledUdpMode::Dnrgb),
+            "json" => OutputProtocol::Json,
+            "opc" => OutputProtocol::Opc,
+            _ => OutputProtocol::Ddp,
         }
     }
 
-    // Parse /proc/net/dev line and accumulate bandwidth
-    // Returns Some when all interfaces have been processed (after collecting all lines)
-    fn update_from_procnet_line(&mut self, line: &str) -> Option<(f64, f64)> {
-        // Format: "  eth9: 12345 ... (16 fields total)"
-        let parts: Vec<&str> = line.split(':').collect();
-        if parts.len() != 2 {
-            return None;
+    fn name(&self) -> &'static str {
+        match self {
+            OutputProtocol::Ddp => "ddp",
+            OutputProtocol::E131 => "e131",
+            OutputProtocol::ArtNet => "artnet",
+            OutputProtocol::WledUdp(WledUdpMode::Auto) => "wled",
+            OutputProtocol::WledUdp(WledUdpMode::Warls) => "warls",
+            OutputProtocol::WledUdp(WledUdpMode::Drgb) => "drgb",
+            OutputProtocol::WledUdp(WledUdpMode::Dnrgb) => "dnrgb",
+            OutputProtocol::Json => "json",
+            OutputProtocol::Opc => "opc",
         }
+    }
 
-        let iface = parts[0].trim();
-        let fields: Vec<&str> = parts[1].trim().split_whitespace().collect();
+    // WLED's own UDP and JSON inputs are RGB only, WLED applies color order and white itself.
+    // OPC servers (Fadecandy, gl_server) always read 8-bit RGB triplets
+    fn takes_encoded_pixels(&self) -> bool {
+        !matches!(self, OutputProtocol::WledUdp(_) | OutputProtocol::Json | OutputProtocol::Opc)
+    }
+}
 
-        // /proc/net/dev format:
-        // RX: bytes packets errs drop fifo frame compressed multicast
-        // TX: bytes packets errs drop fifo colls carrier compressed
-        if fields.len() < 16 {
-            return None;
+// Where and how one target's frames are sent
+#[derive(Debug, Clone, PartialEq)]
+struct TargetOutput {
+    address: String,
+    protocol: OutputProtocol,
+    universe: u16,
+    start_channel: u16,
+    e131_multicast: bool,
+    opc_channel: u8,
+    encoding: PixelEncoding,
+}
+
+impl TargetOutput {
+    // The rendered RGB frame as this target's protocol sends it
+    fn encode_frame(&self, frame: Vec<u8>) -> Vec<u8> {
+        if self.protocol.takes_encoded_pixels() {
+            self.encoding.encode(frame)
+        } else {
+            frame
+        }
+    }
+
+    // Encoding encode_frame applies, plain RGB for protocols that don't take encoded pixels
+    fn sent_encoding(&self) -> PixelEncoding {
+        if self.protocol.takes_encoded_pixels() {
+            self.encoding.clone()
+        } else {
+            PixelEncoding::parse("rgb", "none")
         }
+    }
 
-        let rx_bytes = fields[0].parse::<u64>().ok()?;
-        let tx_bytes = fields[8].parse::<u64>().ok()?;
+    fn describe(&self) -> String {
+        let description = match self.protocol {
+            OutputProtocol::E131 if self.e131_multicast => format!("multicast (e131 universe {})", self.universe),
+            OutputProtocol::E131 | OutputProtocol::ArtNet => {
+                format!("{} ({} universe {})", self.address, self.protocol.name(), self.universe)
+            }
+            OutputProtocol::Opc => format!("{} (opc channel {})", self.address, self.opc_channel),
+            _ => format!("{} ({})", self.address, self.protocol.name()),
+        };
+        if self.protocol.takes_encoded_pixels() && !self.encoding.is_rgb() {
+            format!("{} {}", description, self.encoding.name())
+        } else {
+            description
+        }
+    }
+}
 
-        let now = Instant::now();
+// How a white channel is derived from the rendered RGB color on RGBW strips
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WhiteMode {
+    // Leave white off, RGB only
+    None,
+    // Move the common part of R, G and B to the white LED, keeping the color the same
+    Accurate,
+    // Add the common part to the white LED but keep RGB as well, brighter but less saturated
+    Brighter,
+}
 
-        if let Some(state) = self.interfaces.get(iface) {
-            let time_delta = now.duration_since(state.prev_time).as_secs_f64();
-            if time_delta > 0.0 {
-                let rx_delta = rx_bytes.saturating_sub(state.prev_rx_bytes) as f64;
-                let tx_delta = tx_bytes.saturating_sub(state.prev_tx_bytes) as f64;
+impl WhiteMode {
+    fn parse(mode: &str) -> Self {
+        match mode.to_lowercase().as_str() {
+            "none" | "off" => WhiteMode::None,
+            "brighter" | "add" => WhiteMode::Brighter,
+            _ => WhiteMode::Accurate,
+        }
+    }
+}
 
-                // Calculate kbps: (bytes * 8) / (time_seconds * 1000)
-                let rx_kbps = (rx_delta * 8.0) / (time_delta * 1000.0);
-                let tx_kbps = (tx_delta * 8.0) / (time_delta * 1000.0);
+// Channel order and count sent to the strip, e.g. "grb" or "rgbw"
+#[derive(Debug, Clone, PartialEq)]
+struct PixelEncoding {
+    // Indexes into [r, g, b, w] in the order the strip expects them
+    order: Vec<usize>,
+    white_mode: WhiteMode,
+}
 
-                self.interfaces.insert(
-                    iface.to_string(),
-                    InterfaceState {
-                        prev_rx_bytes: rx_bytes,
-                        prev_tx_bytes: tx_bytes,
-                        prev_time: now,
-                    },
-                );
+impl PixelEncoding {
+    fn parse(format: &str, white_mode: &str) -> Self {
+        let order: Vec<usize> = format
+            .to_lowercase()
+            .chars()
+            .filter_map(|c| "rgbw".find(c))
+            .collect();
+        let is_valid = (order.len() == 3 || order.len() == 4)
+            && (0..order.len()).all(|channel| order.contains(&channel));
 
-                // Return the bandwidth for this interface
-                return Some((rx_kbps, tx_kbps));
-            }
+        PixelEncoding {
+            order: if is_valid { order } else { vec![0, 1, 2] },
+            white_mode: WhiteMode::parse(white_mode),
         }
+    }
 
-        // First reading - just store values
-        self.interfaces.insert(
-            iface.to_string(),
-            InterfaceState {
-                prev_rx_bytes: rx_bytes,
-                prev_tx_bytes: tx_bytes,
-                prev_time: now,
-            },
-        );
+    fn channels(&self) -> usize {
+        self.order.len()
+    }
 
-        None
+    fn name(&self) -> String {
+        self.order.iter().map(|&i| ['r', 'g', 'b', 'w'][i]).collect()
     }
-}
 
-fn parse_bandwidth_line(line: &str, tracker: &mut Option<BandwidthTracker>) -> Option<(f64, f64)> {
-    let parts: Vec<&str> = line.trim().split_whitespace().collect();
+    fn is_rgb(&self) -> bool {
+        self.order == [0, 1, 2]
+    }
 
-    // macOS netstat format: 7 columns (packets errs bytes packets errs bytes colls)
-    // Column 2 = input bytes/sec, Column 5 = output bytes/sec
-    if parts.len() == 7 {
-        let rx_bytes_per_sec = parts[2].parse::<f64>().ok()?;
-        let tx_bytes_per_sec = parts[5].parse::<f64>().ok()?;
+    // Re-encode a rendered RGB frame into the strip's channel order
+    fn encode(&self, frame: Vec<u8>) -> Vec<u8> {
+        if self.is_rgb() {
+            return frame;
+        }
+
+        let mut encoded = Vec::with_capacity(frame.len() / 3 * self.channels());
+        for rgb in frame.chunks_exact(3) {
+            let channels = self.pixel_channels(rgb);
+            encoded.extend(self.order.iter().map(|&i| channels[i]));
+        }
+        encoded
+    }
 
-        // Convert bytes/sec to kbps
-        let rx_kbps = (rx_bytes_per_sec * 8.0) / 1000.0;
-        let tx_kbps = (tx_bytes_per_sec * 8.0) / 1000.0;
+    // [r, g, b, w] of one RGB pixel as the strip receives it, w stays 0 without a white channel
+    fn pixel_channels(&self, rgb: &[u8]) -> [u8; 4] {
+        let (mut r, mut g, mut b) = (rgb[0], rgb[1], rgb[2]);
+        let mut w = 0;
+        if self.channels() == 4 && self.white_mode != WhiteMode::None {
+            w = r.min(g).min(b);
+            if self.white_mode == WhiteMode::Accurate {
+                r -= w;
+                g -= w;
+                b -= w;
+            }
+        }
+        [r, g, b, w]
+    }
 
-        Some((rx_kbps, tx_kbps))
+    // Sum of every channel value the strip receives for an RGB frame, for the power estimate
+    fn channel_total(&self, frame: &[u8]) -> u64 {
+        frame
+            .chunks_exact(3)
+            .map(|rgb| self.pixel_channels(rgb).iter().map(|&v| v as u64).sum::<u64>())
+            .sum()
     }
-    // Linux /proc/net/dev format: interface: rx_bytes ... (has colon)
-    else if line.contains(':') {
-        if let Some(t) = tracker {
-            t.update_from_procnet_line(line)
+
+    fn ddp_config(&self) -> PixelConfig {
+        use ddp_rs::protocol::{DataType, PixelFormat};
+        if self.channels() == 4 {
+            PixelConfig {
+                data_type: DataType::RGBW,
+                data_size: PixelFormat::Pixel32Bits,
+                ..PixelConfig::default()
+            }
         } else {
-            None
+            PixelConfig::default()
         }
-    } else {
-        None
     }
 }
 
-fn parse_led_numbers(test_str: &str) -> Result<Vec<usize>> {
-    let mut leds = Vec::new();
+// Something that can show one frame of RGB pixel data
+trait PixelSink: Send {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()>;
+}
 
-    for part in test_str.split(',') {
-        let part = part.trim();
-        if part.contains('-') {
-            let range_parts: Vec<&str> = part.split('-').collect();
-            if range_parts.len() == 2 {
-                let start = range_parts[0].parse::<usize>()?;
-                let end = range_parts[1].parse::<usize>()?;
-                for i in start..=end {
-                    leds.push(i);
-                }
-            }
-        } else {
-            leds.push(part.parse::<usize>()?);
+fn connect_pixel_sink(output: &TargetOutput, socket: UdpSocket) -> Result<Box<dyn PixelSink>> {
+    match output.protocol {
+        OutputProtocol::Ddp => {
+            let dest_addr = format!("{}:4048", output.address);
+            let conn = DDPConnection::try_new(&dest_addr, output.encoding.ddp_config(), ID::Default, socket)?;
+            Ok(Box::new(DdpSink { conn }))
         }
+        OutputProtocol::E131 => Ok(Box::new(E131Sink::new(output, socket)?)),
+        OutputProtocol::ArtNet => Ok(Box::new(ArtNetSink::new(output, socket)?)),
+        OutputProtocol::WledUdp(mode) => Ok(Box::new(WledUdpSink::new(output, mode, socket)?)),
+        OutputProtocol::Json => Ok(Box::new(JsonSink::new(output)?)),
+        OutputProtocol::Opc => Ok(Box::new(OpcSink::new(output))),
     }
+}
 
-    Ok(leds)
+fn resolve_udp_addr(address: &str, port: u16) -> Result<std::net::SocketAddr> {
+    use std::net::ToSocketAddrs;
+    (address, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Could not resolve {}", address))
 }
 
-async fn test_mode(args: &Args) -> Result<()> {
-    let test_str = args.test.as_ref().unwrap();
-    let led_numbers = parse_led_numbers(test_str)?;
+struct DdpSink {
+    conn: DDPConnection,
+}
 
-    let default_wled = "led.local".to_string();
-    let wled_ip = args.wled_ip.as_ref().unwrap_or(&default_wled);
+impl PixelSink for DdpSink {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        self.conn.write_offset(frame, 0)?;
+        Ok(())
+    }
+}
 
-    println!("Test mode: blinking LEDs {:?}", led_numbers);
-    println!("Connecting to WLED at {}:4048", wled_ip);
+const DMX_UNIVERSE_SIZE: usize = 512;
 
-    let dest_addr = format!("{}:4048", wled_ip);
-    let socket = UdpSocket::bind("0.0.0.0:4048")?;
-    let mut ddp_conn =
-        DDPConnection::try_new(&dest_addr, PixelConfig::default(), ID::Default, socket)?;
+// One universe worth of a frame: which universe (relative to the first), the DMX slot
+// the data starts at (0-based) and the bytes of the frame it carries
+struct DmxChunk {
+    universe_offset: u16,
+    first_slot: usize,
+    bytes: std::ops::Range<usize>,
+}
 
-    println!("Connected! Starting blink loop...");
+// Split a frame across DMX universes without splitting a pixel between two universes.
+// The first universe starts at start_channel (1-based), the following ones at channel 1,
+// so 3-byte pixels fit 170 to a universe and 4-byte RGBW pixels 128.
+fn dmx_chunks(frame_len: usize, start_channel: u16, channels_per_pixel: usize) -> Vec<DmxChunk> {
+    let mut chunks = Vec::new();
+    let mut first_slot = (start_channel.clamp(1, DMX_UNIVERSE_SIZE as u16) - 1) as usize;
+    let mut offset = 0;
+    let mut universe_offset = 0;
+
+    while offset < frame_len {
+        let pixels = (DMX_UNIVERSE_SIZE - first_slot) / channels_per_pixel;
+        let len = (pixels * channels_per_pixel).min(frame_len - offset);
+        if len > 0 {
+            chunks.push(DmxChunk {
+                universe_offset,
+                first_slot,
+                bytes: offset..offset + len,
+            });
+        }
+        offset += len;
+        universe_offset += 1;
+        first_slot = 0;
+    }
 
-    let test_color = Rgb::from_hex("FF0000")?;
-    let mut iteration = 0;
+    chunks
+}
 
-    loop {
-        iteration += 1;
-        println!("\n=== Iteration {} ===", iteration);
+// E1.31 (sACN) data packets, unicast to the target or multicast to 239.255.<universe>
+struct E131Sink {
+    socket: UdpSocket,
+    unicast_addr: Option<std::net::SocketAddr>,
+    universe: u16,
+    start_channel: u16,
+    channels_per_pixel: usize,
+    cid: [u8; 16],
+    sequence: u8,
+}
 
-        println!("Turning ON LEDs {:?}", led_numbers);
-        let max_led = led_numbers.iter().max().copied().unwrap_or(0);
-        let frame_size = (max_led + 1) * 3;
-        let mut frame = vec![0u8; frame_size];
+impl E131Sink {
+    const PORT: u16 = 5568;
 
-        for &led_num in &led_numbers {
-            let offset = led_num * 3;
-            frame[offset] = test_color.r;
-            frame[offset + 1] = test_color.g;
-            frame[offset + 2] = test_color.b;
+    fn new(output: &TargetOutput, socket: UdpSocket) -> Result<Self> {
+        let unicast_addr = if output.e131_multicast {
+            None
+        } else {
+            Some(resolve_udp_addr(&output.address, Self::PORT)?)
+        };
+
+        // Component identifier, only needs to be unique per sender
+        let mut seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64
+            ^ ((std::process::id() as u64) << 32)
+            ^ output.universe as u64;
+        let mut cid = [0u8; 16];
+        for byte in cid.iter_mut() {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            *byte = seed as u8;
         }
 
-        ddp_conn.write_offset(&frame, 0)?;
-        println!("Sent {} bytes starting at offset 0", frame.len());
+        Ok(E131Sink {
+            socket,
+            unicast_addr,
+            universe: output.universe.clamp(1, 63999),
+            start_channel: output.start_channel,
+            channels_per_pixel: output.encoding.channels(),
+            cid,
+            sequence: 0,
+        })
+    }
 
-        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+    fn packet(&self, universe: u16, first_slot: usize, data: &[u8]) -> Vec<u8> {
+        let slots = first_slot + data.len();
+        let total = 126 + slots;
+        let mut packet = Vec::with_capacity(total);
+
+        // Root layer
+        packet.extend_from_slice(&[0x00, 0x10, 0x00, 0x00]);
+        packet.extend_from_slice(b"ASC-E1.17\0\0\0");
+        packet.extend_from_slice(&(0x7000 | (total - 16) as u16).to_be_bytes());
+        packet.extend_from_slice(&4u32.to_be_bytes());
+        packet.extend_from_slice(&self.cid);
+
+        // Framing layer
+        packet.extend_from_slice(&(0x7000 | (total - 38) as u16).to_be_bytes());
+        packet.extend_from_slice(&2u32.to_be_bytes());
+        let mut source_name = [0u8; 64];
+        let name = b"bandwidth_meter";
+        source_name[..name.len()].copy_from_slice(name);
+        packet.extend_from_slice(&source_name);
+        packet.push(100); // Priority
+        packet.extend_from_slice(&[0x00, 0x00]); // Synchronization address
+        packet.push(self.sequence);
+        packet.push(0x00); // Options
+        packet.extend_from_slice(&universe.to_be_bytes());
+
+        // DMP layer
+        packet.extend_from_slice(&(0x7000 | (total - 115) as u16).to_be_bytes());
+        packet.push(0x02);
+        packet.push(0xa1);
+        packet.extend_from_slice(&0u16.to_be_bytes()); // First property address
+        packet.extend_from_slice(&1u16.to_be_bytes()); // Address increment
+        packet.extend_from_slice(&(slots as u16 + 1).to_be_bytes());
+        packet.push(0x00); // DMX start code
+        packet.resize(packet.len() + first_slot, 0);
+        packet.extend_from_slice(data);
+
+        packet
+    }
 
-        println!("Turning OFF LEDs");
-        let frame = vec![0u8; frame_size];
-        ddp_conn.write_offset(&frame, 0)?;
-        println!("Sent {} bytes of black", frame.len());
+    // The data packets carrying one frame, each with the universe it is for
+    fn packets(&self, frame: &[u8]) -> Vec<(u16, Vec<u8>)> {
+        dmx_chunks(frame.len(), self.start_channel, self.channels_per_pixel)
+            .into_iter()
+            .map(|chunk| {
+                let universe = self.universe.saturating_add(chunk.universe_offset);
+                (universe, self.packet(universe, chunk.first_slot, &frame[chunk.bytes]))
+            })
+            .collect()
+    }
+}
 
-        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+impl PixelSink for E131Sink {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        for (universe, packet) in self.packets(frame) {
+            let dest = match self.unicast_addr {
+                Some(addr) => addr,
+                None => std::net::SocketAddr::from(([239, 255, (universe >> 8) as u8, universe as u8], Self::PORT)),
+            };
+            self.socket.send_to(&packet, dest)?;
+        }
+        self.sequence = self.sequence.wrapping_add(1);
+        Ok(())
     }
 }
-// HTTP Configuration Server Module
 
-const WEB_UI_HTML: &str = r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>Bandwidth Meter Configuration</title>
-    <style>
-        * { margin: 0; padding: 0; box-sizing: border-box; }
-        body {
-            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, sans-serif;
-            background: #1a1a1a;
-            color: #e0e0e0;
-            padding: 20px;
-            line-height: 1.6;
-        }
-        .container {
-            max-width: 900px;
-            margin: 0 auto;
-        }
-        h1 {
-            color: #00aaff;
-            margin-bottom: 30px;
-            font-size: 2em;
+// Art-Net ArtDmx packets, unicast to the target
+struct ArtNetSink {
+    socket: UdpSocket,
+    addr: std::net::SocketAddr,
+    universe: u16,
+    start_channel: u16,
+    channels_per_pixel: usize,
+    sequence: u8,
+}
+
+impl ArtNetSink {
+    const PORT: u16 = 6454;
+
+    fn new(output: &TargetOutput, socket: UdpSocket) -> Result<Self> {
+        Ok(ArtNetSink {
+            socket,
+            addr: resolve_udp_addr(&output.address, Self::PORT)?,
+            universe: output.universe & 0x7fff,
+            start_channel: output.start_channel,
+            channels_per_pixel: output.encoding.channels(),
+            sequence: 1,
+        })
+    }
+
+    // The ArtDmx packets carrying one frame
+    fn packets(&self, frame: &[u8]) -> Vec<Vec<u8>> {
+        dmx_chunks(frame.len(), self.start_channel, self.channels_per_pixel)
+            .into_iter()
+            .map(|chunk| {
+                let universe = (self.universe + chunk.universe_offset) & 0x7fff;
+                // Length must be even
+                let length = (chunk.first_slot + chunk.bytes.len() + 1) & !1;
+
+                let mut packet = Vec::with_capacity(18 + length);
+                packet.extend_from_slice(b"Art-Net\0");
+                packet.extend_from_slice(&0x5000u16.to_le_bytes()); // OpDmx
+                packet.extend_from_slice(&14u16.to_be_bytes()); // Protocol version
+                packet.push(self.sequence);
+                packet.push(0); // Physical port
+                packet.extend_from_slice(&universe.to_le_bytes()); // SubUni, Net
+                packet.extend_from_slice(&(length as u16).to_be_bytes());
+                packet.resize(packet.len() + chunk.first_slot, 0);
+                packet.extend_from_slice(&frame[chunk.bytes]);
+                packet.resize(18 + length, 0);
+                packet
+            })
+            .collect()
+    }
+}
+
+impl PixelSink for ArtNetSink {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        for packet in self.packets(frame) {
+            self.socket.send_to(&packet, self.addr)?;
         }
-        .config-grid {
-            display: grid;
-            gap: 15px;
+        // Sequence 0 means "not used", so wrap from 255 back to 1
+        self.sequence = if self.sequence == 255 { 1 } else { self.sequence + 1 };
+        Ok(())
+    }
+}
+
+// WLED's own realtime UDP protocols on port 21324
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WledUdpMode {
+    // DRGB when the strip fits in one packet, DNRGB otherwise
+    Auto,
+    Warls,
+    Drgb,
+    Dnrgb,
+}
+
+struct WledUdpSink {
+    socket: UdpSocket,
+    addr: std::net::SocketAddr,
+    mode: WledUdpMode,
+}
+
+impl WledUdpSink {
+    const PORT: u16 = 21324;
+    // Seconds WLED waits after the last packet before returning to its normal effect
+    const TIMEOUT_SECS: u8 = 2;
+    const WARLS_MAX_PIXELS: usize = 255;
+    const DRGB_MAX_PIXELS: usize = 490;
+    const DNRGB_MAX_PIXELS: usize = 489;
+
+    fn new(output: &TargetOutput, mode: WledUdpMode, socket: UdpSocket) -> Result<Self> {
+        Ok(WledUdpSink {
+            socket,
+            addr: resolve_udp_addr(&output.address, Self::PORT)?,
+            mode,
+        })
+    }
+
+    // The UDP packets carrying one RGB frame in the given mode
+    fn packets(mode: WledUdpMode, frame: &[u8]) -> Vec<Vec<u8>> {
+        let pixels = frame.len() / 3;
+        let mode = match mode {
+            WledUdpMode::Auto if pixels <= Self::DRGB_MAX_PIXELS => WledUdpMode::Drgb,
+            WledUdpMode::Auto => WledUdpMode::Dnrgb,
+            mode => mode,
+        };
+
+        match mode {
+            WledUdpMode::Warls => {
+                // Index is a single byte, so only the first 255 LEDs can be addressed
+                let mut packet = vec![1, Self::TIMEOUT_SECS];
+                for (i, rgb) in frame.chunks_exact(3).take(Self::WARLS_MAX_PIXELS).enumerate() {
+                    packet.push(i as u8);
+                    packet.extend_from_slice(rgb);
+                }
+                vec![packet]
+            }
+            WledUdpMode::Drgb => {
+                let mut packet = vec![2, Self::TIMEOUT_SECS];
+                packet.extend_from_slice(&frame[..pixels.min(Self::DRGB_MAX_PIXELS) * 3]);
+                vec![packet]
+            }
+            WledUdpMode::Dnrgb | WledUdpMode::Auto => frame
+                .chunks(Self::DNRGB_MAX_PIXELS * 3)
+                .enumerate()
+                .map(|(chunk_index, chunk)| {
+                    let start = (chunk_index * Self::DNRGB_MAX_PIXELS) as u16;
+                    let mut packet = vec![4, Self::TIMEOUT_SECS];
+                    packet.extend_from_slice(&start.to_be_bytes());
+                    packet.extend_from_slice(chunk);
+                    packet
+                })
+                .collect(),
         }
-        .config-item {
-            margin-bottom: 12px;
+    }
+}
+
+impl PixelSink for WledUdpSink {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        for packet in Self::packets(self.mode, frame) {
+            self.socket.send_to(&packet, self.addr)?;
         }
-        .config-item label {
-            display: block;
-            color: #b0b0b0;
-            margin-bottom: 8px;
-            font-size: 0.9em;
-            text-transform: uppercase;
-            letter-spacing: 0.5px;
+        Ok(())
+    }
+}
+
+// Slow fallback through WLED's JSON API for controllers without any realtime input.
+// Frames are rate limited and reduced to a handful of color runs, enough for status colors.
+struct JsonSink {
+    client: reqwest::blocking::Client,
+    url: String,
+    last_sent: Option<Instant>,
+    last_body: String,
+}
+
+impl JsonSink {
+    const MIN_INTERVAL: Duration = Duration::from_millis(500);
+    const MAX_RUNS: usize = 64;
+
+    fn new(output: &TargetOutput) -> Result<Self> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(2))
+            .build()?;
+        Ok(JsonSink {
+            client,
+            url: format!("http://{}/json/state", output.address),
+            last_sent: None,
+            last_body: String::new(),
+        })
+    }
+
+    // Collapse the frame into at most MAX_RUNS [start, stop, "RRGGBB"] ranges for the segment "i" array
+    fn color_runs(frame: &[u8]) -> Vec<serde_json::Value> {
+        let pixels: Vec<&[u8]> = frame.chunks_exact(3).collect();
+        let block = pixels.len().div_ceil(Self::MAX_RUNS).max(1);
+
+        let mut runs: Vec<(usize, usize, [u8; 3])> = Vec::new();
+        for (block_index, block_pixels) in pixels.chunks(block).enumerate() {
+            // Average each block so gradients survive as a coarse approximation
+            let mut sum = [0usize; 3];
+            for rgb in block_pixels {
+                for c in 0..3 {
+                    sum[c] += rgb[c] as usize;
+                }
+            }
+            let color = sum.map(|v| (v / block_pixels.len()) as u8);
+            let start = block_index * block;
+            let stop = start + block_pixels.len();
+
+            match runs.last_mut() {
+                Some(last) if last.2 == color => last.1 = stop,
+                _ => runs.push((start, stop, color)),
+            }
         }
-        .input-group {
-            display: flex;
-            gap: 10px;
-            align-items: center;
+
+        runs.into_iter()
+            .flat_map(|(start, stop, [r, g, b])| {
+                [
+                    serde_json::json!(start),
+                    serde_json::json!(stop),
+                    serde_json::json!(format!("{:02X}{:02X}{:02X}", r, g, b)),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl PixelSink for JsonSink {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        if self.last_sent.is_some_and(|sent| sent.elapsed() < Self::MIN_INTERVAL) {
+            return Ok(());
         }
-        input[type="text"], input[type="number"], select, textarea {
-            flex: 1;
-            background: #1a1a1a;
-            border: 1px solid #505050;
-            color: #e0e0e0;
-            padding: 10px 12px;
-            border-radius: 4px;
-            font-size: 1em;
+
+        let body = serde_json::json!({
+            "on": true,
+            "seg": [{ "id": 0, "i": Self::color_runs(frame) }],
+        })
+        .to_string();
+        self.last_sent = Some(Instant::now());
+        if body == self.last_body {
+            return Ok(());
         }
-        input.invalid {
-            border: 2px solid #ff4444;
-            background: #3a1a1a;
+
+        self.client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body.clone())
+            .send()?
+            .error_for_status()?;
+        self.last_body = body;
+        Ok(())
+    }
+}
+
+// Open Pixel Control over TCP (Fadecandy fcserver, gl_server and other OPC simulators)
+struct OpcSink {
+    address: String,
+    channel: u8,
+    stream: Option<std::net::TcpStream>,
+    last_attempt: Option<Instant>,
+}
+
+impl OpcSink {
+    const PORT: u16 = 7890;
+    const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+    // Connects on the first frame, so a server that's down at startup is retried like a dropped one
+    fn new(output: &TargetOutput) -> Self {
+        // OPC servers often run several instances on one host, so wled_ip may carry a port
+        let address = match output.address.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') && port.parse::<u16>().is_ok() => output.address.clone(),
+            _ => format!("{}:{}", output.address, Self::PORT),
+        };
+        OpcSink {
+            address,
+            channel: output.opc_channel,
+            stream: None,
+            last_attempt: None,
         }
-        button:disabled {
-            background: #555555;
-            cursor: not-allowed;
-            opacity: 0.5;
+    }
+
+    fn connect(&self) -> Result<std::net::TcpStream> {
+        use std::net::ToSocketAddrs;
+        let addr = self
+            .address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Could not resolve {}", self.address))?;
+        let stream = std::net::TcpStream::connect_timeout(&addr, Duration::from_secs(2))?;
+        stream.set_nodelay(true)?;
+        // A stalled server must not hold up this target's sender thread for long
+        stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+        Ok(stream)
+    }
+
+    // Set Pixel Colors (command 0); the length field limits a message to 21845 pixels
+    fn message(channel: u8, frame: &[u8]) -> Vec<u8> {
+        let data = &frame[..frame.len().min(0xffff / 3 * 3)];
+        let mut message = Vec::with_capacity(4 + data.len());
+        message.push(channel);
+        message.push(0);
+        message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        message.extend_from_slice(data);
+        message
+    }
+}
+
+impl PixelSink for OpcSink {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        if self.stream.is_none() {
+            if self.last_attempt.is_some_and(|last| last.elapsed() < Self::RECONNECT_INTERVAL) {
+                anyhow::bail!("not connected to {}", self.address);
+            }
+            self.last_attempt = Some(Instant::now());
+            self.stream = Some(self.connect()?);
         }
-        input[type="checkbox"] {
-            width: 20px;
-            height: 20px;
-            cursor: pointer;
+
+        let message = Self::message(self.channel, frame);
+        if let Some(stream) = self.stream.as_mut() {
+            if let Err(e) = stream.write_all(&message) {
+                // Drop the connection so the next frame reconnects
+                self.stream = None;
+                self.last_attempt = Some(Instant::now());
+                return Err(e.into());
+            }
         }
-        input[type="range"] {
-            flex: 1;
-            cursor: pointer;
+        Ok(())
+    }
+}
+
+// Dedicated renderer that runs in its own thread at configurable FPS
+struct Renderer {
+    targets: Vec<RenderTarget>,
+    shared_state: Arc<Mutex<SharedRenderState>>,
+    shutdown: Arc<AtomicBool>,
+
+    // Built from shared state
+    tx_gradient: Option<colorgrad::Gradient>,
+    rx_gradient: Option<colorgrad::Gradient>,
+    tx_colors: Vec<Rgb>,
+    rx_colors: Vec<Rgb>,
+    tx_solid_color: Rgb,
+    rx_solid_color: Rgb,
+    zones: Vec<ColorZone>,
+
+    // Peak-hold state, shared by all targets
+    tx_peak: PeakHold,
+    rx_peak: PeakHold,
+
+    // Live error sparkles and the xorshift state that scatters them
+    sparkles: Vec<Sparkle>,
+    rng: u64,
+
+    // Cache to detect when gradients need rebuilding
+    last_generation: u64,
+}
+
+impl Renderer {
+    fn new(
+        targets: Vec<RenderTarget>,
+        shared_state: Arc<Mutex<SharedRenderState>>,
+        shutdown: Arc<AtomicBool>,
+    ) -> Result<Self> {
+        // Lock shared state to get initial colors
+        let state = shared_state.lock().unwrap();
+        let (tx_gradient, tx_colors, tx_solid_color) =
+            build_gradient_from_color(&state.tx_color, state.use_gradient, state.interpolation_mode)?;
+        let (rx_gradient, rx_colors, rx_solid_color) =
+            build_gradient_from_color(&state.rx_color, state.use_gradient, state.interpolation_mode)?;
+        let zones = build_color_zones(&state.zone_colors)?;
+        let last_generation = state.generation;
+        drop(state);
+
+        Ok(Renderer {
+            targets,
+            shared_state,
+            shutdown,
+            tx_gradient,
+            rx_gradient,
+            tx_colors,
+            rx_colors,
+            tx_solid_color,
+            rx_solid_color,
+            zones,
+            tx_peak: PeakHold::default(),
+            rx_peak: PeakHold::default(),
+            sparkles: Vec::new(),
+            rng: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos() as u64 | 1,
+            last_generation,
+        })
+    }
+
+    // Uniform 0.0-1.0 from a xorshift64 generator, plenty for scattering sparkles
+    fn next_random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Age the live sparkles and start one for each new error or drop
+    fn update_sparkles(&mut self, rx_events: u64, tx_events: u64, delta_seconds: f64) {
+        for sparkle in &mut self.sparkles {
+            sparkle.age_secs += delta_seconds;
         }
-        .range-value {
-            min-width: 80px;
-            text-align: center;
-            color: #00aaff;
-            font-weight: 600;
+        self.sparkles.retain(|s| s.age_secs < Sparkle::LIFETIME_SECS);
+
+        let new_events = [(false, rx_events), (true, tx_events)]
+            .into_iter()
+            .flat_map(|(is_tx, events)| std::iter::repeat_n(is_tx, events.min(Sparkle::MAX_ACTIVE as u64) as usize))
+            .collect::<Vec<_>>();
+        for is_tx in new_events {
+            let position = self.next_random();
+            self.sparkles.push(Sparkle { is_tx, position, age_secs: 0.0 });
         }
-        button {
-            background: #00aaff;
-            color: white;
-            border: none;
-            padding: 10px 20px;
-            border-radius: 4px;
-            cursor: pointer;
-            font-size: 0.9em;
-            font-weight: 600;
-            transition: background 0.2s;
+
+        // Oldest sparkles go first when there are too many
+        let excess = self.sparkles.len().saturating_sub(Sparkle::MAX_ACTIVE);
+        self.sparkles.drain(..excess);
+    }
+
+    fn rebuild_gradients_if_needed(&mut self) -> Result<()> {
+        let state = self.shared_state.lock().unwrap();
+
+        // Check if generation changed (config updated)
+        if state.generation != self.last_generation {
+            let (tx_gradient, tx_colors, tx_solid_color) =
+                build_gradient_from_color(&state.tx_color, state.use_gradient, state.interpolation_mode)?;
+            let (rx_gradient, rx_colors, rx_solid_color) =
+                build_gradient_from_color(&state.rx_color, state.use_gradient, state.interpolation_mode)?;
+            let zones = build_color_zones(&state.zone_colors)?;
+
+            self.tx_gradient = tx_gradient;
+            self.tx_colors = tx_colors;
+            self.tx_solid_color = tx_solid_color;
+            self.rx_gradient = rx_gradient;
+            self.rx_colors = rx_colors;
+            self.rx_solid_color = rx_solid_color;
+            self.zones = zones;
+            self.last_generation = state.generation;
         }
-        button:hover {
-            background: #0088cc;
+
+        Ok(())
+    }
+
+    // Whole LEDs to light, plus the brightness (0.0-1.0) of the partly filled LED just past them
+    fn calculate_leds(&self, bandwidth_kbps: f64, max_bandwidth_kbps: f64, scale: &BandwidthScale, leds_per_direction: usize) -> (usize, f64) {
+        let percentage = scale.fraction(bandwidth_kbps, max_bandwidth_kbps);
+        let exact = (percentage * leds_per_direction as f64).min(leds_per_direction as f64);
+        let leds = exact as usize;
+        let partial = if leds < leds_per_direction { exact - leds as f64 } else { 0.0 };
+        (leds, partial)
+    }
+
+    fn calculate_effective_speed(&self, rx_kbps: f64, tx_kbps: f64, state: &SharedRenderState) -> (f64, f64) {
+        if state.scale_animation_speed {
+            // Use the currently displayed (interpolated) bandwidth values, not the target values
+            // This ensures animation continues smoothly during the interpolation period
+            let tx_utilization = state.scale.fraction(tx_kbps, state.max_bandwidth_kbps);
+            let rx_utilization = state.scale.fraction(rx_kbps, state.max_bandwidth_kbps);
+
+            // Quantize to nice fractions to avoid aliasing/stuttering
+            // Use FPS for quantization to avoid stuttering at different frame rates
+            let tx_quantized = (tx_utilization * state.fps).round() / state.fps;
+            let rx_quantized = (rx_utilization * state.fps).round() / state.fps;
+
+            let tx_speed = state.animation_speed * tx_quantized;
+            let rx_speed = state.animation_speed * rx_quantized;
+
+            (tx_speed, rx_speed)
+        } else {
+            (state.animation_speed, state.animation_speed)
         }
-        button:active {
-            background: #006699;
+    }
+
+    fn calculate_led_positions(&self, tx_leds: usize, rx_leds: usize, layout: &TargetLayout) -> (Vec<usize>, Vec<usize>) {
+        let TargetLayout { total_leds, direction, swap, .. } = *layout;
+
+        // A segment map lists the LEDs of each bar in fill order
+        if let Some(ref segments) = layout.segments {
+            let (tx_run, rx_run) = if swap { (&segments.rx, &segments.tx) } else { (&segments.tx, &segments.rx) };
+            return (
+                tx_run.iter().copied().take(tx_leds).collect(),
+                rx_run.iter().copied().take(rx_leds).collect(),
+            );
         }
-        .message {
-            position: fixed;
-            top: 20px;
-            right: 20px;
-            padding: 15px 20px;
-            border-radius: 4px;
-            font-weight: 500;
-            opacity: 0;
-            transition: opacity 0.3s;
-            z-index: 1000;
-        }
-        .message.show {
-            opacity: 1;
-        }
-        .message.success {
-            background: #2d5016;
-            border: 1px solid #4a8028;
-            color: #a3d977;
+
+        let half = total_leds / 2;
+
+        let (first_half_leds, second_half_leds) = if swap {
+            (tx_leds, rx_leds)
+        } else {
+            (rx_leds, tx_leds)
+        };
+
+        let (first_half_pos, second_half_pos) = match direction {
+            DirectionMode::Mirrored => {
+                let first: Vec<usize> = (0..first_half_leds).map(|i| half - 1 - i).collect();
+                let second: Vec<usize> = (0..second_half_leds).map(|i| half + i).collect();
+                (first, second)
+            }
+            DirectionMode::Opposing => {
+                let first: Vec<usize> = (0..first_half_leds).collect();
+                let second: Vec<usize> = (0..second_half_leds)
+                    .map(|i| total_leds - 1 - i)
+                    .collect();
+                (first, second)
+            }
+            DirectionMode::Left => {
+                let first: Vec<usize> = (0..first_half_leds).map(|i| half - 1 - i).collect();
+                let second: Vec<usize> = (0..second_half_leds)
+                    .map(|i| total_leds - 1 - i)
+                    .collect();
+                (first, second)
+            }
+            DirectionMode::Right => {
+                let first: Vec<usize> = (0..first_half_leds).collect();
+                let second: Vec<usize> = (0..second_half_leds).map(|i| half + i).collect();
+                (first, second)
+            }
+        };
+
+        if swap {
+            (first_half_pos, second_half_pos)
+        } else {
+            (second_half_pos, first_half_pos)
         }
-        .message.error {
-            background: #5a1a1a;
-            border: 1px solid #902020;
-            color: #ff9090;
+    }
+
+    fn render_frame(&mut self, delta_seconds: f64) -> Result<()> {
+        // Rebuild gradients if config changed (very quick check)
+        self.rebuild_gradients_if_needed()?;
+
+        // Lock shared state only long enough to read current values
+        let mut state = self.shared_state.lock().unwrap();
+
+        // Interpolate bandwidth values for smooth transitions
+        let (rx_kbps, tx_kbps) = if let Some(last_update) = state.last_bandwidth_update {
+            let elapsed_ms = last_update.elapsed().as_secs_f64() * 1000.0;
+            let interpolation_time = state.interpolation_time_ms;
+            let t = (elapsed_ms / interpolation_time).min(1.0); // Interpolation factor (0.0 to 1.0)
+
+            // Smoothly transition from start to current over interpolation_time_ms
+            let interpolated_rx = state.start_rx_kbps + (state.current_rx_kbps - state.start_rx_kbps) * t;
+            let interpolated_tx = state.start_tx_kbps + (state.current_tx_kbps - state.start_tx_kbps) * t;
+
+            (interpolated_rx, interpolated_tx)
+        } else {
+            // No update yet, use current values
+            (state.current_rx_kbps, state.current_tx_kbps)
+        };
+
+        let (tx_effective_speed, rx_effective_speed) = self.calculate_effective_speed(rx_kbps, tx_kbps, &state);
+        let layouts = state.targets.clone();
+        let correction = state.correction;
+        let utilization = if state.max_bandwidth_kbps > 0.0 {
+            rx_kbps.max(tx_kbps) / state.max_bandwidth_kbps
+        } else {
+            0.0
+        };
+        let level = state.schedule.level(utilization, delta_seconds);
+        let strobe_on_max = state.strobe_on_max;
+        let strobe_rate_hz = state.strobe_rate_hz;
+        let strobe_duration_ms = state.strobe_duration_ms;
+        let strobe_color_str = state.strobe_color.clone();
+        let peak_hold = state.peak_hold;
+        let peak_markers = peak_hold.enabled.then(|| PeakMarkers {
+            tx_fraction: self.tx_peak.update(state.scale.fraction(tx_kbps, state.max_bandwidth_kbps), delta_seconds, &peak_hold),
+            rx_fraction: self.rx_peak.update(state.scale.fraction(rx_kbps, state.max_bandwidth_kbps), delta_seconds, &peak_hold),
+            tx_color: peak_hold.tx_color,
+            rx_color: peak_hold.rx_color,
+        });
+        let mut inputs = FrameInputs {
+            rx_kbps,
+            tx_kbps,
+            max_bandwidth_kbps: state.max_bandwidth_kbps,
+            scale: state.scale,
+            use_gradient: state.use_gradient,
+            color_zones: state.color_zones,
+            tx_effective_speed,
+            rx_effective_speed,
+            fps: state.fps,
+            tx_animation_direction: state.tx_animation_direction.clone(),
+            rx_animation_direction: state.rx_animation_direction.clone(),
+            strobe_phase_active: false,
+            strobe_color: Rgb { r: 0, g: 0, b: 0 },
+            peak_markers,
+            history: (state.history.enabled || layouts.iter().any(|l| l.matrix.is_some_and(|m| m.mode != MatrixMode::Bars))).then(|| HistoryFrame {
+                enabled: state.history.enabled,
+                samples: state.history_samples.iter().copied().collect(),
+                style: state.history.style,
+                leds_per_sample: state.history.leds_per_sample,
+            }),
+            interface_bands: (state.interface_bands.enabled && state.interface_levels.len() > 1).then(|| InterfaceBands {
+                colors: state.interface_bands.colors.clone(),
+                rx_shares: state.interface_levels.iter().map(|&(rx, _)| rx).collect(),
+                tx_shares: state.interface_levels.iter().map(|&(_, tx)| tx).collect(),
+            }),
+            sparkles: Vec::new(),
+            sparkle_color: state.error_sparkle.unwrap_or(Rgb { r: 0, g: 0, b: 0 }),
+            no_data: None,
+        };
+        let no_data = (state.data_state != DataState::Live).then_some(state.no_data);
+        let error_sparkle = state.error_sparkle.is_some();
+        let rx_error_events = std::mem::take(&mut state.rx_error_events);
+        let tx_error_events = std::mem::take(&mut state.tx_error_events);
+        drop(state); // Release lock immediately
+
+        // Errors that arrive while sparkles are off are dropped, not saved up for later
+        if error_sparkle {
+            self.update_sparkles(rx_error_events, tx_error_events, delta_seconds);
+        } else {
+            self.sparkles.clear();
         }
-        .help-text {
-            font-size: 0.85em;
-            color: #808080;
-            margin-top: 4px;
+        inputs.sparkles = self.sparkles.clone();
+
+        // Parse strobe color
+        inputs.strobe_color = Rgb::from_hex(&strobe_color_str).unwrap_or(Rgb { r: 0, g: 0, b: 0 });
+
+        if strobe_on_max && strobe_rate_hz > 0.0 {
+            let now = SystemTime::now();
+            let elapsed_millis = now.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
+
+            // Calculate full cycle time in milliseconds
+            let cycle_ms = (1000.0 / strobe_rate_hz) as u128;
+            // Clamp strobe duration to not exceed the full cycle
+            let clamped_duration = (strobe_duration_ms as u128).min(cycle_ms);
+
+            // Determine position within the current cycle
+            let position_in_cycle = elapsed_millis % cycle_ms;
+            // Strobe is active during the last 'duration' milliseconds of each cycle
+            inputs.strobe_phase_active = position_in_cycle >= (cycle_ms - clamped_duration);
         }
-        .section {
-            background: #2a2a2a;
-            border: 1px solid #404040;
-            border-radius: 8px;
-            padding: 20px;
-            margin-bottom: 20px;
+
+        // Breathe between 10% and 50% over 2 seconds, or blink on for the first half of every second
+        if let Some(no_data) = no_data {
+            let secs = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs_f64();
+            let color = match no_data.pattern {
+                NoDataPattern::Breathe => no_data.color.scaled(0.3 + 0.2 * (secs * std::f64::consts::PI).sin()),
+                NoDataPattern::Blink if secs.fract() < 0.5 => no_data.color,
+                NoDataPattern::Blink => Rgb { r: 0, g: 0, b: 0 },
+            };
+            inputs.no_data = Some(NoDataConfig { color, ..no_data });
         }
-        .section-header {
-            color: #00aaff;
-            font-size: 1.3em;
-            font-weight: 600;
-            margin-bottom: 20px;
-            padding-bottom: 10px;
-            border-bottom: 2px solid #404040;
+
+        // Every target draws the same bandwidth with its own layout
+        let mut targets = std::mem::take(&mut self.targets);
+        for (target, layout) in targets.iter_mut().zip(&layouts) {
+            let mut frame = self.render_target(target, layout, &inputs, delta_seconds);
+            correction.apply(&mut frame, level, layout.power_limit_ma, &target.encoding);
+            // A busy or unreachable target drops this frame instead of holding up the others
+            let _ = target.frames.try_send(frame);
         }
-        .testing-grid {
-            display: flex;
-            justify-content: center;
-            gap: 40px;
-            align-items: center;
+        self.targets = targets;
+
+        Ok(())
+    }
+
+    fn render_target(&self, target: &mut RenderTarget, layout: &TargetLayout, inputs: &FrameInputs, delta_seconds: f64) -> Vec<u8> {
+        let FrameInputs {
+            rx_kbps,
+            tx_kbps,
+            max_bandwidth_kbps,
+            scale,
+            use_gradient,
+            color_zones,
+            tx_effective_speed,
+            rx_effective_speed,
+            fps,
+            ref tx_animation_direction,
+            ref rx_animation_direction,
+            strobe_phase_active,
+            strobe_color,
+            peak_markers,
+            ref history,
+            ref interface_bands,
+            ..
+        } = *inputs;
+        let total_leds = layout.total_leds;
+        let swap = layout.swap;
+        let rx_split_percent = layout.rx_split_percent.clamp(0.0, 100.0);
+
+        // Calculate LED split based on rx_split_percent, or the segment map when there is one
+        let (rx_leds_available, tx_leds_available) = match layout.segments {
+            Some(ref segments) if swap => (segments.tx.len(), segments.rx.len()),
+            Some(ref segments) => (segments.rx.len(), segments.tx.len()),
+            None => {
+                let rx_leds_available = ((total_leds as f64 * rx_split_percent) / 100.0) as usize;
+                (rx_leds_available, total_leds - rx_leds_available)
+            }
+        };
+        let leds_per_direction = total_leds / 2; // Keep for backward compatibility with position calculations
+
+        // Calculate LED counts using the configurable split
+        let (rx_leds, rx_partial) = self.calculate_leds(rx_kbps, max_bandwidth_kbps, &scale, rx_leds_available);
+        let (tx_leds, tx_partial) = self.calculate_leds(tx_kbps, max_bandwidth_kbps, &scale, tx_leds_available);
+
+        // Strobe a segment if it is at max and in strobe phase
+        let rx_strobe_active = strobe_phase_active && rx_leds >= rx_leds_available;
+        let tx_strobe_active = strobe_phase_active && tx_leds >= tx_leds_available;
+
+        // Update animation offsets independently for TX and RX
+        if tx_effective_speed > 0.0 {
+            let leds_per_second = tx_effective_speed * fps;
+            let offset_delta = (leds_per_second * delta_seconds) / leds_per_direction as f64;
+            target.tx_animation_offset = (target.tx_animation_offset + offset_delta) % 1.0;
         }
-        .testing-item {
-            display: flex;
-            align-items: center;
-            gap: 10px;
+
+        if rx_effective_speed > 0.0 {
+            let leds_per_second = rx_effective_speed * fps;
+            let offset_delta = (leds_per_second * delta_seconds) / leds_per_direction as f64;
+            target.rx_animation_offset = (target.rx_animation_offset + offset_delta) % 1.0;
         }
-        .testing-item label {
-            margin: 0;
-            cursor: pointer;
+
+        // Prepare frame
+        let frame_size = total_leds * 3;
+        let mut frame = vec![0u8; frame_size];
+
+        if let Some(no_data) = inputs.no_data {
+            self.render_no_data(&mut frame, layout, &no_data, tx_leds_available, rx_leds_available);
+            return frame;
         }
-    </style>
-</head>
-<body>
-    <div class="container">
-        <h1>Bandwidth Meter Configuration</h1>
-        <div id="config-container"></div>
-    </div>
-    <div id="message" class="message"></div>
 
-    <script>
-        const fieldSections = [
-            {
-                title: 'Testing',
-                isTesting: true,
-                help: 'Enable to simulate bandwidth utilization for testing purposes',
-                fields: [
-                    { name: 'test_tx', label: 'TX (Upload)', type: 'checkbox' },
-                    { name: 'test_tx_percent', label: 'TX Utilization', type: 'range', min: '0', max: '100', step: '1' },
-                    { name: 'test_rx', label: 'RX (Download)', type: 'checkbox' },
-                    { name: 'test_rx_percent', label: 'RX Utilization', type: 'range', min: '0', max: '100', step: '1' },
-                ]
-            },
-            {
-                title: 'Networking',
-                fields: [
-                    { name: 'interface', label: 'Network Interface', type: 'text', help: 'Interface to monitor, e.g. en0. Multiple interfaces can be comma-separated: en0,en1 (requires restart)' },
-                    { name: 'max_gbps', label: 'Max Bandwidth (Gbps)', type: 'number', step: '0.1', help: 'Maximum bandwidth in Gbps for visualization scaling' },
-                    { name: 'wled_ip', label: 'WLED IP Address', type: 'text', help: 'WLED device IP or hostname (requires restart)' },
-                    { name: 'httpd_ip', label: 'HTTP Server IP', type: 'text', help: 'IP address to listen on (0.0.0.0 for all)' },
-                    { name: 'httpd_port', label: 'HTTP Server Port', type: 'number', step: '1', help: 'Port for HTTP server' },
-                ]
-            },
-            {
-                title: 'LED Layout',
-                fields: [
-                    { name: 'direction', label: 'Fill Direction', type: 'select', options: ['mirrored', 'opposing', 'left', 'right'], help: 'How LEDs fill across the strip' },
-                    { name: 'swap', label: 'Swap TX/RX Halves', type: 'checkbox', help: 'Swap which half shows TX vs RX' },
-                    { name: 'total_leds', label: 'Total LEDs', type: 'number', step: '1', help: 'Total number of LEDs in strip' },
-                    { name: 'rx_split_percent', label: 'RX/TX LED Split', type: 'range', min: '0', max: '100', step: '1', help: 'Percentage of LEDs allocated to RX. TX gets the remainder. (50 = 50/50, 70 = 70/30)' },
-                ]
-            },
-            {
-                title: 'Color Settings',
-                fields: [
-                    { name: 'color', label: 'Default Color', type: 'textarea', help: 'Hex color or gradient (e.g., FF0000,00FF00,0000FF)' },
-                    { name: 'tx_color', label: 'TX (Upload) Color', type: 'textarea', help: 'Overrides default color for TX. Leave empty to use default.' },
-                    { name: 'rx_color', label: 'RX (Download) Color', type: 'textarea', help: 'Overrides default color for RX. Leave empty to use default.' },
-                    { name: 'use_gradient', label: 'Use Gradient Blending', type: 'checkbox', help: 'Smooth gradients vs hard color segments' },
-                    { name: 'interpolation', label: 'Gradient Interpolation', type: 'select', options: ['linear', 'basis', 'catmullrom'], help: 'Gradient interpolation algorithm' },
-                ]
-            },
-            {
-                title: 'Animation Settings',
-                fields: [
-                    { name: 'animation_speed', label: 'Animation Speed', type: 'number', step: '0.1', help: 'Speed of gradient animation (0 = disabled)' },
-                    { name: 'scale_animation_speed', label: 'Scale Animation with Bandwidth', type: 'checkbox', help: 'Animation speed scales with bandwidth utilization' },
-                    { name: 'tx_animation_direction', label: 'TX Animation Direction', type: 'radio', options: ['left', 'right'], help: 'Direction TX (upload) animation moves' },
-                    { name: 'rx_animation_direction', label: 'RX Animation Direction', type: 'radio', options: ['left', 'right'], help: 'Direction RX (download) animation moves' },
-                    { name: 'interpolation_time_ms', label: 'Interpolation Time (ms)', type: 'number', step: '10', help: 'Time in milliseconds to smoothly transition between bandwidth readings' },
-                    { name: 'strobe_on_max', label: 'Strobe at Max Bandwidth', type: 'checkbox', help: 'Flash entire RX or TX segment when bandwidth exceeds maximum' },
-                    { name: 'strobe_rate_hz', label: 'Strobe Rate (Hz)', type: 'number', step: '0.1', help: 'Strobe frequency in Hz (flashes per second). Default: 3.0 Hz' },
-                    { name: 'strobe_duration_ms', label: 'Strobe Duration (ms)', type: 'number', step: '1', help: 'Duration of strobe effect in milliseconds. Cannot exceed cycle time (e.g., 3 Hz = 333ms max)' },
-                    { name: 'strobe_color', label: 'Strobe Color (Hex)', type: 'text', help: 'Hex color to display during strobe "off" phase. Default: 000000 (black/off)' },
-                    { name: 'fps', label: 'Frame Rate (FPS)', type: 'number', step: '1', help: 'Rendering frame rate. Try 30, 60, 120, or 144' },
-                ]
-            },
-        ];
+        if let Some(matrix) = layout.matrix {
+            self.render_matrix(&mut frame, target, &matrix, swap, inputs);
+            return frame;
+        }
 
-        let config = {};
-        let pollingInterval = null;
+        // History mode replaces the bar with a scrolling time series
+        if let Some(history) = history.as_ref().filter(|h| h.enabled) {
+            self.render_history(&mut frame, history, layout, tx_leds_available, rx_leds_available, inputs);
+            self.render_sparkles(&mut frame, layout, tx_leds_available, rx_leds_available, inputs);
+            self.render_status(&mut frame, layout, inputs);
+            return frame;
+        }
 
-        async function loadConfig() {
-            try {
-                const res = await fetch('/api/config');
-                const newConfig = await res.json();
+        // The leading LED is drawn like the rest of the bar and dimmed to its partial fill afterwards
+        let tx_lit = tx_leds + usize::from(tx_partial > 0.0);
+        let rx_lit = rx_leds + usize::from(rx_partial > 0.0);
+        let (tx_positions, rx_positions) = self.calculate_led_positions(tx_lit, rx_lit, layout);
 
-                // Check if config actually changed
-                const configChanged = JSON.stringify(newConfig) !== JSON.stringify(config);
+        // Where an LED sits along its bar for the gradient, 0.0-1.0
+        let gradient_position = |index: usize, led_pos: usize, available: usize| {
+            if layout.segments.is_some() {
+                index as f64 / available.max(1) as f64
+            } else {
+                (led_pos % leds_per_direction) as f64 / leds_per_direction as f64
+            }
+        };
 
-                config = newConfig;
+        // In zone mode the whole bar takes the color of the zone its fill level is in
+        let (tx_zone_color, rx_zone_color) = if color_zones {
+            (
+                zone_color(&self.zones, scale.fraction(tx_kbps, max_bandwidth_kbps)),
+                zone_color(&self.zones, scale.fraction(rx_kbps, max_bandwidth_kbps)),
+            )
+        } else {
+            (None, None)
+        };
 
-                // Only re-render if config changed or this is the initial load
-                if (configChanged || pollingInterval === null) {
-                    renderConfig();
+        // Render TX positions
+        if tx_strobe_active {
+            // Strobe mode: fill all TX LEDs with strobe color
+            for &led_pos in tx_positions.iter() {
+                let offset = led_pos * 3;
+                frame[offset] = strobe_color.r;
+                frame[offset + 1] = strobe_color.g;
+                frame[offset + 2] = strobe_color.b;
+            }
+        } else if let Some(bands) = interface_bands {
+            self.render_bands(&mut frame, &tx_positions, &bands.tx_shares, &bands.colors);
+        } else if let Some(color) = tx_zone_color {
+            for &led_pos in &tx_positions {
+                let offset = led_pos * 3;
+                frame[offset] = color.r;
+                frame[offset + 1] = color.g;
+                frame[offset + 2] = color.b;
+            }
+        } else if !use_gradient && self.tx_colors.len() >= 2 && !tx_positions.is_empty() {
+            let num_leds = tx_positions.len() as f64;
+            let pattern_offset = if tx_animation_direction == "right" {
+                -target.tx_animation_offset * num_leds
+            } else {
+                target.tx_animation_offset * num_leds
+            };
+            let segment_size = num_leds / self.tx_colors.len() as f64;
+
+            for (i, &led_pos) in tx_positions.iter().enumerate() {
+                let pattern_pos = ((i as f64 + pattern_offset) % num_leds + num_leds) % num_leds;
+                let segment_idx = (pattern_pos / segment_size).floor() as usize % self.tx_colors.len();
+                let color = &self.tx_colors[segment_idx];
+
+                let offset = led_pos * 3;
+                frame[offset] = color.r;
+                frame[offset + 1] = color.g;
+                frame[offset + 2] = color.b;
+            }
+        } else if let Some(ref tx_gradient) = self.tx_gradient {
+            for (i, &led_pos) in tx_positions.iter().enumerate() {
+                // Map LED position to gradient position (0.0-1.0 across the full TX half)
+                let pos_ratio = gradient_position(i, led_pos, tx_leds_available);
+                let animated_pos = if tx_animation_direction == "right" {
+                    (1.0 + pos_ratio - target.tx_animation_offset) % 1.0
+                } else {
+                    (pos_ratio + target.tx_animation_offset) % 1.0
+                };
+
+                let rgba = tx_gradient.at(animated_pos).to_rgba8();
+                let offset = led_pos * 3;
+                frame[offset] = rgba[0];
+                frame[offset + 1] = rgba[1];
+                frame[offset + 2] = rgba[2];
+            }
+        } else {
+            for &led_pos in &tx_positions {
+                let offset = led_pos * 3;
+                frame[offset] = self.tx_solid_color.r;
+                frame[offset + 1] = self.tx_solid_color.g;
+                frame[offset + 2] = self.tx_solid_color.b;
+            }
+        }
+
+        // Render RX positions
+        if rx_strobe_active {
+            // Strobe mode: fill all RX LEDs with strobe color
+            for &led_pos in rx_positions.iter() {
+                let offset = led_pos * 3;
+                frame[offset] = strobe_color.r;
+                frame[offset + 1] = strobe_color.g;
+                frame[offset + 2] = strobe_color.b;
+            }
+        } else if let Some(bands) = interface_bands {
+            self.render_bands(&mut frame, &rx_positions, &bands.rx_shares, &bands.colors);
+        } else if let Some(color) = rx_zone_color {
+            for &led_pos in &rx_positions {
+                let offset = led_pos * 3;
+                frame[offset] = color.r;
+                frame[offset + 1] = color.g;
+                frame[offset + 2] = color.b;
+            }
+        } else if !use_gradient && self.rx_colors.len() >= 2 && !rx_positions.is_empty() {
+            let num_leds = rx_positions.len() as f64;
+            let pattern_offset = if rx_animation_direction == "right" {
+                -target.rx_animation_offset * num_leds
+            } else {
+                target.rx_animation_offset * num_leds
+            };
+            let segment_size = num_leds / self.rx_colors.len() as f64;
+
+            for (i, &led_pos) in rx_positions.iter().enumerate() {
+                let pattern_pos = ((i as f64 + pattern_offset) % num_leds + num_leds) % num_leds;
+                let segment_idx = (pattern_pos / segment_size).floor() as usize % self.rx_colors.len();
+                let color = &self.rx_colors[segment_idx];
+
+                let offset = led_pos * 3;
+                frame[offset] = color.r;
+                frame[offset + 1] = color.g;
+                frame[offset + 2] = color.b;
+            }
+        } else if let Some(ref rx_gradient) = self.rx_gradient {
+            for (i, &led_pos) in rx_positions.iter().enumerate() {
+                // Map LED position to gradient position (0.0-1.0 across the full RX half)
+                let pos_ratio = gradient_position(i, led_pos, rx_leds_available);
+                let animated_pos = if rx_animation_direction == "right" {
+                    (1.0 + pos_ratio - target.rx_animation_offset) % 1.0
+                } else {
+                    (pos_ratio + target.rx_animation_offset) % 1.0
+                };
+
+                let rgba = rx_gradient.at(animated_pos).to_rgba8();
+                let offset = led_pos * 3;
+                frame[offset] = rgba[0];
+                frame[offset + 1] = rgba[1];
+                frame[offset + 2] = rgba[2];
+            }
+        } else {
+            for &led_pos in &rx_positions {
+                let offset = led_pos * 3;
+                frame[offset] = self.rx_solid_color.r;
+                frame[offset + 1] = self.rx_solid_color.g;
+                frame[offset + 2] = self.rx_solid_color.b;
+            }
+        }
+
+        for (positions, partial) in [(&tx_positions, tx_partial), (&rx_positions, rx_partial)] {
+            if let Some(&led_pos) = positions.last().filter(|_| partial > 0.0) {
+                for channel in &mut frame[led_pos * 3..led_pos * 3 + 3] {
+                    *channel = (*channel as f64 * partial).round() as u8;
+                }
+            }
+        }
+
+        // Peak-hold markers go on top of the fill, at the last LED a bar of peak height would light
+        if let Some(peaks) = peak_markers {
+            let tx_peak_leds = ((peaks.tx_fraction * tx_leds_available as f64) as usize).min(tx_leds_available);
+            let rx_peak_leds = ((peaks.rx_fraction * rx_leds_available as f64) as usize).min(rx_leds_available);
+            let (tx_peak_positions, rx_peak_positions) = self.calculate_led_positions(tx_peak_leds, rx_peak_leds, layout);
+
+            for (positions, color) in [(tx_peak_positions, peaks.tx_color), (rx_peak_positions, peaks.rx_color)] {
+                if let Some(&led_pos) = positions.last() {
+                    let offset = led_pos * 3;
+                    frame[offset] = color.r;
+                    frame[offset + 1] = color.g;
+                    frame[offset + 2] = color.b;
+                }
+            }
+        }
+
+        self.render_sparkles(&mut frame, layout, tx_leds_available, rx_leds_available, inputs);
+        self.render_status(&mut frame, layout, inputs);
+        frame
+    }
+
+    // Stacked interfaces: each one lights its share of the bar, the first interface where the bar starts
+    fn render_bands(&self, frame: &mut [u8], positions: &[usize], shares: &[f64], colors: &[Rgb]) {
+        let total: f64 = shares.iter().sum();
+        if total <= 0.0 || colors.is_empty() {
+            return;
+        }
+
+        let mut band = 0;
+        let mut band_end = shares[0] / total;
+        for (i, &led_pos) in positions.iter().enumerate() {
+            let position = (i as f64 + 0.5) / positions.len() as f64;
+            while position >= band_end && band + 1 < shares.len() {
+                band += 1;
+                band_end += shares[band] / total;
+            }
+
+            let color = colors[band % colors.len()];
+            let offset = led_pos * 3;
+            frame[offset] = color.r;
+            frame[offset + 1] = color.g;
+            frame[offset + 2] = color.b;
+        }
+    }
+
+    // Error sparkles fade from the sparkle color back to whatever the bar shows underneath
+    fn render_sparkles(&self, frame: &mut [u8], layout: &TargetLayout, tx_leds_available: usize, rx_leds_available: usize, inputs: &FrameInputs) {
+        if inputs.sparkles.is_empty() {
+            return;
+        }
+        let (tx_positions, rx_positions) = self.calculate_led_positions(tx_leds_available, rx_leds_available, layout);
+        let color = inputs.sparkle_color;
+
+        for sparkle in &inputs.sparkles {
+            let positions = if sparkle.is_tx { &tx_positions } else { &rx_positions };
+            if positions.is_empty() {
+                continue;
+            }
+            let led_pos = positions[((sparkle.position * positions.len() as f64) as usize).min(positions.len() - 1)];
+            let brightness = sparkle.brightness();
+            for (channel, target) in frame[led_pos * 3..led_pos * 3 + 3].iter_mut().zip([color.r, color.g, color.b]) {
+                *channel = (*channel as f64 + (target as f64 - *channel as f64) * brightness).round() as u8;
+            }
+        }
+    }
+
+    // Status LEDs of a segment map show the busier direction as a severity color, or strobe at max
+    fn render_status(&self, frame: &mut [u8], layout: &TargetLayout, inputs: &FrameInputs) {
+        let Some(ref segments) = layout.segments else {
+            return;
+        };
+        let fraction = inputs
+            .scale
+            .fraction(inputs.tx_kbps.max(inputs.rx_kbps), inputs.max_bandwidth_kbps);
+        let color = if inputs.strobe_phase_active && fraction >= 1.0 {
+            inputs.strobe_color
+        } else {
+            zone_color(&self.zones, fraction).unwrap_or_else(|| Rgb::from_hue(120.0 * (1.0 - fraction)))
+        };
+
+        for &led_pos in &segments.status {
+            let offset = led_pos * 3;
+            frame[offset] = color.r;
+            frame[offset + 1] = color.g;
+            frame[offset + 2] = color.b;
+        }
+    }
+
+    // Breathe fills every LED the layout uses (unused segments stay dark), blink only the end of each bar.
+    // A matrix has no bar ends to speak of, so it blinks its last LED
+    fn render_no_data(
+        &self,
+        frame: &mut [u8],
+        layout: &TargetLayout,
+        no_data: &NoDataConfig,
+        tx_leds_available: usize,
+        rx_leds_available: usize,
+    ) {
+        let positions: Vec<usize> = match (no_data.pattern, layout.segments.as_ref()) {
+            (NoDataPattern::Breathe, Some(segments)) => {
+                segments.tx.iter().chain(&segments.rx).chain(&segments.status).copied().collect()
+            }
+            (NoDataPattern::Breathe, None) => (0..layout.total_leds).collect(),
+            (NoDataPattern::Blink, _) if layout.matrix.is_some() => layout.total_leds.checked_sub(1).into_iter().collect(),
+            (NoDataPattern::Blink, _) => {
+                let (tx_positions, rx_positions) = self.calculate_led_positions(tx_leds_available, rx_leds_available, layout);
+                tx_positions.last().into_iter().chain(rx_positions.last()).copied().collect()
+            }
+        };
+        let color = no_data.color;
+        for led_pos in positions {
+            let offset = led_pos * 3;
+            frame[offset] = color.r;
+            frame[offset + 1] = color.g;
+            frame[offset + 2] = color.b;
+        }
+    }
+
+    // Each LED group along a half shows one past sample, newest where the bar would start filling
+    fn render_history(
+        &self,
+        frame: &mut [u8],
+        history: &HistoryFrame,
+        layout: &TargetLayout,
+        tx_leds_available: usize,
+        rx_leds_available: usize,
+        inputs: &FrameInputs,
+    ) {
+        let (tx_positions, rx_positions) = self.calculate_led_positions(tx_leds_available, rx_leds_available, layout);
+
+        for (positions, base, is_tx) in [(tx_positions, self.tx_solid_color, true), (rx_positions, self.rx_solid_color, false)] {
+            for (i, &led_pos) in positions.iter().enumerate() {
+                let Some(sample) = history.samples.get(i / history.leds_per_sample) else {
+                    break;
+                };
+                let kbps = if is_tx { sample.tx_kbps } else { sample.rx_kbps };
+                let fraction = inputs.scale.fraction(kbps, inputs.max_bandwidth_kbps);
+
+                let color = match history.style {
+                    HistoryStyle::Brightness => base.scaled(fraction),
+                    // Idle samples stay dark so the strip is not lit blue around the clock
+                    HistoryStyle::Hue if fraction <= 0.0 => continue,
+                    HistoryStyle::Hue => Rgb::from_hue(240.0 * (1.0 - fraction)),
+                };
+
+                let offset = led_pos * 3;
+                frame[offset] = color.r;
+                frame[offset + 1] = color.g;
+                frame[offset + 2] = color.b;
+            }
+        }
+    }
+
+    // Color of a bar pixel at pos_ratio (0.0-1.0 along the bar), following the same gradient animation as strips
+    fn bar_color(&self, is_tx: bool, pos_ratio: f64, target: &RenderTarget, inputs: &FrameInputs) -> Rgb {
+        let (gradient, colors, solid_color, offset, animation_direction) = if is_tx {
+            (&self.tx_gradient, &self.tx_colors, self.tx_solid_color, target.tx_animation_offset, &inputs.tx_animation_direction)
+        } else {
+            (&self.rx_gradient, &self.rx_colors, self.rx_solid_color, target.rx_animation_offset, &inputs.rx_animation_direction)
+        };
+
+        if !inputs.use_gradient && colors.len() >= 2 {
+            colors[((pos_ratio * colors.len() as f64) as usize).min(colors.len() - 1)]
+        } else if let Some(gradient) = gradient {
+            let animated_pos = if animation_direction == "right" {
+                (1.0 + pos_ratio - offset) % 1.0
+            } else {
+                (pos_ratio + offset) % 1.0
+            };
+            let rgba = gradient.at(animated_pos).to_rgba8();
+            Rgb { r: rgba[0], g: rgba[1], b: rgba[2] }
+        } else {
+            solid_color
+        }
+    }
+
+    // 2D panels show bar graphs, scrolling history columns or a spectrum-like waterfall
+    fn render_matrix(&self, frame: &mut [u8], target: &RenderTarget, matrix: &MatrixLayout, swap: bool, inputs: &FrameInputs) {
+        let (width, height) = matrix.display_size();
+        let mut set = |x: usize, y: usize, color: Rgb| {
+            let offset = matrix.index(x, y) * 3;
+            frame[offset] = color.r;
+            frame[offset + 1] = color.g;
+            frame[offset + 2] = color.b;
+        };
+        let fraction = |kbps: f64| inputs.scale.fraction(kbps, inputs.max_bandwidth_kbps);
+
+        // RX takes the left (or lower) part and TX the right (or upper), swap exchanges them
+        let left_width = width.div_ceil(2);
+        let (left_is_tx, right_is_tx) = if swap { (true, false) } else { (false, true) };
+
+        match matrix.mode {
+            MatrixMode::Bars => {
+                for (is_tx, columns) in [(left_is_tx, 0..left_width), (right_is_tx, left_width..width)] {
+                    let (kbps, peak) = if is_tx {
+                        (inputs.tx_kbps, inputs.peak_markers.map(|p| (p.tx_fraction, p.tx_color)))
+                    } else {
+                        (inputs.rx_kbps, inputs.peak_markers.map(|p| (p.rx_fraction, p.rx_color)))
+                    };
+                    let level = fraction(kbps);
+                    let exact = (level * height as f64).min(height as f64);
+                    let rows = exact as usize;
+                    let strobe = inputs.strobe_phase_active && rows >= height;
+                    let zone = if inputs.color_zones { zone_color(&self.zones, level) } else { None };
+
+                    // Bars fill from the bottom row up, the top row dimmed to its partial fill
+                    for row in 0..exact.ceil() as usize {
+                        let color = if strobe {
+                            inputs.strobe_color
+                        } else {
+                            zone.unwrap_or_else(|| self.bar_color(is_tx, row as f64 / height as f64, target, inputs))
+                        };
+                        let color = if row < rows { color } else { color.scaled(exact - rows as f64) };
+                        for x in columns.clone() {
+                            set(x, height - 1 - row, color);
+                        }
+                    }
+
+                    if let Some((peak_fraction, peak_color)) = peak {
+                        let peak_rows = ((peak_fraction * height as f64) as usize).min(height);
+                        if peak_rows > 0 {
+                            for x in columns.clone() {
+                                set(x, height - peak_rows, peak_color);
+                            }
+                        }
+                    }
+                }
+            }
+            MatrixMode::History | MatrixMode::Spectrum => {
+                let Some(history) = inputs.history.as_ref() else {
+                    return;
+                };
+                let sample_color = |is_tx: bool, level: f64| match history.style {
+                    HistoryStyle::Hue => Rgb::from_hue(240.0 * (1.0 - level)),
+                    HistoryStyle::Brightness if is_tx => self.tx_solid_color,
+                    HistoryStyle::Brightness => self.rx_solid_color,
+                };
+
+                if matrix.mode == MatrixMode::History {
+                    // Newest column on the right, TX rising from the middle and RX falling from it
+                    let upper_height = height / 2;
+                    let lower_height = height - upper_height;
+                    let (upper_is_tx, lower_is_tx) = if swap { (false, true) } else { (true, false) };
+                    for x in 0..width {
+                        let Some(sample) = history.samples.get((width - 1 - x) / history.leds_per_sample) else {
+                            break;
+                        };
+                        for (is_tx, upper) in [(upper_is_tx, true), (lower_is_tx, false)] {
+                            let level = fraction(if is_tx { sample.tx_kbps } else { sample.rx_kbps });
+                            let span = if upper { upper_height } else { lower_height };
+                            let rows = ((level * span as f64).round() as usize).min(span);
+                            for row in 0..rows {
+                                let y = if upper { upper_height - 1 - row } else { upper_height + row };
+                                set(x, y, sample_color(is_tx, level));
+                            }
+                        }
+                    }
+                } else {
+                    // Newest row on top, RX extending left of the center and TX right of it
+                    let right_width = width - left_width;
+                    for y in 0..height {
+                        let Some(sample) = history.samples.get(y / history.leds_per_sample) else {
+                            break;
+                        };
+                        for (is_tx, left) in [(left_is_tx, true), (right_is_tx, false)] {
+                            let level = fraction(if is_tx { sample.tx_kbps } else { sample.rx_kbps });
+                            let span = if left { left_width } else { right_width };
+                            let columns = ((level * span as f64).round() as usize).min(span);
+                            for column in 0..columns {
+                                let x = if left { left_width - 1 - column } else { left_width + column };
+                                set(x, y, sample_color(is_tx, level));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Main render loop that runs at configurable FPS
+    fn run(mut self) {
+        let mut last_frame = Instant::now();
+
+        loop {
+            // Check for shutdown signal
+            if self.shutdown.load(Ordering::Relaxed) {
+                break;
+            }
+
+            // Read FPS from shared state
+            let fps = {
+                let state = self.shared_state.lock().unwrap();
+                state.fps
+            };
+
+            // Calculate frame duration based on FPS
+            let frame_duration_micros = (1_000_000.0 / fps) as u64;
+            let frame_duration = Duration::from_micros(frame_duration_micros);
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(last_frame);
+
+            if elapsed >= frame_duration {
+                let delta_seconds = elapsed.as_secs_f64();
+                last_frame = now;
+
+                // Render frame - this is the only thing happening in this thread
+                let _ = self.render_frame(delta_seconds);
+            }
+
+            // Tiny sleep to avoid spinning CPU at 100%
+            thread::sleep(Duration::from_micros(100));
+        }
+    }
+}
+
+// Detect OS type (Darwin/Linux) via uname
+async fn detect_os(host: Option<&String>) -> Result<String> {
+    let output = if let Some(host) = host {
+        Command::new("ssh")
+            .arg(host)
+            .arg("uname")
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .await?
+    } else {
+        Command::new("uname")
+            .output()
+            .await?
+    };
+
+    let os_name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(os_name)
+}
+
+// Link speed in Mbps of the configured interfaces (summed), locally or on a remote host.
+// Linux reports it in /sys/class/net/<if>/speed, macOS only in the ifconfig media line.
+async fn detect_link_speed_mbps(host: Option<&String>, interface: &str) -> Option<f64> {
+    let interfaces = parse_interface_list(interface);
+    // The names end up in the remote shell script, so anything a real interface name can't contain is refused
+    if !interfaces.iter().all(|iface| is_valid_interface_name(iface)) {
+        return None;
+    }
+
+    // One sysfs speed or ifconfig media line per interface
+    let mut values = Vec::new();
+    if let Some(host) = host {
+        let script = interfaces
+            .iter()
+            .map(|iface| {
+                format!(
+                    "echo \"{0} $(cat /sys/class/net/{0}/speed 2>/dev/null || ifconfig {0} 2>/dev/null | grep -i 'media:')\"",
+                    iface
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        let output = Command::new("ssh")
+            .arg(host)
+            .arg(&script)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::null())
+            .output()
+            .await
+            .ok()?;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            if let Some((_, value)) = line.split_once(' ') {
+                values.push(value.trim().to_string());
+            }
+        }
+    } else {
+        // Locally no shell is involved: sysfs is read directly and ifconfig gets the name as an argument
+        for iface in &interfaces {
+            if let Ok(speed) = std::fs::read_to_string(format!("/sys/class/net/{}/speed", iface)) {
+                values.push(speed.trim().to_string());
+                continue;
+            }
+            let Ok(output) = Command::new("ifconfig").arg(iface).stderr(Stdio::null()).output().await else {
+                continue;
+            };
+            let media = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .find(|line| line.to_lowercase().contains("media:"))
+                .map(|line| line.trim().to_string());
+            values.extend(media);
+        }
+    }
+
+    total_link_speed_mbps(&values)
+}
+
+// Bonded interfaces add up, members that are down or unknown count as nothing
+fn total_link_speed_mbps(values: &[String]) -> Option<f64> {
+    let total_mbps: f64 = values.iter().filter_map(|value| parse_link_speed_mbps(value)).sum();
+    if total_mbps > 0.0 {
+        Some(total_mbps)
+    } else {
+        None
+    }
+}
+
+// A sysfs speed in Mbps or an ifconfig media line, None when the link is down or its speed unknown
+fn parse_link_speed_mbps(value: &str) -> Option<f64> {
+    let mbps = match value.trim().parse::<f64>() {
+        // sysfs reports -1 for links that are down or don't know their speed,
+        // older kernels and some drivers print that as 4294967295 or 65535
+        Ok(mbps) if mbps == u32::MAX as f64 || mbps == u16::MAX as f64 => None,
+        Ok(mbps) => Some(mbps),
+        Err(_) => parse_media_speed_mbps(value),
+    };
+    mbps.filter(|&mbps| mbps.is_finite() && mbps > 0.0)
+}
+
+// Parse a macOS/BSD media line such as "media: autoselect (1000baseT <full-duplex>)" or "(10Gbase-T)"
+fn parse_media_speed_mbps(media: &str) -> Option<f64> {
+    let lower = media.to_lowercase();
+    let base = lower.find("base")?;
+    let prefix = &lower[..base];
+    let start = prefix
+        .rfind(|c: char| !(c.is_ascii_digit() || c == '.' || c == 'g'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let speed = &prefix[start..];
+
+    match speed.strip_suffix('g') {
+        Some(gbps) => gbps.parse::<f64>().ok().map(|g| g * 1000.0),
+        None => speed.parse::<f64>().ok(),
+    }
+}
+
+// A single bandwidth reading, timestamped when it was sampled
+#[derive(Debug, Clone)]
+struct BandwidthSample {
+    timestamp: Instant,
+    rx_kbps: f64,
+    tx_kbps: f64,
+    packets: PacketStats,
+    // What each interface contributed to the totals above; empty when the source can't tell (netstat -w)
+    interfaces: Vec<InterfaceReading>,
+}
+
+// Packet rates and error/drop events over one sample; zero for sources that only count bytes (SNMP, Prometheus)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+struct PacketStats {
+    rx_pps: f64,
+    tx_pps: f64,
+    rx_errors: u64,
+    tx_errors: u64,
+    rx_drops: u64,
+    tx_drops: u64,
+}
+
+impl PacketStats {
+    fn accumulate(&mut self, other: &PacketStats) {
+        self.rx_pps += other.rx_pps;
+        self.tx_pps += other.tx_pps;
+        self.rx_errors += other.rx_errors;
+        self.tx_errors += other.tx_errors;
+        self.rx_drops += other.rx_drops;
+        self.tx_drops += other.tx_drops;
+    }
+}
+
+// One interface's part of a sample
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct InterfaceReading {
+    name: String,
+    rx_kbps: f64,
+    tx_kbps: f64,
+    #[serde(flatten)]
+    packets: PacketStats,
+}
+
+// Sum the readings of several interfaces from one sample window, ordered as in the interface setting
+fn combine_samples(mut samples: Vec<BandwidthSample>, order: &[String]) -> Option<BandwidthSample> {
+    let position = |sample: &BandwidthSample| {
+        sample
+            .interfaces
+            .first()
+            .and_then(|reading| order.iter().position(|name| *name == reading.name))
+            .unwrap_or(order.len())
+    };
+    samples.sort_by_key(position);
+
+    let mut samples = samples.into_iter();
+    let mut total = samples.next()?;
+    for sample in samples {
+        total.timestamp = total.timestamp.max(sample.timestamp);
+        total.rx_kbps += sample.rx_kbps;
+        total.tx_kbps += sample.tx_kbps;
+        total.packets.accumulate(&sample.packets);
+        total.interfaces.extend(sample.interfaces);
+    }
+    Some(total)
+}
+
+// Describes where samples come from (shown in the TUI log)
+#[derive(Debug, Clone)]
+struct SourceMetadata {
+    kind: &'static str,
+    description: String,
+}
+
+type SampleFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<BandwidthSample>>> + Send + 'a>>;
+type ConnectFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+// A stream of bandwidth samples - netstat, /proc/net/dev, SSH or anything else
+// The main loop only ever sees BandwidthSample values, so new backends don't touch it
+trait BandwidthSource: Send {
+    fn metadata(&self) -> SourceMetadata;
+
+    // Wait for the next sample; Ok(None) means the source has ended
+    fn next_sample(&mut self) -> SampleFuture<'_>;
+
+    // Counter wraps and resets seen since the last call, for the TUI log
+    fn take_notices(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Fastest rate the link can carry, so a counter reset isn't mistaken for a wrap
+    fn set_link_limit_kbps(&mut self, _kbps: f64) {}
+
+    // Whether reconnect() can bring the source back after it ended or failed
+    fn can_reconnect(&self) -> bool {
+        false
+    }
+
+    // Start over after the source ended or failed
+    fn reconnect(&mut self) -> ConnectFuture<'_> {
+        Box::pin(async { Err(anyhow::anyhow!("Source cannot reconnect")) })
+    }
+}
+
+// Pick a bandwidth source based on config, args and OS
+async fn create_bandwidth_source(args: &Args, config: &BandwidthConfig) -> Result<Box<dyn BandwidthSource>> {
+    if config.source.eq_ignore_ascii_case("snmp") {
+        println!("Connecting to SNMP agent {}:{}...", config.snmp_host, config.snmp_port);
+        let source = SnmpSource::connect(config).await?;
+        Ok(Box::new(source))
+    } else if config.source.eq_ignore_ascii_case("prometheus") {
+        let source = PrometheusSource::new(config)?;
+        Ok(Box::new(source))
+    } else if let Err(e) = validate_interface_list(&config.interface) {
+        Err(e)
+    } else if let Some(host) = &args.host {
+        // For remote hosts, use a single SSH connection that auto-detects OS and runs appropriate command
+        let mut source = ProcessSource::ssh(host, &config.interface, config.sample_interval_ms).await?;
+
+        // Wait for first line of output to ensure connection succeeded
+        println!("Waiting for connection to establish...");
+        source.wait_for_first_line().await?;
+        println!("Connection established!");
+
+        Ok(Box::new(source))
+    } else {
+        // Local monitoring - detect OS
+        let os = detect_os(None).await?;
+
+        if os == "Darwin" {
+            // macOS: use netstat
+            let source = ProcessSource::netstat(&config.interface, config.sample_interval_ms).await?;
+            Ok(Box::new(source))
+        } else {
+            // Linux: read /proc/net/dev directly, no child process needed
+            let source = ProcnetSource::new(&config.interface, config.sample_interval_ms)?;
+            Ok(Box::new(source))
+        }
+    }
+}
+
+// What the source task tells the main thread
+enum SourceEvent {
+    Sample(BandwidthSample),
+    // The source stopped delivering samples, with the reason
+    Lost(String),
+    // A lost source is delivering samples again
+    Restored,
+}
+
+// Reconnect delays double after every failed attempt up to the cap
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+// Pull samples from a source and hand them to the main thread until either side goes away.
+// A source that ends or fails is reported lost and, if it can, reconnected with exponential backoff
+async fn run_bandwidth_source(
+    mut source: Box<dyn BandwidthSource>,
+    bandwidth_tx: mpsc::Sender<SourceEvent>,
+    log_tx: mpsc::Sender<String>,
+) {
+    loop {
+        let reason = match source.next_sample().await {
+            Ok(Some(sample)) => {
+                for notice in source.take_notices() {
+                    let _ = log_tx.send(notice);
+                }
+                if bandwidth_tx.send(SourceEvent::Sample(sample)).is_err() {
+                    break; // Main thread dropped receiver, time to exit
+                }
+                continue;
+            }
+            Ok(None) => "output ended".to_string(),
+            Err(e) => e.to_string(),
+        };
+        if bandwidth_tx.send(SourceEvent::Lost(reason)).is_err() {
+            break;
+        }
+
+        if !source.can_reconnect() {
+            // Nothing to retry, the strip stays in the lost state until the meter is restarted
+            let _ = log_tx.send(format!("{} source ended and can't reconnect, restart to resume", source.metadata().kind));
+            return;
+        }
+        let mut delay = RECONNECT_INITIAL_DELAY;
+        loop {
+            let _ = log_tx.send(format!("Reconnecting in {}s...", delay.as_secs()));
+            tokio::time::sleep(delay).await;
+            match source.reconnect().await {
+                Ok(()) => break,
+                Err(e) => {
+                    let _ = log_tx.send(format!("Reconnect failed: {}", e));
+                    delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+                }
+            }
+        }
+        if bandwidth_tx.send(SourceEvent::Restored).is_err() {
+            break;
+        }
+    }
+}
+
+// Interface names as Linux and macOS allow them in practice, safe to pass to a shell unquoted
+fn is_valid_interface_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "_.:@-".contains(c))
+}
+
+// The netstat and SSH monitors put interface names into shell scripts, so every name has to be plain
+fn validate_interface_list(interface: &str) -> Result<()> {
+    match parse_interface_list(interface).into_iter().find(|iface| !is_valid_interface_name(iface)) {
+        Some(iface) => anyhow::bail!("Invalid interface name: {:?}", iface),
+        None => Ok(()),
+    }
+}
+
+// Split a comma-separated interface setting into individual interface names
+fn parse_interface_list(interface: &str) -> Vec<String> {
+    interface
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+// Format a sample interval for the shell `sleep` command (fractional seconds)
+fn sleep_secs(sample_interval_ms: f64) -> String {
+    format!("{:.3}", sample_interval_ms.max(10.0) / 1000.0)
+}
+
+// macOS netstat command for the configured sample interval
+// `netstat -w` only accepts whole seconds, so other intervals poll the cumulative counters instead
+fn netstat_command(interface: &str, sample_interval_ms: f64) -> String {
+    let interval_ms = sample_interval_ms.max(10.0);
+    if interval_ms >= 1000.0 && interval_ms % 1000.0 == 0.0 {
+        format!("netstat -w {} -I {}", (interval_ms / 1000.0) as u64, interface)
+    } else {
+        format!(
+            "while true; do netstat -ibn -I {}; sleep {}; done",
+            interface,
+            sleep_secs(interval_ms)
+        )
+    }
+}
+
+// Remote monitoring with OS auto-detection in a single SSH session
+async fn spawn_remote_monitor(host: &String, interface: &str, sample_interval_ms: f64, batch_mode: bool) -> Result<tokio::process::Child> {
+    // Parse comma-separated interfaces for egrep pattern (Linux)
+    let interfaces: Vec<&str> = interface.split(',').map(|s| s.trim()).collect();
+    let egrep_pattern = interfaces.join("|");
+
+    // Create a script that detects OS and runs appropriate monitoring command
+    // This all runs in ONE SSH session, so only ONE password prompt
+    let script = format!(
+        r#"
+OS=$(uname)
+if [ "$OS" = "Darwin" ]; then
+    # macOS
+    {}
+else
+    # Linux: each batch starts with the remote clock, so SSH jitter doesn't skew the rates
+    while true; do date +@%s.%N; cat /proc/net/dev | egrep '({})'; sleep {}; done
+fi
+"#,
+        netstat_command(interface, sample_interval_ms),
+        egrep_pattern,
+        sleep_secs(sample_interval_ms)
+    );
+
+    let mut command = Command::new("ssh");
+    // Keepalives make a dead network path end the session instead of leaving it hanging
+    command.args(["-o", "ServerAliveInterval=10", "-o", "ServerAliveCountMax=3"]);
+    if batch_mode {
+        // Reconnects run under the TUI with nobody to answer a prompt, so only key or agent auth is tried
+        command.args(["-o", "BatchMode=yes"]).stdin(Stdio::null()).stderr(Stdio::null());
+    } else {
+        command.stdin(Stdio::inherit()).stderr(Stdio::inherit());
+    }
+    let child = command
+        .arg(host)
+        .arg(&script)
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    Ok(child)
+}
+
+// macOS: netstat -w <secs> -I <interfaces>, or a netstat -ibn polling loop for sub-second intervals
+async fn spawn_netstat_monitor(host: Option<&String>, interface: &str, sample_interval_ms: f64) -> Result<tokio::process::Child> {
+    let netstat_cmd = netstat_command(interface, sample_interval_ms);
+
+    let child = if let Some(host) = host {
+        // SSH without pseudo-terminal - allows password prompt via stdin/stderr
+        Command::new("ssh")
+            .arg(host)
+            .arg(&netstat_cmd)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?
+    } else {
+        Command::new("sh")
+            .arg("-c")
+            .arg(&netstat_cmd)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?
+    };
+
+    Ok(child)
+}
+
+// Linux: read /proc/net/dev in-process on a tokio interval
+// Interfaces are matched by exact name, so nothing from the config ever reaches a shell
+struct ProcnetSource {
+    interfaces: Vec<String>,
+    ticker: tokio::time::Interval,
+    tracker: BandwidthTracker,
+    pending: VecDeque<BandwidthSample>,
+}
+
+impl ProcnetSource {
+    fn new(interface: &str, sample_interval_ms: f64) -> Result<Self> {
+        let interfaces = parse_interface_list(interface);
+        if interfaces.is_empty() {
+            anyhow::bail!("No network interface configured");
+        }
+        std::fs::metadata("/proc/net/dev")?;
+
+        let period = Duration::from_micros((sample_interval_ms.max(10.0) * 1000.0) as u64);
+        let mut ticker = tokio::time::interval(period);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        Ok(ProcnetSource {
+            interfaces,
+            ticker,
+            tracker: BandwidthTracker::new(sample_interval_ms),
+            pending: VecDeque::new(),
+        })
+    }
+
+    async fn read_sample(&mut self) -> Result<Option<BandwidthSample>> {
+        loop {
+            if let Some(sample) = self.pending.pop_front() {
+                return Ok(Some(sample));
+            }
+
+            self.ticker.tick().await;
+
+            let contents = match tokio::fs::read_to_string("/proc/net/dev").await {
+                Ok(c) => c,
+                Err(_) => continue,
+            };
+            let sampled_at = Instant::now();
+
+            let mut samples = Vec::new();
+            for line in contents.lines() {
+                let name = match line.split_once(':') {
+                    Some((name, _)) => name.trim(),
+                    None => continue,
+                };
+                if !self.interfaces.iter().any(|iface| iface == name) {
+                    continue;
+                }
+
+                samples.extend(self.tracker.update_from_procnet_line(line, sampled_at));
+            }
+            self.pending.extend(combine_samples(samples, &self.interfaces));
+        }
+    }
+}
+
+impl BandwidthSource for ProcnetSource {
+    fn metadata(&self) -> SourceMetadata {
+        SourceMetadata {
+            kind: "procnet",
+            description: format!("/proc/net/dev ({})", self.interfaces.join(",")),
+        }
+    }
+
+    fn next_sample(&mut self) -> SampleFuture<'_> {
+        Box::pin(self.read_sample())
+    }
+
+    fn take_notices(&mut self) -> Vec<String> {
+        self.tracker.take_notices()
+    }
+
+    fn set_link_limit_kbps(&mut self, kbps: f64) {
+        self.tracker.link_limit_kbps = kbps;
+    }
+}
+
+// A monitoring command (local netstat or a remote SSH script) whose output lines are parsed into samples
+// Lines arrive one interface at a time, collected here until the sample window is complete
+struct SampleWindow {
+    interfaces: Vec<String>,
+    samples: Vec<BandwidthSample>,
+}
+
+impl SampleWindow {
+    fn new(interfaces: Vec<String>) -> Self {
+        SampleWindow {
+            interfaces,
+            samples: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    // Add one interface's sample, returning the summed window once it is complete
+    fn push(&mut self, sample: BandwidthSample) -> Option<BandwidthSample> {
+        // netstat -w readings are not per interface and already cover the whole interval
+        let Some(name) = sample.interfaces.first().map(|reading| reading.name.clone()) else {
+            return Some(sample);
+        };
+        // The remote egrep also matches eth10 when eth1 is configured
+        if !self.interfaces.contains(&name) {
+            return None;
+        }
+
+        // An interface reporting again means the window moved on without the missing ones
+        if self.samples.iter().any(|s| s.interfaces.iter().any(|reading| reading.name == name)) {
+            let total = combine_samples(std::mem::take(&mut self.samples), &self.interfaces);
+            self.samples.push(sample);
+            return total;
+        }
+
+        self.samples.push(sample);
+        if self.samples.len() >= self.interfaces.len() {
+            return combine_samples(std::mem::take(&mut self.samples), &self.interfaces);
+        }
+        None
+    }
+}
+
+struct ProcessSource {
+    metadata: SourceMetadata,
+    // Kept so the child lives as long as the source
+    child: tokio::process::Child,
+    lines: tokio::io::Lines<BufReader<tokio::process::ChildStdout>>,
+    tracker: BandwidthTracker,
+    interface: String,
+    window: SampleWindow,
+    sample_interval_ms: f64,
+    // SSH only: the host to reconnect to, and how long without output counts as a dead session
+    host: Option<String>,
+    stall_timeout: Option<Duration>,
+    debug_log: Option<std::fs::File>,
+}
+
+impl ProcessSource {
+    async fn netstat(interface: &str, sample_interval_ms: f64) -> Result<Self> {
+        let child = spawn_netstat_monitor(None, interface, sample_interval_ms).await?;
+        let metadata = SourceMetadata {
+            kind: "netstat",
+            description: format!("netstat ({})", interface),
+        };
+        Self::from_child(child, metadata, interface, sample_interval_ms)
+    }
+
+    async fn ssh(host: &String, interface: &str, sample_interval_ms: f64) -> Result<Self> {
+        let child = spawn_remote_monitor(host, interface, sample_interval_ms, false).await?;
+        let metadata = SourceMetadata {
+            kind: "ssh",
+            description: format!("{} on {}", interface, host),
+        };
+        let mut source = Self::from_child(child, metadata, interface, sample_interval_ms)?;
+        source.host = Some(host.clone());
+        source.stall_timeout = Some(Duration::from_secs_f64((sample_interval_ms * 10.0 / 1000.0).max(30.0)));
+        Ok(source)
+    }
+
+    fn from_child(
+        mut child: tokio::process::Child,
+        metadata: SourceMetadata,
+        interface: &str,
+        sample_interval_ms: f64,
+    ) -> Result<Self> {
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("No stdout available"))?;
+
+        Ok(ProcessSource {
+            metadata,
+            child,
+            lines: BufReader::new(stdout).lines(),
+            tracker: BandwidthTracker::new(sample_interval_ms),
+            interface: interface.to_string(),
+            window: SampleWindow::new(parse_interface_list(interface)),
+            sample_interval_ms,
+            host: None,
+            stall_timeout: None,
+            // Always create debug log file
+            debug_log: std::fs::File::create("/tmp/bandwidth_debug.log").ok(),
+        })
+    }
+
+    // Block until the command produces output, so SSH failures surface before the TUI starts
+    async fn wait_for_first_line(&mut self) -> Result<()> {
+        match self.lines.next_line().await {
+            Ok(Some(line)) => {
+                // Prime the tracker so the first real sample already has a baseline
+                parse_bandwidth_line(&line, Instant::now(), &mut self.tracker);
+                Ok(())
+            }
+            Ok(None) => Err(anyhow::anyhow!("SSH connection failed or closed immediately")),
+            Err(e) => Err(anyhow::anyhow!("Error reading from SSH: {}", e)),
+        }
+    }
+
+    // Start a new SSH session after the last one ended, with fresh counters so the outage isn't averaged in
+    async fn respawn(&mut self) -> Result<()> {
+        let host = self.host.clone().ok_or_else(|| anyhow::anyhow!("Source cannot reconnect"))?;
+        let mut child = spawn_remote_monitor(&host, &self.interface, self.sample_interval_ms, true).await?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("No stdout available"))?;
+        self.child = child;
+        self.lines = BufReader::new(stdout).lines();
+        self.tracker = BandwidthTracker {
+            link_limit_kbps: self.tracker.link_limit_kbps,
+            ..BandwidthTracker::new(self.sample_interval_ms)
+        };
+        self.window.clear();
+
+        let limit = self.stall_timeout.unwrap_or(Duration::from_secs(30));
+        match tokio::time::timeout(limit, self.wait_for_first_line()).await {
+            Ok(result) => result,
+            Err(_) => {
+                let _ = self.child.kill().await;
+                Err(anyhow::anyhow!("No output within {}s", limit.as_secs()))
+            }
+        }
+    }
+
+    // Next output line; a session that stays silent past the stall timeout is killed and reported
+    async fn next_line(&mut self) -> Result<Option<String>> {
+        let Some(limit) = self.stall_timeout else {
+            return Ok(self.lines.next_line().await?);
+        };
+        match tokio::time::timeout(limit, self.lines.next_line()).await {
+            Ok(line) => Ok(line?),
+            Err(_) => {
+                let _ = self.child.kill().await;
+                Err(anyhow::anyhow!("No output for {}s", limit.as_secs()))
+            }
+        }
+    }
+
+    async fn read_sample(&mut self) -> Result<Option<BandwidthSample>> {
+        while let Some(line) = self.next_line().await? {
+            let sampled_at = Instant::now();
+
+            // Debug: write raw line with timestamp to file when received from the command
+            if let Some(ref mut log) = self.debug_log {
+                let _ = writeln!(log, "[{}] SSH OUTPUT: {}", get_timestamp(), line);
+                let _ = log.flush(); // Flush immediately so tail -f works
+            }
+
+            if let Some(total) = parse_bandwidth_line(&line, sampled_at, &mut self.tracker)
+                .and_then(|sample| self.window.push(sample))
+            {
+                return Ok(Some(total));
+            }
+        }
+
+        // Say how the command ended (ssh exits with 255 when the connection drops)
+        match tokio::time::timeout(Duration::from_secs(1), self.child.wait()).await {
+            Ok(Ok(status)) if !status.success() => Err(anyhow::anyhow!("{} exited with {}", self.metadata.kind, status)),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl BandwidthSource for ProcessSource {
+    fn metadata(&self) -> SourceMetadata {
+        self.metadata.clone()
+    }
+
+    fn next_sample(&mut self) -> SampleFuture<'_> {
+        Box::pin(self.read_sample())
+    }
+
+    fn take_notices(&mut self) -> Vec<String> {
+        self.tracker.take_notices()
+    }
+
+    fn set_link_limit_kbps(&mut self, kbps: f64) {
+        self.tracker.link_limit_kbps = kbps;
+    }
+
+    fn can_reconnect(&self) -> bool {
+        self.host.is_some()
+    }
+
+    fn reconnect(&mut self) -> ConnectFuture<'_> {
+        Box::pin(self.respawn())
+    }
+}
+
+// SNMP source: polls ifHCInOctets/ifHCOutOctets (or 32-bit ifInOctets/ifOutOctets) over v2c or v3 USM
+const SNMP_TIMEOUT: Duration = Duration::from_secs(2);
+// Polls that may fail in a row before the source is reported lost
+const SNMP_MAX_FAILED_POLLS: u32 = 3;
+
+const SNMP_GET: u8 = 0xA0;
+const SNMP_GET_NEXT: u8 = 0xA1;
+const SNMP_RESPONSE: u8 = 0xA2;
+const SNMP_REPORT: u8 = 0xA8;
+
+const OID_IF_DESCR: &[u32] = &[1, 3, 6, 1, 2, 1, 2, 2, 1, 2];
+const OID_IF_IN_OCTETS: &[u32] = &[1, 3, 6, 1, 2, 1, 2, 2, 1, 10];
+const OID_IF_OUT_OCTETS: &[u32] = &[1, 3, 6, 1, 2, 1, 2, 2, 1, 16];
+const OID_IF_NAME: &[u32] = &[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 1];
+const OID_IF_HC_IN_OCTETS: &[u32] = &[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 6];
+const OID_IF_HC_OUT_OCTETS: &[u32] = &[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 10];
+const OID_USM_STATS_NOT_IN_TIME_WINDOWS: &[u32] = &[1, 3, 6, 1, 6, 3, 15, 1, 1, 2, 0];
+const OID_USM_STATS_UNKNOWN_USER_NAMES: &[u32] = &[1, 3, 6, 1, 6, 3, 15, 1, 1, 3, 0];
+const OID_USM_STATS_UNKNOWN_ENGINE_IDS: &[u32] = &[1, 3, 6, 1, 6, 3, 15, 1, 1, 4, 0];
+const OID_USM_STATS_WRONG_DIGESTS: &[u32] = &[1, 3, 6, 1, 6, 3, 15, 1, 1, 5, 0];
+
+#[derive(Debug, Clone, PartialEq)]
+enum SnmpValue {
+    Integer(i64),
+    OctetString(Vec<u8>),
+    ObjectId(Vec<u32>),
+    // Counter32, Gauge32, TimeTicks and Counter64
+    Unsigned(u64),
+    Null,
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+    Other(u8),
+}
+
+impl SnmpValue {
+    fn as_counter(&self) -> Option<u64> {
+        match self {
+            SnmpValue::Unsigned(v) => Some(*v),
+            SnmpValue::Integer(v) if *v >= 0 => Some(*v as u64),
+            _ => None,
+        }
+    }
+}
+
+fn format_oid(oid: &[u32]) -> String {
+    oid.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(".")
+}
+
+// BER encoding (only what SNMP GET/GETNEXT needs)
+fn ber_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let first = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len() - 1);
+        out.push(0x80 | (bytes.len() - first) as u8);
+        out.extend_from_slice(&bytes[first..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+fn ber_integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    // Strip leading bytes that only repeat the sign
+    let mut start = 0;
+    while start < bytes.len() - 1
+        && ((bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xFF && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    ber_tlv(0x02, &bytes[start..])
+}
+
+fn ber_oid(oid: &[u32]) -> Vec<u8> {
+    let mut content = Vec::new();
+    // The first two arcs share one subidentifier, which can itself need several bytes (2.999 and up)
+    let first = (oid.len() >= 2).then(|| oid[0] * 40 + oid[1]);
+    for sub in first.into_iter().chain(oid.iter().skip(2).copied()) {
+        let mut encoded = vec![(sub & 0x7F) as u8];
+        let mut rest = sub >> 7;
+        while rest > 0 {
+            encoded.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        encoded.reverse();
+        content.extend(encoded);
+    }
+    ber_tlv(0x06, &content)
+}
+
+fn ber_decode_integer(content: &[u8]) -> i64 {
+    let negative = content.first().is_some_and(|b| b & 0x80 != 0);
+    let initial: i64 = if negative { -1 } else { 0 };
+    content.iter().fold(initial, |acc, &b| (acc << 8) | b as i64)
+}
+
+fn ber_decode_unsigned(content: &[u8]) -> u64 {
+    content.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64)
+}
+
+fn ber_decode_oid(content: &[u8]) -> Vec<u32> {
+    let mut oid = Vec::new();
+    let mut sub: u32 = 0;
+    for &b in content {
+        sub = (sub << 7) | (b & 0x7F) as u32;
+        if b & 0x80 == 0 {
+            if oid.is_empty() {
+                // Arcs under 2 (joint-iso-itu-t) have no 40 limit on the second arc
+                let top = (sub / 40).min(2);
+                oid.push(top);
+                oid.push(sub - top * 40);
+            } else {
+                oid.push(sub);
+            }
+            sub = 0;
+        }
+    }
+    oid
+}
+
+fn ber_decode_value(tag: u8, content: &[u8]) -> SnmpValue {
+    match tag {
+        0x02 => SnmpValue::Integer(ber_decode_integer(content)),
+        0x04 => SnmpValue::OctetString(content.to_vec()),
+        0x05 => SnmpValue::Null,
+        0x06 => SnmpValue::ObjectId(ber_decode_oid(content)),
+        0x41 | 0x42 | 0x43 | 0x46 => SnmpValue::Unsigned(ber_decode_unsigned(content)),
+        0x80 => SnmpValue::NoSuchObject,
+        0x81 => SnmpValue::NoSuchInstance,
+        0x82 => SnmpValue::EndOfMibView,
+        other => SnmpValue::Other(other),
+    }
+}
+
+struct BerReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BerReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BerReader { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn read_tlv(&mut self) -> Result<(u8, &'a [u8])> {
+        let truncated = || anyhow::anyhow!("Truncated SNMP message");
+
+        let tag = *self.data.get(self.pos).ok_or_else(truncated)?;
+        let first = *self.data.get(self.pos + 1).ok_or_else(truncated)?;
+        let mut pos = self.pos + 2;
+
+        let len = if first & 0x80 == 0 {
+            first as usize
+        } else {
+            let n = (first & 0x7F) as usize;
+            if n == 0 || n > 4 {
+                anyhow::bail!("Unsupported BER length encoding");
+            }
+            let bytes = self.data.get(pos..pos + n).ok_or_else(truncated)?;
+            pos += n;
+            bytes.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize)
+        };
+
+        let content = self.data.get(pos..pos + len).ok_or_else(truncated)?;
+        self.pos = pos + len;
+        Ok((tag, content))
+    }
+
+    fn expect(&mut self, tag: u8) -> Result<&'a [u8]> {
+        let (actual, content) = self.read_tlv()?;
+        if actual != tag {
+            anyhow::bail!("Unexpected BER tag 0x{:02x} (expected 0x{:02x})", actual, tag);
+        }
+        Ok(content)
+    }
+
+    fn read_integer(&mut self) -> Result<i64> {
+        Ok(ber_decode_integer(self.expect(0x02)?))
+    }
+
+    fn read_sequence(&mut self) -> Result<BerReader<'a>> {
+        Ok(BerReader::new(self.expect(0x30)?))
+    }
+}
+
+struct SnmpPdu {
+    pdu_type: u8,
+    request_id: i64,
+    error_status: i64,
+    error_index: i64,
+    varbinds: Vec<(Vec<u32>, SnmpValue)>,
+}
+
+fn encode_snmp_pdu(pdu_type: u8, request_id: i32, oids: &[Vec<u32>]) -> Vec<u8> {
+    let mut varbinds = Vec::new();
+    for oid in oids {
+        let mut varbind = ber_oid(oid);
+        varbind.extend(ber_tlv(0x05, &[]));
+        varbinds.extend(ber_tlv(0x30, &varbind));
+    }
+
+    let mut content = ber_integer(request_id as i64);
+    content.extend(ber_integer(0)); // error-status
+    content.extend(ber_integer(0)); // error-index
+    content.extend(ber_tlv(0x30, &varbinds));
+    ber_tlv(pdu_type, &content)
+}
+
+fn decode_snmp_pdu(reader: &mut BerReader) -> Result<SnmpPdu> {
+    let (pdu_type, content) = reader.read_tlv()?;
+    let mut pdu = BerReader::new(content);
+
+    let request_id = pdu.read_integer()?;
+    let error_status = pdu.read_integer()?;
+    let error_index = pdu.read_integer()?;
+
+    let mut list = pdu.read_sequence()?;
+    let mut varbinds = Vec::new();
+    while !list.is_empty() {
+        let mut varbind = list.read_sequence()?;
+        let oid = ber_decode_oid(varbind.expect(0x06)?);
+        let (tag, value) = varbind.read_tlv()?;
+        varbinds.push((oid, ber_decode_value(tag, value)));
+    }
+
+    Ok(SnmpPdu {
+        pdu_type,
+        request_id,
+        error_status,
+        error_index,
+        varbinds,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SnmpAuthProtocol {
+    None,
+    Md5,
+    Sha1,
+}
+
+fn snmp_digest(auth: SnmpAuthProtocol, data: &[u8]) -> Vec<u8> {
+    match auth {
+        SnmpAuthProtocol::Md5 => Md5::digest(data).to_vec(),
+        SnmpAuthProtocol::Sha1 => Sha1::digest(data).to_vec(),
+        SnmpAuthProtocol::None => Vec::new(),
+    }
+}
+
+// HMAC (RFC 2104) over MD5/SHA-1, both of which use a 64-byte block
+fn snmp_hmac(auth: SnmpAuthProtocol, key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut block_key = if key.len() > 64 {
+        snmp_digest(auth, key)
+    } else {
+        key.to_vec()
+    };
+    block_key.resize(64, 0);
+
+    let mut inner: Vec<u8> = block_key.iter().map(|b| b ^ 0x36).collect();
+    inner.extend_from_slice(data);
+    let mut outer: Vec<u8> = block_key.iter().map(|b| b ^ 0x5C).collect();
+    outer.extend(snmp_digest(auth, &inner));
+    snmp_digest(auth, &outer)
+}
+
+// RFC 3414 A.2: hash 1 MB of the repeated password, then localize the key to the agent's engine ID
+fn snmp_localize_key(auth: SnmpAuthProtocol, password: &str, engine_id: &[u8]) -> Result<Vec<u8>> {
+    if password.len() < 8 {
+        anyhow::bail!("SNMPv3 auth password must be at least 8 characters");
+    }
+
+    let expanded: Vec<u8> = password.bytes().cycle().take(1_048_576).collect();
+    let user_key = snmp_digest(auth, &expanded);
+
+    let mut localized = user_key.clone();
+    localized.extend_from_slice(engine_id);
+    localized.extend_from_slice(&user_key);
+    Ok(snmp_digest(auth, &localized))
+}
+
+// The agent's view of itself as reported in USM security parameters
+struct SnmpEngine {
+    id: Vec<u8>,
+    boots: i64,
+    time: i64,
+}
+
+struct UsmSession {
+    engine: SnmpEngine,
+    synced_at: Instant,
+    auth_key: Vec<u8>,
+}
+
+impl UsmSession {
+    fn engine_time(&self) -> i64 {
+        self.engine.time + self.synced_at.elapsed().as_secs() as i64
+    }
+}
+
+enum SnmpSecurity {
+    Community(String),
+    Usm {
+        username: String,
+        auth: SnmpAuthProtocol,
+        password: String,
+        session: Option<UsmSession>,
+    },
+}
+
+struct SnmpResponse {
+    pdu: SnmpPdu,
+    engine: Option<SnmpEngine>,
+}
+
+// Build an SNMPv3 message (noAuthNoPriv or authNoPriv), signing it in place when authenticating
+fn encode_v3_message(msg_id: i32, pdu: &[u8], username: &str, auth: SnmpAuthProtocol, session: Option<&UsmSession>) -> Vec<u8> {
+    let authenticated = auth != SnmpAuthProtocol::None && session.is_some();
+    // Always reportable so the agent tells us about time window and auth problems
+    let flags: u8 = if authenticated { 0x05 } else { 0x04 };
+
+    let (engine_id, boots, time, user) = match session {
+        Some(s) => (s.engine.id.as_slice(), s.engine.boots, s.engine_time(), username.as_bytes()),
+        // Engine discovery uses an empty engine ID and user name
+        None => (&[][..], 0, 0, &[][..]),
+    };
+
+    let mut header = ber_integer(msg_id as i64);
+    header.extend(ber_integer(65507)); // msgMaxSize
+    header.extend(ber_tlv(0x04, &[flags]));
+    header.extend(ber_integer(3)); // msgSecurityModel = USM
+
+    let mut security = ber_tlv(0x04, engine_id);
+    security.extend(ber_integer(boots));
+    security.extend(ber_integer(time));
+    security.extend(ber_tlv(0x04, user));
+    let auth_offset_in_security = security.len() + 2;
+    let auth_placeholder: &[u8] = if authenticated { &[0u8; 12] } else { &[] };
+    security.extend(ber_tlv(0x04, auth_placeholder));
+    security.extend(ber_tlv(0x04, &[])); // privacy parameters
+    let security_seq = ber_tlv(0x30, &security);
+    let security_octets = ber_tlv(0x04, &security_seq);
+
+    let mut scoped = ber_tlv(0x04, engine_id); // contextEngineID
+    scoped.extend(ber_tlv(0x04, &[])); // contextName
+    scoped.extend_from_slice(pdu);
+
+    let mut content = ber_integer(3);
+    content.extend(ber_tlv(0x30, &header));
+    let security_start = content.len();
+    content.extend(&security_octets);
+    content.extend(ber_tlv(0x30, &scoped));
+    let mut message = ber_tlv(0x30, &content);
+
+    if let (true, Some(session)) = (authenticated, session) {
+        let offset = (message.len() - content.len())
+            + security_start
+            + (security_octets.len() - security_seq.len())
+            + (security_seq.len() - security.len())
+            + auth_offset_in_security;
+        let mac = snmp_hmac(auth, &session.auth_key, &message);
+        message[offset..offset + 12].copy_from_slice(&mac[..12]);
+    }
+
+    message
+}
+
+struct SnmpClient {
+    socket: tokio::net::UdpSocket,
+    security: SnmpSecurity,
+    next_id: i32,
+}
+
+impl SnmpClient {
+    async fn connect(host: &str, port: u16, security: SnmpSecurity) -> Result<Self> {
+        let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect((host, port)).await?;
+
+        let mut client = SnmpClient {
+            socket,
+            security,
+            next_id: (SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.subsec_nanos() & 0x7FFF) as i32,
+        };
+
+        if matches!(client.security, SnmpSecurity::Usm { .. }) {
+            client.discover_engine().await?;
+        }
+
+        Ok(client)
+    }
+
+    fn next_request_id(&mut self) -> i32 {
+        self.next_id = self.next_id.wrapping_add(1) & 0x7FFF_FFFF;
+        self.next_id
+    }
+
+    async fn get(&mut self, oids: &[Vec<u32>]) -> Result<Vec<(Vec<u32>, SnmpValue)>> {
+        self.request(SNMP_GET, oids).await
+    }
+
+    async fn get_next(&mut self, oid: &[u32]) -> Result<(Vec<u32>, SnmpValue)> {
+        self.request(SNMP_GET_NEXT, &[oid.to_vec()])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Empty SNMP GETNEXT response"))
+    }
+
+    async fn request(&mut self, pdu_type: u8, oids: &[Vec<u32>]) -> Result<Vec<(Vec<u32>, SnmpValue)>> {
+        // SNMPv3 may need one retry after re-syncing with the agent's clock
+        for _ in 0..2 {
+            let request_id = self.next_request_id();
+            let pdu = encode_snmp_pdu(pdu_type, request_id, oids);
+            let message = match &self.security {
+                SnmpSecurity::Community(community) => {
+                    let mut content = ber_integer(1); // version 2c
+                    content.extend(ber_tlv(0x04, community.as_bytes()));
+                    content.extend(pdu);
+                    ber_tlv(0x30, &content)
+                }
+                SnmpSecurity::Usm { username, auth, session, .. } => {
+                    encode_v3_message(request_id, &pdu, username, *auth, session.as_ref())
+                }
+            };
+
+            let response = self.exchange(&message, request_id).await?;
+            match response.pdu.pdu_type {
+                SNMP_RESPONSE => {
+                    if response.pdu.error_status != 0 {
+                        anyhow::bail!(
+                            "SNMP error status {} at index {}",
+                            response.pdu.error_status,
+                            response.pdu.error_index
+                        );
+                    }
+                    return Ok(response.pdu.varbinds);
+                }
+                SNMP_REPORT => self.handle_report(response)?,
+                other => anyhow::bail!("Unexpected SNMP PDU type 0x{:02x}", other),
+            }
+        }
+
+        anyhow::bail!("SNMP agent kept rejecting requests (engine time out of sync)")
+    }
+
+    // Send a message and wait for the response carrying the same request ID
+    async fn exchange(&self, message: &[u8], request_id: i32) -> Result<SnmpResponse> {
+        self.socket.send(message).await?;
+
+        let mut buf = vec![0u8; 65535];
+        // Datagrams that fail to decode are skipped, but the last failure explains a timeout
+        let mut last_error = None;
+        let receive = async {
+            loop {
+                let len = self.socket.recv(&mut buf).await?;
+                match self.decode_message(&buf[..len]) {
+                    Ok(response) if response.pdu.request_id == request_id as i64 => return Ok(response),
+                    // Stale or unrelated datagram - keep waiting
+                    Ok(_) => continue,
+                    Err(e) => last_error = Some(e),
+                }
+            }
+        };
+
+        let received = tokio::time::timeout(SNMP_TIMEOUT, receive).await;
+        match received {
+            Ok(result) => result,
+            Err(_) => Err(match last_error {
+                Some(e) => anyhow::anyhow!("SNMP request timed out: {}", e),
+                None => anyhow::anyhow!("SNMP request timed out"),
+            }),
+        }
+    }
+
+    fn decode_message(&self, data: &[u8]) -> Result<SnmpResponse> {
+        let mut message = BerReader::new(data).read_sequence()?;
+
+        match message.read_integer()? {
+            1 => {
+                let _community = message.expect(0x04)?;
+                let pdu = decode_snmp_pdu(&mut message)?;
+                Ok(SnmpResponse { pdu, engine: None })
+            }
+            3 => {
+                let mut header = message.read_sequence()?;
+                let _msg_id = header.read_integer()?;
+                let _max_size = header.read_integer()?;
+                let flags = header.expect(0x04)?.first().copied().unwrap_or(0);
+                if flags & 0x02 != 0 {
+                    anyhow::bail!("Encrypted SNMPv3 responses are not supported");
+                }
+
+                let mut security = BerReader::new(message.expect(0x04)?).read_sequence()?;
+                let engine = SnmpEngine {
+                    id: security.expect(0x04)?.to_vec(),
+                    boots: security.read_integer()?,
+                    time: security.read_integer()?,
+                };
+                let _username = security.expect(0x04)?;
+                let auth_params = security.expect(0x04)?;
+
+                // Verify the agent's signature on authenticated responses
+                let authenticated = flags & 0x01 != 0;
+                let auth_required = matches!(
+                    &self.security,
+                    SnmpSecurity::Usm { auth, session: Some(_), .. } if *auth != SnmpAuthProtocol::None
+                );
+                if authenticated {
+                    if let SnmpSecurity::Usm { auth, session: Some(session), .. } = &self.security {
+                        if auth_params.len() != 12 {
+                            anyhow::bail!("Malformed SNMPv3 authentication parameters");
+                        }
+                        let offset = auth_params.as_ptr() as usize - data.as_ptr() as usize;
+                        let mut unsigned = data.to_vec();
+                        unsigned[offset..offset + 12].fill(0);
+                        let mac = snmp_hmac(*auth, &session.auth_key, &unsigned);
+                        if mac[..12] != *auth_params {
+                            anyhow::bail!("SNMPv3 response failed authentication");
+                        }
+                    }
+                }
+
+                let mut scoped = message.read_sequence()?;
+                let _context_engine_id = scoped.expect(0x04)?;
+                let _context_name = scoped.expect(0x04)?;
+                let pdu = decode_snmp_pdu(&mut scoped)?;
+
+                // Only reports may come back unsigned, anything else could be spoofed
+                if auth_required && !authenticated && pdu.pdu_type != SNMP_REPORT {
+                    anyhow::bail!("Unauthenticated SNMPv3 response rejected");
+                }
+
+                Ok(SnmpResponse {
+                    pdu,
+                    engine: Some(engine),
+                })
+            }
+            version => anyhow::bail!("Unsupported SNMP version {}", version),
+        }
+    }
+
+    // Reports are the agent complaining about our USM parameters
+    fn handle_report(&mut self, response: SnmpResponse) -> Result<()> {
+        let oid = response
+            .pdu
+            .varbinds
+            .first()
+            .map(|(oid, _)| oid.clone())
+            .unwrap_or_default();
+
+        if oid == OID_USM_STATS_NOT_IN_TIME_WINDOWS || oid == OID_USM_STATS_UNKNOWN_ENGINE_IDS {
+            // Re-sync our idea of the agent's boots/time, the caller retries
+            if let (SnmpSecurity::Usm { session: Some(session), .. }, Some(engine)) =
+                (&mut self.security, response.engine)
+            {
+                session.engine.boots = engine.boots;
+                session.engine.time = engine.time;
+                session.synced_at = Instant::now();
+            }
+            Ok(())
+        } else if oid == OID_USM_STATS_WRONG_DIGESTS {
+            anyhow::bail!("SNMPv3 authentication failed (wrong auth password or protocol?)")
+        } else if oid == OID_USM_STATS_UNKNOWN_USER_NAMES {
+            anyhow::bail!("SNMPv3 user name is unknown to the agent")
+        } else {
+            anyhow::bail!("SNMP agent returned report {}", format_oid(&oid))
+        }
+    }
+
+    // SNMPv3 engine discovery: an empty request makes the agent report its engine ID, boots and time
+    async fn discover_engine(&mut self) -> Result<()> {
+        let request_id = self.next_request_id();
+        let pdu = encode_snmp_pdu(SNMP_GET, request_id, &[]);
+        let message = encode_v3_message(request_id, &pdu, "", SnmpAuthProtocol::None, None);
+
+        let response = self.exchange(&message, request_id).await?;
+        let engine = match response.engine {
+            Some(engine) if !engine.id.is_empty() => engine,
+            _ => anyhow::bail!("SNMP agent did not report an engine ID"),
+        };
+
+        if let SnmpSecurity::Usm { auth, password, session, .. } = &mut self.security {
+            let auth_key = if *auth == SnmpAuthProtocol::None {
+                Vec::new()
+            } else {
+                snmp_localize_key(*auth, password, &engine.id)?
+            };
+            *session = Some(UsmSession {
+                engine,
+                synced_at: Instant::now(),
+                auth_key,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+// A monitored interface resolved to the OIDs of its octet counters
+struct SnmpInterface {
+    name: String,
+    in_oid: Vec<u32>,
+    out_oid: Vec<u32>,
+    counter_bits: u32,
+}
+
+struct SnmpSource {
+    client: SnmpClient,
+    description: String,
+    interfaces: Vec<SnmpInterface>,
+    ticker: tokio::time::Interval,
+    tracker: BandwidthTracker,
+    pending: VecDeque<BandwidthSample>,
+    sample_interval_ms: f64,
+    failed_polls: u32,
+}
+
+impl SnmpSource {
+    async fn connect(config: &BandwidthConfig) -> Result<Self> {
+        if config.snmp_host.is_empty() {
+            anyhow::bail!("snmp_host must be set when source = \"snmp\"");
+        }
+
+        let security = match config.snmp_version.to_lowercase().as_str() {
+            "3" => {
+                let auth = match config.snmp_auth_protocol.to_lowercase().as_str() {
+                    "none" => SnmpAuthProtocol::None,
+                    "md5" => SnmpAuthProtocol::Md5,
+                    "sha" | "sha1" => SnmpAuthProtocol::Sha1,
+                    other => anyhow::bail!("Unsupported snmp_auth_protocol \"{}\" (expected none, md5 or sha)", other),
+                };
+                SnmpSecurity::Usm {
+                    username: config.snmp_username.clone(),
+                    auth,
+                    password: config.snmp_auth_password.clone(),
+                    session: None,
+                }
+            }
+            "2c" => SnmpSecurity::Community(config.snmp_community.clone()),
+            other => anyhow::bail!("Unsupported snmp_version \"{}\" (expected 2c or 3)", other),
+        };
+
+        let mut client = SnmpClient::connect(&config.snmp_host, config.snmp_port, security).await?;
+
+        let mut interfaces = Vec::new();
+        for name in parse_interface_list(&config.interface) {
+            let if_index = Self::resolve_if_index(&mut client, &name).await?;
+
+            // Prefer 64-bit counters, older agents only have the 32-bit ones
+            let hc_in_oid = [OID_IF_HC_IN_OCTETS, &[if_index]].concat();
+            let has_hc = matches!(
+                client.get(std::slice::from_ref(&hc_in_oid)).await?.first(),
+                Some((_, SnmpValue::Unsigned(_)))
+            );
+
+            interfaces.push(if has_hc {
+                SnmpInterface {
+                    name,
+                    in_oid: hc_in_oid,
+                    out_oid: [OID_IF_HC_OUT_OCTETS, &[if_index]].concat(),
+                    counter_bits: 64,
+                }
+            } else {
+                SnmpInterface {
+                    name,
+                    in_oid: [OID_IF_IN_OCTETS, &[if_index]].concat(),
+                    out_oid: [OID_IF_OUT_OCTETS, &[if_index]].concat(),
+                    counter_bits: 32,
+                }
+            });
+        }
+
+        if interfaces.is_empty() {
+            anyhow::bail!("No network interface configured");
+        }
+
+        let period = Duration::from_micros((config.sample_interval_ms.max(10.0) * 1000.0) as u64);
+        let mut ticker = tokio::time::interval(period);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        Ok(SnmpSource {
+            client,
+            description: format!(
+                "{} on {}:{} (SNMP v{})",
+                config.interface, config.snmp_host, config.snmp_port, config.snmp_version
+            ),
+            interfaces,
+            ticker,
+            tracker: BandwidthTracker::new(config.sample_interval_ms),
+            pending: VecDeque::new(),
+            sample_interval_ms: config.sample_interval_ms,
+            failed_polls: 0,
+        })
+    }
+
+    fn counter_oids(&self) -> Vec<Vec<u32>> {
+        self.interfaces
+            .iter()
+            .flat_map(|iface| [iface.in_oid.clone(), iface.out_oid.clone()])
+            .collect()
+    }
+
+    // Rediscover the agent's engine (it may have rebooted) and check it answers, with fresh counters
+    async fn resync(&mut self) -> Result<()> {
+        if matches!(self.client.security, SnmpSecurity::Usm { .. }) {
+            self.client.discover_engine().await?;
+        }
+        let oids = self.counter_oids();
+        self.client.get(&oids).await?;
+        self.tracker = BandwidthTracker {
+            link_limit_kbps: self.tracker.link_limit_kbps,
+            ..BandwidthTracker::new(self.sample_interval_ms)
+        };
+        self.pending.clear();
+        self.failed_polls = 0;
+        Ok(())
+    }
+
+    // A numeric interface is an ifIndex, anything else is looked up in ifName and then ifDescr
+    async fn resolve_if_index(client: &mut SnmpClient, name: &str) -> Result<u32> {
+        if let Ok(if_index) = name.parse::<u32>() {
+            return Ok(if_index);
+        }
+
+        for table in [OID_IF_NAME, OID_IF_DESCR] {
+            let mut oid = table.to_vec();
+            loop {
+                let (next_oid, value) = client.get_next(&oid).await?;
+                if !next_oid.starts_with(table) || next_oid <= oid {
+                    break;
+                }
+                if let SnmpValue::OctetString(bytes) = &value {
+                    if String::from_utf8_lossy(bytes) == name {
+                        if let Some(&if_index) = next_oid.get(table.len()) {
+                            return Ok(if_index);
+                        }
+                    }
+                }
+                oid = next_oid;
+            }
+        }
+
+        anyhow::bail!("Interface {} not found on SNMP agent", name)
+    }
+
+    async fn read_sample(&mut self) -> Result<Option<BandwidthSample>> {
+        loop {
+            if let Some(sample) = self.pending.pop_front() {
+                return Ok(Some(sample));
+            }
+
+            self.ticker.tick().await;
+
+            let oids = self.counter_oids();
+
+            // A lost or late response just skips this interval, repeated failures end the source
+            let varbinds = match self.client.get(&oids).await {
+                Ok(v) => {
+                    self.failed_polls = 0;
+                    v
+                }
+                Err(e) => {
+                    self.failed_polls += 1;
+                    if self.failed_polls >= SNMP_MAX_FAILED_POLLS {
+                        self.failed_polls = 0;
+                        return Err(e);
+                    }
+                    continue;
+                }
+            };
+            let sampled_at = Instant::now();
+
+            let mut samples = Vec::new();
+            for (i, iface) in self.interfaces.iter().enumerate() {
+                let rx_bytes = varbinds.get(i * 2).and_then(|(_, v)| v.as_counter());
+                let tx_bytes = varbinds.get(i * 2 + 1).and_then(|(_, v)| v.as_counter());
+
+                if let (Some(rx_bytes), Some(tx_bytes)) = (rx_bytes, tx_bytes) {
+                    if let Some(sample) = self.tracker.update_wrapping_counters(
+                        &iface.name,
+                        rx_bytes,
+                        tx_bytes,
+                        iface.counter_bits,
+                        sampled_at,
+                    ) {
+                        samples.push(sample);
+                    }
+                }
+            }
+            let names: Vec<String> = self.interfaces.iter().map(|iface| iface.name.clone()).collect();
+            self.pending.extend(combine_samples(samples, &names));
+        }
+    }
+}
+
+impl BandwidthSource for SnmpSource {
+    fn metadata(&self) -> SourceMetadata {
+        SourceMetadata {
+            kind: "snmp",
+            description: self.description.clone(),
+        }
+    }
+
+    fn next_sample(&mut self) -> SampleFuture<'_> {
+        Box::pin(self.read_sample())
+    }
+
+    fn take_notices(&mut self) -> Vec<String> {
+        self.tracker.take_notices()
+    }
+
+    fn set_link_limit_kbps(&mut self, kbps: f64) {
+        self.tracker.link_limit_kbps = kbps;
+    }
+
+    fn can_reconnect(&self) -> bool {
+        true
+    }
+
+    fn reconnect(&mut self) -> ConnectFuture<'_> {
+        Box::pin(self.resync())
+    }
+}
+
+// Prometheus source: scrapes a text exposition endpoint (e.g. node_exporter) and derives rates from byte counters
+const PROMETHEUS_TIMEOUT: Duration = Duration::from_secs(5);
+// Scrapes that may fail in a row before the source is reported lost
+const PROMETHEUS_MAX_FAILED_SCRAPES: u32 = 3;
+
+// One sample line from the Prometheus text exposition (or OpenMetrics) format
+struct PrometheusSeries {
+    name: String,
+    labels: Vec<(String, String)>,
+    value: f64,
+}
+
+// Parse `name{label="value",...} value [timestamp]`, returns None for comments and malformed lines
+fn parse_prometheus_line(line: &str) -> Option<PrometheusSeries> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let name_end = line.find(|c: char| c == '{' || c.is_whitespace())?;
+    let name = line[..name_end].to_string();
+    let mut rest = &line[name_end..];
+    let mut labels = Vec::new();
+
+    if let Some(body) = rest.strip_prefix('{') {
+        let (pairs, after) = parse_label_list(body)?;
+        for pair in pairs {
+            if pair.op != "=" {
+                return None;
+            }
+            labels.push((pair.name, pair.value));
+        }
+        rest = after;
+    }
+
+    let value = match rest.split_whitespace().next()? {
+        "+Inf" => f64::INFINITY,
+        "-Inf" => f64::NEG_INFINITY,
+        v => v.parse().ok()?,
+    };
+
+    Some(PrometheusSeries { name, labels, value })
+}
+
+// One `name="value"` (or `name!="value"`, `name=~"regex"`...) from a label list
+struct LabelPair {
+    name: String,
+    op: String,
+    value: String,
+}
+
+// Read a label list up to and including its closing brace (the opening one already consumed), returning the
+// pairs and the text after it. Commas and braces inside quoted values belong to the value, and `\"`, `\\` and `\n`
+// are unescaped. Values without quotes run to the next comma, brace or space
+fn parse_label_list(body: &str) -> Option<(Vec<LabelPair>, &str)> {
+    let mut chars = body.char_indices().peekable();
+    let mut pairs = Vec::new();
+    loop {
+        while chars.next_if(|&(_, c)| c == ',' || c.is_whitespace()).is_some() {}
+        let (i, c) = *chars.peek()?;
+        if c == '}' {
+            return Some((pairs, &body[i + 1..]));
+        }
+
+        let mut name = String::new();
+        while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_alphanumeric() || c == '_' || c == ':' || c == '.') {
+            name.push(c);
+        }
+        while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+        let mut op = String::new();
+        while let Some((_, c)) = chars.next_if(|&(_, c)| matches!(c, '=' | '!' | '~')) {
+            op.push(c);
+        }
+        while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+        if name.is_empty() || op.is_empty() {
+            return None;
+        }
+
+        let mut value = String::new();
+        if chars.next_if(|&(_, c)| c == '"').is_some() {
+            loop {
+                match chars.next()?.1 {
+                    '"' => break,
+                    '\\' => match chars.next()?.1 {
+                        'n' => value.push('\n'),
+                        other => value.push(other),
+                    },
+                    other => value.push(other),
+                }
+            }
+        } else {
+            while let Some((_, c)) = chars.next_if(|&(_, c)| !(c == ',' || c == '}' || c.is_whitespace())) {
+                value.push(c);
+            }
+        }
+        pairs.push(LabelPair { name, op, value });
+    }
+}
+
+// A single `label="value"` or `label!="value"` matcher from prometheus_selector
+struct LabelMatcher {
+    label: String,
+    value: String,
+    negate: bool,
+}
+
+impl LabelMatcher {
+    fn matches(&self, labels: &[(String, String)]) -> bool {
+        let value = labels
+            .iter()
+            .find(|(k, _)| *k == self.label)
+            .map(|(_, v)| v.as_str())
+            .unwrap_or("");
+        (value == self.value) != self.negate
+    }
+}
+
+// Parse a comma-separated selector such as `device="eth0",instance!="backup"`
+fn parse_label_selector(selector: &str) -> Result<Vec<LabelMatcher>> {
+    let trimmed = selector.trim();
+    let body = trimmed.strip_prefix('{').unwrap_or(trimmed);
+    // The closing brace is optional in the config, the label list parser wants one
+    let body = if body.ends_with('}') { body.to_string() } else { format!("{}}}", body) };
+    let pairs = match parse_label_list(&body) {
+        Some((pairs, "")) => pairs,
+        _ => anyhow::bail!("Invalid label selector: {}", selector),
+    };
+
+    let mut matchers = Vec::new();
+    for pair in pairs {
+        let negate = match pair.op.as_str() {
+            "=" => false,
+            "!=" => true,
+            "=~" | "!~" => anyhow::bail!("Regex label matchers are not supported: {}{}\"{}\"", pair.name, pair.op, pair.value),
+            _ => anyhow::bail!("Invalid label matcher: {}{}\"{}\"", pair.name, pair.op, pair.value),
+        };
+        matchers.push(LabelMatcher {
+            label: pair.name,
+            value: pair.value,
+            negate,
+        });
+    }
+
+    Ok(matchers)
+}
+
+struct PrometheusSource {
+    client: reqwest::Client,
+    url: String,
+    rx_metric: String,
+    tx_metric: String,
+    // Empty selector: match the `device` label against the configured interfaces
+    matchers: Vec<LabelMatcher>,
+    interfaces: Vec<String>,
+    ticker: tokio::time::Interval,
+    tracker: BandwidthTracker,
+    pending: VecDeque<BandwidthSample>,
+    sample_interval_ms: f64,
+    failed_scrapes: u32,
+}
+
+impl PrometheusSource {
+    fn new(config: &BandwidthConfig) -> Result<Self> {
+        if config.prometheus_url.is_empty() {
+            anyhow::bail!("prometheus_url must be set when source = \"prometheus\"");
+        }
+
+        let matchers = parse_label_selector(&config.prometheus_selector)?;
+        let interfaces = parse_interface_list(&config.interface);
+        if matchers.is_empty() && interfaces.is_empty() {
+            anyhow::bail!("No network interface or prometheus_selector configured");
+        }
+
+        let client = reqwest::Client::builder().timeout(PROMETHEUS_TIMEOUT).build()?;
+
+        let period = Duration::from_micros((config.sample_interval_ms.max(10.0) * 1000.0) as u64);
+        let mut ticker = tokio::time::interval(period);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        Ok(PrometheusSource {
+            client,
+            url: config.prometheus_url.clone(),
+            rx_metric: config.prometheus_rx_metric.clone(),
+            tx_metric: config.prometheus_tx_metric.clone(),
+            matchers,
+            interfaces,
+            ticker,
+            tracker: BandwidthTracker::new(config.sample_interval_ms),
+            pending: VecDeque::new(),
+            sample_interval_ms: config.sample_interval_ms,
+            failed_scrapes: 0,
+        })
+    }
+
+    // Check the exporter answers again, with fresh counters so the outage isn't averaged in
+    async fn resync(&mut self) -> Result<()> {
+        self.scrape().await?;
+        self.tracker = BandwidthTracker {
+            link_limit_kbps: self.tracker.link_limit_kbps,
+            ..BandwidthTracker::new(self.sample_interval_ms)
+        };
+        self.pending.clear();
+        self.failed_scrapes = 0;
+        Ok(())
+    }
+
+    fn selects(&self, labels: &[(String, String)]) -> bool {
+        if self.matchers.is_empty() {
+            labels
+                .iter()
+                .any(|(k, v)| k == "device" && self.interfaces.iter().any(|iface| iface == v))
+        } else {
+            self.matchers.iter().all(|m| m.matches(labels))
+        }
+    }
+
+    async fn scrape(&self) -> Result<String> {
+        let response = self
+            .client
+            .get(&self.url)
+            .header("Accept", "text/plain;version=0.0.4")
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.text().await?)
+    }
+
+    async fn read_sample(&mut self) -> Result<Option<BandwidthSample>> {
+        loop {
+            if let Some(sample) = self.pending.pop_front() {
+                return Ok(Some(sample));
+            }
+
+            self.ticker.tick().await;
+
+            // A failed scrape just skips this interval, repeated failures end the source
+            let body = match self.scrape().await {
+                Ok(b) => {
+                    self.failed_scrapes = 0;
+                    b
+                }
+                Err(e) => {
+                    self.failed_scrapes += 1;
+                    if self.failed_scrapes >= PROMETHEUS_MAX_FAILED_SCRAPES {
+                        self.failed_scrapes = 0;
+                        return Err(e);
+                    }
+                    continue;
+                }
+            };
+            let sampled_at = Instant::now();
+
+            // Pair up RX and TX counters by their label set, e.g. {device="eth0",instance="..."}
+            let mut counters: Vec<(String, Option<u64>, Option<u64>)> = Vec::new();
+            for series in body.lines().filter_map(parse_prometheus_line) {
+                let is_rx = series.name == self.rx_metric;
+                if !is_rx && series.name != self.tx_metric {
+                    continue;
+                }
+                if !self.selects(&series.labels) || !series.value.is_finite() || series.value < 0.0 {
+                    continue;
+                }
+
+                let mut labels = series.labels;
+                labels.sort();
+                let key = labels
+                    .iter()
+                    .map(|(k, v)| format!("{}=\"{}\"", k, v))
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                let idx = match counters.iter().position(|(k, _, _)| *k == key) {
+                    Some(idx) => idx,
+                    None => {
+                        counters.push((key, None, None));
+                        counters.len() - 1
+                    }
+                };
+                if is_rx {
+                    counters[idx].1 = Some(series.value as u64);
+                } else {
+                    counters[idx].2 = Some(series.value as u64);
+                }
+            }
+
+            // Every matching series counts as one interface, summed in the order they were scraped
+            let mut samples = Vec::new();
+            for (key, rx_bytes, tx_bytes) in counters {
+                if let (Some(rx_bytes), Some(tx_bytes)) = (rx_bytes, tx_bytes) {
+                    samples.extend(self.tracker.update_counters(&key, rx_bytes, tx_bytes, sampled_at));
+                }
+            }
+            self.pending.extend(combine_samples(samples, &[]));
+        }
+    }
+}
+
+impl BandwidthSource for PrometheusSource {
+    fn metadata(&self) -> SourceMetadata {
+        let selector = if self.matchers.is_empty() {
+            format!("device={}", self.interfaces.join(","))
+        } else {
+            self.matchers
+                .iter()
+                .map(|m| format!("{}{}\"{}\"", m.label, if m.negate { "!=" } else { "=" }, m.value))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        SourceMetadata {
+            kind: "prometheus",
+            description: format!("{}{{{}}} from {}", self.rx_metric, selector, self.url),
+        }
+    }
+
+    fn next_sample(&mut self) -> SampleFuture<'_> {
+        Box::pin(self.read_sample())
+    }
+
+    fn take_notices(&mut self) -> Vec<String> {
+        self.tracker.take_notices()
+    }
+
+    fn set_link_limit_kbps(&mut self, kbps: f64) {
+        self.tracker.link_limit_kbps = kbps;
+    }
+
+    fn can_reconnect(&self) -> bool {
+        true
+    }
+
+    fn reconnect(&mut self) -> ConnectFuture<'_> {
+        Box::pin(self.resync())
+    }
+}
+
+fn get_timestamp() -> String {
+    let now = SystemTime::now();
+    let duration = now.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+    let secs = duration.as_secs();
+    let millis = duration.subsec_millis();
+
+    // Format as HH:MM:SS.mmm
+    let hours = (secs / 3600) % 24;
+    let minutes = (secs / 60) % 60;
+    let seconds = secs % 60;
+
+    format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, millis)
+}
+
+// Cumulative packet counters of one interface, as read from /proc/net/dev or netstat
+#[derive(Debug, Clone, Copy, Default)]
+struct PacketCounters {
+    rx_packets: u64,
+    tx_packets: u64,
+    rx_errors: u64,
+    tx_errors: u64,
+    rx_drops: u64,
+    tx_drops: u64,
+}
+
+// State for tracking bandwidth calculation per interface
+struct InterfaceState {
+    prev_rx_bytes: u64,
+    prev_tx_bytes: u64,
+    prev_packets: Option<PacketCounters>,
+    prev_time: Instant,
+    prev_remote_time: Option<f64>,
+    // Width the counters wrap at: configured for SNMP, otherwise 32 until a reading needs more
+    counter_bits: u32,
+}
+
+struct BandwidthTracker {
+    interfaces: std::collections::HashMap<String, InterfaceState>,
+    // Nominal sample interval, used for sources that already report per-interval deltas
+    sample_interval_secs: f64,
+    // Counter wraps and resets waiting to be logged
+    notices: Vec<String>,
+    // Detected link speed, or DEFAULT_LINK_LIMIT_KBPS: a wrap implying more than this is really a reset
+    link_limit_kbps: f64,
+    // Seconds on the remote host's clock when the current batch of lines was read (SSH only)
+    remote_time: Option<f64>,
+}
+
+impl BandwidthTracker {
+    // Without a detected link speed, only a wrap faster than 100 Gbps is taken for a reset
+    const DEFAULT_LINK_LIMIT_KBPS: f64 = 100_000_000.0;
+
+    fn new(sample_interval_ms: f64) -> Self {
+        BandwidthTracker {
+            interfaces: std::collections::HashMap::new(),
+            sample_interval_secs: sample_interval_ms.max(10.0) / 1000.0,
+            notices: Vec::new(),
+            link_limit_kbps: Self::DEFAULT_LINK_LIMIT_KBPS,
+            remote_time: None,
+        }
+    }
+
+    // Parse /proc/net/dev line and accumulate bandwidth
+    // Returns Some when all interfaces have been processed (after collecting all lines)
+    fn update_from_procnet_line(&mut self, line: &str, now: Instant) -> Option<BandwidthSample> {
+        // Format: "  eth9: 12345 ... (16 fields total)"
+        let parts: Vec<&str> = line.split(':').collect();
+        if parts.len() != 2 {
+            return None;
+        }
+
+        let iface = parts[0].trim();
+        let fields: Vec<&str> = parts[1].trim().split_whitespace().collect();
+
+        // /proc/net/dev format:
+        // RX: bytes packets errs drop fifo frame compressed multicast
+        // TX: bytes packets errs drop fifo colls carrier compressed
+        if fields.len() < 16 {
+            return None;
+        }
+
+        let rx_bytes = fields[0].parse::<u64>().ok()?;
+        let tx_bytes = fields[8].parse::<u64>().ok()?;
+        let field = |i: usize| fields[i].parse::<u64>().unwrap_or(0);
+        let packets = PacketCounters {
+            rx_packets: field(1),
+            tx_packets: field(9),
+            rx_errors: field(2),
+            tx_errors: field(10),
+            rx_drops: field(3),
+            tx_drops: field(11),
+        };
+
+        self.update_counters_with(iface, rx_bytes, tx_bytes, Some(packets), None, now)
+    }
+
+    // Parse a cumulative macOS `netstat -ibn` link-layer row
+    // Format: "en0 1500 <Link#6> aa:bb:cc:dd:ee:ff Ipkts Ierrs Ibytes Opkts Oerrs Obytes Coll"
+    fn update_from_netstat_counters_line(&mut self, line: &str, now: Instant) -> Option<BandwidthSample> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        // The address column is missing for interfaces without a MAC, so index from the end
+        if parts.len() < 10 || !parts[2].starts_with("<Link#") {
+            return None;
+        }
+
+        let n = parts.len();
+        let rx_bytes = parts[n - 5].parse::<u64>().ok()?;
+        let tx_bytes = parts[n - 2].parse::<u64>().ok()?;
+        // netstat -ibn has no drop columns; errors may be "-" on some interfaces
+        let field = |i: usize| parts[n - i].parse::<u64>().unwrap_or(0);
+        let packets = PacketCounters {
+            rx_packets: field(7),
+            tx_packets: field(4),
+            rx_errors: field(6),
+            tx_errors: field(3),
+            ..PacketCounters::default()
+        };
+
+        self.update_counters_with(parts[0], rx_bytes, tx_bytes, Some(packets), None, now)
+    }
+
+    // Turn cumulative 64-bit byte counters (Prometheus) into kbps, any decrease is a reset
+    fn update_counters(&mut self, iface: &str, rx_bytes: u64, tx_bytes: u64, now: Instant) -> Option<BandwidthSample> {
+        self.update_counters_with(iface, rx_bytes, tx_bytes, None, Some(64), now)
+    }
+
+    // Same as update_counters, for counters that wrap at 2^counter_bits (SNMP ifInOctets/ifHCInOctets)
+    fn update_wrapping_counters(
+        &mut self,
+        iface: &str,
+        rx_bytes: u64,
+        tx_bytes: u64,
+        counter_bits: u32,
+        now: Instant,
+    ) -> Option<BandwidthSample> {
+        self.update_counters_with(iface, rx_bytes, tx_bytes, None, Some(counter_bits), now)
+    }
+
+    // Wrap and reset notices since the last call, for the TUI log
+    fn take_notices(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.notices)
+    }
+
+    // Turn cumulative counters into kbps using the real time elapsed between samples,
+    // measured on the remote host's clock when both readings carry a remote timestamp.
+    // Without a known counter width (/proc/net/dev, netstat) an interface counts as 32-bit
+    // until one of its byte counters goes past 2^32. A reset while a counter sits in the upper
+    // half of that range looks like a wrap, so a wrap faster than the link limit counts as a reset
+    fn update_counters_with(
+        &mut self,
+        iface: &str,
+        rx_bytes: u64,
+        tx_bytes: u64,
+        packets: Option<PacketCounters>,
+        counter_bits: Option<u32>,
+        now: Instant,
+    ) -> Option<BandwidthSample> {
+        let counter_bits = counter_bits.unwrap_or(match self.interfaces.get(iface) {
+            Some(state) if state.counter_bits == 64 => 64,
+            _ if rx_bytes.max(tx_bytes) > u32::MAX as u64 => 64,
+            _ => 32,
+        });
+        let previous = self.interfaces.insert(
+            iface.to_string(),
+            InterfaceState {
+                prev_rx_bytes: rx_bytes,
+                prev_tx_bytes: tx_bytes,
+                prev_packets: packets,
+                prev_time: now,
+                prev_remote_time: self.remote_time,
+                counter_bits,
+            },
+        );
+
+        // First reading - just store values
+        let state = previous?;
+
+        let time_delta = match (state.prev_remote_time, self.remote_time) {
+            (Some(prev), Some(current)) => current - prev,
+            _ => now.duration_since(state.prev_time).as_secs_f64(),
+        };
+        if time_delta <= 0.0 {
+            return None;
+        }
+
+        let delta = |prev: u64, current: u64| counter_delta(prev, current, counter_bits);
+        let link_limit_kbps = self.link_limit_kbps;
+        let byte_delta = |prev: u64, current: u64| {
+            delta(prev, current).filter(|&bytes| {
+                let kbps = (bytes as f64 * 8.0) / (time_delta * 1000.0);
+                current >= prev || kbps <= link_limit_kbps
+            })
+        };
+        let (Some(rx_delta), Some(tx_delta)) = (byte_delta(state.prev_rx_bytes, rx_bytes), byte_delta(state.prev_tx_bytes, tx_bytes)) else {
+            // The new readings become the baseline for the next sample
+            self.notices.push(format!("{}: counters were reset, sample skipped", iface));
+            return None;
+        };
+        for (direction, prev, current) in [("RX", state.prev_rx_bytes, rx_bytes), ("TX", state.prev_tx_bytes, tx_bytes)] {
+            if current < prev {
+                self.notices.push(format!("{}: {} byte counter wrapped at {} bits", iface, direction, counter_bits));
+            }
+        }
+        let rx_delta = rx_delta as f64;
+        let tx_delta = tx_delta as f64;
+
+        // Calculate kbps: (bytes * 8) / (time_seconds * 1000)
+        let rx_kbps = (rx_delta * 8.0) / (time_delta * 1000.0);
+        let tx_kbps = (tx_delta * 8.0) / (time_delta * 1000.0);
+
+        // Packet counters that reset on their own just count as no packets for this sample
+        let packets = match (state.prev_packets, packets) {
+            (Some(prev), Some(current)) => {
+                let count = |prev: u64, current: u64| delta(prev, current).unwrap_or(0);
+                PacketStats {
+                    rx_pps: count(prev.rx_packets, current.rx_packets) as f64 / time_delta,
+                    tx_pps: count(prev.tx_packets, current.tx_packets) as f64 / time_delta,
+                    rx_errors: count(prev.rx_errors, current.rx_errors),
+                    tx_errors: count(prev.tx_errors, current.tx_errors),
+                    rx_drops: count(prev.rx_drops, current.rx_drops),
+                    tx_drops: count(prev.tx_drops, current.tx_drops),
+                }
+            }
+            _ => PacketStats::default(),
+        };
+
+        // Return the bandwidth for this interface
+        Some(BandwidthSample {
+            timestamp: now,
+            rx_kbps,
+            tx_kbps,
+            packets,
+            interfaces: vec![InterfaceReading {
+                name: iface.to_string(),
+                rx_kbps,
+                tx_kbps,
+                packets,
+            }],
+        })
+    }
+}
+
+// Delta between two readings of a counter that wraps at 2^counter_bits
+// Going backwards by more than half the counter range is a reset rather than a wrap, so there is no delta
+fn counter_delta(prev: u64, current: u64, counter_bits: u32) -> Option<u64> {
+    if current >= prev {
+        return Some(current - prev);
+    }
+
+    let range = 1u128 << counter_bits;
+    if prev as u128 >= range {
+        return None;
+    }
+
+    let wrapped = range - prev as u128 + current as u128;
+    if wrapped < range / 2 {
+        Some(wrapped as u64)
+    } else {
+        None
+    }
+}
+
+fn parse_bandwidth_line(line: &str, now: Instant, tracker: &mut BandwidthTracker) -> Option<BandwidthSample> {
+    // Remote clock ahead of a batch: "@<secs>.<nanos>" from `date +@%s.%N`
+    // A date without %N support gives no usable time, so the batch falls back to arrival time
+    if let Some(secs) = line.trim().strip_prefix('@') {
+        tracker.remote_time = secs.parse::<f64>().ok().filter(|secs| secs.is_finite());
+        return None;
+    }
+
+    let parts: Vec<&str> = line.trim().split_whitespace().collect();
+
+    // macOS netstat -w format: 7 columns (packets errs bytes packets errs bytes colls)
+    // Column 2 = input bytes, Column 5 = output bytes, both per sample interval
+    if parts.len() == 7 {
+        let rx_bytes = parts[2].parse::<f64>().ok()?;
+        let tx_bytes = parts[5].parse::<f64>().ok()?;
+
+        // Convert bytes per interval to kbps
+        let interval = tracker.sample_interval_secs;
+        let rx_kbps = (rx_bytes * 8.0) / (interval * 1000.0);
+        let tx_kbps = (tx_bytes * 8.0) / (interval * 1000.0);
+
+        // Packets and errs are per interval too; netstat -w has no drop columns
+        let field = |i: usize| parts[i].parse::<u64>().unwrap_or(0);
+        let packets = PacketStats {
+            rx_pps: field(0) as f64 / interval,
+            tx_pps: field(3) as f64 / interval,
+            rx_errors: field(1),
+            tx_errors: field(4),
+            ..PacketStats::default()
+        };
+
+        Some(BandwidthSample {
+            timestamp: now,
+            rx_kbps,
+            tx_kbps,
+            packets,
+            interfaces: Vec::new(),
+        })
+    }
+    // macOS netstat -ibn format: cumulative counters on the <Link#N> row (checked before ':' since it has a MAC)
+    else if line.contains("<Link#") {
+        tracker.update_from_netstat_counters_line(line, now)
+    }
+    // Linux /proc/net/dev format: interface: rx_bytes ... (has colon)
+    else if line.contains(':') {
+        tracker.update_from_procnet_line(line, now)
+    } else {
+        None
+    }
+}
+
+fn parse_led_numbers(test_str: &str) -> Result<Vec<usize>> {
+    let mut leds = Vec::new();
+
+    for part in test_str.split(',') {
+        let part = part.trim();
+        if part.contains('-') {
+            let range_parts: Vec<&str> = part.split('-').collect();
+            if range_parts.len() == 2 {
+                let start = range_parts[0].parse::<usize>()?;
+                let end = range_parts[1].parse::<usize>()?;
+                for i in start..=end {
+                    leds.push(i);
+                }
+            }
+        } else {
+            leds.push(part.parse::<usize>()?);
+        }
+    }
+
+    Ok(leds)
+}
+
+async fn test_mode(args: &Args) -> Result<()> {
+    let test_str = args.test.as_ref().unwrap();
+    let led_numbers = parse_led_numbers(test_str)?;
+
+    let default_wled = "led.local".to_string();
+    let wled_ip = args.wled_ip.as_ref().unwrap_or(&default_wled);
+
+    println!("Test mode: blinking LEDs {:?}", led_numbers);
+    println!("Connecting to WLED at {}:4048", wled_ip);
+
+    let dest_addr = format!("{}:4048", wled_ip);
+    let socket = UdpSocket::bind("0.0.0.0:4048")?;
+    let mut ddp_conn =
+        DDPConnection::try_new(&dest_addr, PixelConfig::default(), ID::Default, socket)?;
+
+    println!("Connected! Starting blink loop...");
+
+    let test_color = Rgb::from_hex("FF0000")?;
+    let mut iteration = 0;
+
+    loop {
+        iteration += 1;
+        println!("\n=== Iteration {} ===", iteration);
+
+        println!("Turning ON LEDs {:?}", led_numbers);
+        let max_led = led_numbers.iter().max().copied().unwrap_or(0);
+        let frame_size = (max_led + 1) * 3;
+        let mut frame = vec![0u8; frame_size];
+
+        for &led_num in &led_numbers {
+            let offset = led_num * 3;
+            frame[offset] = test_color.r;
+            frame[offset + 1] = test_color.g;
+            frame[offset + 2] = test_color.b;
+        }
+
+        ddp_conn.write_offset(&frame, 0)?;
+        println!("Sent {} bytes starting at offset 0", frame.len());
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+        println!("Turning OFF LEDs");
+        let frame = vec![0u8; frame_size];
+        ddp_conn.write_offset(&frame, 0)?;
+        println!("Sent {} bytes of black", frame.len());
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+    }
+}
+// HTTP Configuration Server Module
+
+const WEB_UI_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Bandwidth Meter Configuration</title>
+    <style>
+        * { margin: 0; padding: 0; box-sizing: border-box; }
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, sans-serif;
+            background: #1a1a1a;
+            color: #e0e0e0;
+            padding: 20px;
+            line-height: 1.6;
+        }
+        .container {
+            max-width: 900px;
+            margin: 0 auto;
+        }
+        h1 {
+            color: #00aaff;
+            margin-bottom: 30px;
+            font-size: 2em;
+        }
+        .config-grid {
+            display: grid;
+            gap: 15px;
+        }
+        .config-item {
+            margin-bottom: 12px;
+        }
+        .config-item label {
+            display: block;
+            color: #b0b0b0;
+            margin-bottom: 8px;
+            font-size: 0.9em;
+            text-transform: uppercase;
+            letter-spacing: 0.5px;
+        }
+        .input-group {
+            display: flex;
+            gap: 10px;
+            align-items: center;
+        }
+        input[type="text"], input[type="number"], select, textarea {
+            flex: 1;
+            background: #1a1a1a;
+            border: 1px solid #505050;
+            color: #e0e0e0;
+            padding: 10px 12px;
+            border-radius: 4px;
+            font-size: 1em;
+        }
+        input.invalid {
+            border: 2px solid #ff4444;
+            background: #3a1a1a;
+        }
+        button:disabled {
+            background: #555555;
+            cursor: not-allowed;
+            opacity: 0.5;
+        }
+        input[type="checkbox"] {
+            width: 20px;
+            height: 20px;
+            cursor: pointer;
+        }
+        input[type="range"] {
+            flex: 1;
+            cursor: pointer;
+        }
+        .range-value {
+            min-width: 80px;
+            text-align: center;
+            color: #00aaff;
+            font-weight: 600;
+        }
+        button {
+            background: #00aaff;
+            color: white;
+            border: none;
+            padding: 10px 20px;
+            border-radius: 4px;
+            cursor: pointer;
+            font-size: 0.9em;
+            font-weight: 600;
+            transition: background 0.2s;
+        }
+        button:hover {
+            background: #0088cc;
+        }
+        button:active {
+            background: #006699;
+        }
+        .message {
+            position: fixed;
+            top: 20px;
+            right: 20px;
+            padding: 15px 20px;
+            border-radius: 4px;
+            font-weight: 500;
+            opacity: 0;
+            transition: opacity 0.3s;
+            z-index: 1000;
+        }
+        .message.show {
+            opacity: 1;
+        }
+        .message.success {
+            background: #2d5016;
+            border: 1px solid #4a8028;
+            color: #a3d977;
+        }
+        .message.error {
+            background: #5a1a1a;
+            border: 1px solid #902020;
+            color: #ff9090;
+        }
+        .help-text {
+            font-size: 0.85em;
+            color: #808080;
+            margin-top: 4px;
+        }
+        .section {
+            background: #2a2a2a;
+            border: 1px solid #404040;
+            border-radius: 8px;
+            padding: 20px;
+            margin-bottom: 20px;
+        }
+        .section-header {
+            color: #00aaff;
+            font-size: 1.3em;
+            font-weight: 600;
+            margin-bottom: 20px;
+            padding-bottom: 10px;
+            border-bottom: 2px solid #404040;
+        }
+        .testing-grid {
+            display: flex;
+            justify-content: center;
+            gap: 40px;
+            align-items: center;
+        }
+        .testing-item {
+            display: flex;
+            align-items: center;
+            gap: 10px;
+        }
+        .testing-item label {
+            margin: 0;
+            cursor: pointer;
+        }
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>Bandwidth Meter Configuration</h1>
+        <div id="config-container"></div>
+    </div>
+    <div id="message" class="message"></div>
+
+    <script>
+        const fieldSections = [
+            {
+                title: 'Testing',
+                isTesting: true,
+                help: 'Enable to simulate bandwidth utilization for testing purposes',
+                fields: [
+                    { name: 'test_tx', label: 'TX (Upload)', type: 'checkbox' },
+                    { name: 'test_tx_percent', label: 'TX Utilization', type: 'range', min: '0', max: '100', step: '1' },
+                    { name: 'test_rx', label: 'RX (Download)', type: 'checkbox' },
+                    { name: 'test_rx_percent', label: 'RX Utilization', type: 'range', min: '0', max: '100', step: '1' },
+                ]
+            },
+            {
+                title: 'Networking',
+                fields: [
+                    { name: 'interface', label: 'Network Interface', type: 'text', help: 'Interface to monitor, e.g. en0. Multiple interfaces can be comma-separated: en0,en1 (requires restart)' },
+                    { name: 'source', label: 'Bandwidth Source', type: 'select', options: ['auto', 'snmp', 'prometheus'], help: 'auto = netstat/procfs locally or SSH with -H; snmp = poll an SNMP agent; prometheus = scrape a metrics endpoint (requires restart)' },
+                    { name: 'sample_interval_ms', label: 'Sample Interval (ms)', type: 'number', step: '10', help: 'How often interface counters are sampled, e.g. 1000, 250 or 100 (requires restart)' },
+                    { name: 'snmp_host', label: 'SNMP Host', type: 'text', help: 'SNMP agent address when source = snmp (requires restart)' },
+                    { name: 'snmp_port', label: 'SNMP Port', type: 'number', step: '1', help: 'SNMP agent UDP port, usually 161 (requires restart)' },
+                    { name: 'snmp_version', label: 'SNMP Version', type: 'select', options: ['2c', '3'], help: 'SNMP protocol version (requires restart)' },
+                    { name: 'snmp_community', label: 'SNMP Community', type: 'password', help: 'Community string for SNMP v2c, only saved when edited (requires restart)' },
+                    { name: 'snmp_username', label: 'SNMP v3 User', type: 'text', help: 'USM user name for SNMP v3 (requires restart)' },
+                    { name: 'snmp_auth_protocol', label: 'SNMP v3 Auth', type: 'select', options: ['none', 'md5', 'sha'], help: 'USM authentication protocol, privacy is not supported (requires restart)' },
+                    { name: 'snmp_auth_password', label: 'SNMP v3 Auth Password', type: 'password', help: 'USM authentication password, at least 8 characters, only saved when edited (requires restart)' },
+                    { name: 'prometheus_url', label: 'Prometheus URL', type: 'text', help: 'Metrics endpoint when source = prometheus, e.g. http://server:9100/metrics (requires restart)' },
+                    { name: 'prometheus_rx_metric', label: 'Prometheus RX Metric', type: 'text', help: 'Receive byte counter, e.g. node_network_receive_bytes_total (requires restart)' },
+                    { name: 'prometheus_tx_metric', label: 'Prometheus TX Metric', type: 'text', help: 'Transmit byte counter, e.g. node_network_transmit_bytes_total (requires restart)' },
+                    { name: 'prometheus_selector', label: 'Prometheus Selector', type: 'text', help: 'Label selector such as device=\"eth0\"; empty matches device against the interface setting (requires restart)' },
+                    { name: 'max_gbps', label: 'Max Bandwidth (Gbps)', type: 'number', step: '0.1', help: 'Maximum bandwidth in Gbps for visualization scaling' },
+                    { name: 'scale', label: 'Bandwidth Scale', type: 'select', options: ['linear', 'log', 'sqrt', 'power'], help: 'How bandwidth maps onto strip length. log and sqrt make light traffic visible on a fast link' },
+                    { name: 'scale_log_floor_kbps', label: 'Log Scale Floor (kbps)', type: 'number', step: '0.1', help: 'Bandwidth at which the log scale starts lighting LEDs, e.g. 1 kbps' },
+                    { name: 'scale_exponent', label: 'Power Scale Exponent', type: 'number', step: '0.05', help: 'Exponent for the power scale (0.5 = sqrt, lower expands light traffic further)' },
+                    { name: 'detect_link_speed', label: 'Detect Link Speed', type: 'checkbox', help: 'Use the interface link speed instead of Max Bandwidth (requires restart)' },
+                    { name: 'auto_range', label: 'Auto-Range', type: 'checkbox', help: 'Scale to a decaying peak of recent traffic so the whole strip is used on quiet links' },
+                    { name: 'auto_range_window_secs', label: 'Auto-Range Window (s)', type: 'number', step: '1', help: 'How quickly the auto-range peak decays' },
+                    { name: 'auto_range_min_mbps', label: 'Auto-Range Minimum (Mbps)', type: 'number', step: '1', help: 'Lowest max auto-range will scale to, keeps idle links dark' },
+                    { name: 'fill_metric', label: 'Fill Metric', type: 'select', options: ['bandwidth', 'packets'], help: 'Fill the bars by bandwidth or by packets per second (packets need the local, netstat or SSH source)' },
+                    { name: 'max_pps', label: 'Max Packets/s', type: 'number', step: '1000', help: 'Packets per second that fill the strip when the fill metric is packets' },
+                    { name: 'wled_ip', label: 'WLED IP Address', type: 'text', help: 'WLED device IP or hostname (requires restart)' },
+                    { name: 'protocol', label: 'Output Protocol', type: 'select', options: ['ddp', 'e131', 'artnet', 'wled', 'warls', 'drgb', 'dnrgb', 'json', 'opc'], help: 'ddp (port 4048), e131/sACN (port 5568), artnet (port 6454), WLED realtime UDP (port 21324), the slow JSON API fallback or OPC over TCP (port 7890) (requires restart)' },
+                    { name: 'universe', label: 'Start Universe', type: 'number', step: '1', help: 'First DMX universe for e131 and artnet (requires restart)' },
+                    { name: 'start_channel', label: 'Start Channel', type: 'number', step: '1', help: 'DMX channel (1-512) of the first pixel in the first universe (requires restart)' },
+                    { name: 'e131_multicast', label: 'E1.31 Multicast', type: 'checkbox', help: 'Send e131 to 239.255.<universe> instead of the WLED address (requires restart)' },
+                    { name: 'opc_channel', label: 'OPC Channel', type: 'number', step: '1', help: 'Open Pixel Control channel, 0 for all (requires restart)' },
+                    { name: 'pixel_format', label: 'Pixel Format', type: 'select', options: ['rgb', 'grb', 'bgr', 'rbg', 'gbr', 'brg', 'rgbw', 'grbw', 'bgrw', 'wrgb'], help: 'Channel order of the strip, w for RGBW strips. WLED UDP, JSON and OPC always send RGB (requires restart)' },
+                    { name: 'white_mode', label: 'White Mode', type: 'select', options: ['accurate', 'brighter', 'none'], help: 'How the white channel is derived on RGBW formats (requires restart)' },
+                    { name: 'httpd_ip', label: 'HTTP Server IP', type: 'text', help: 'IP address to listen on (0.0.0.0 for all)' },
+                    { name: 'httpd_port', label: 'HTTP Server Port', type: 'number', step: '1', help: 'Port for HTTP server' },
+                ]
+            },
+            {
+                title: 'LED Layout',
+                fields: [
+                    { name: 'direction', label: 'Fill Direction', type: 'select', options: ['mirrored', 'opposing', 'left', 'right'], help: 'How LEDs fill across the strip' },
+                    { name: 'swap', label: 'Swap TX/RX Halves', type: 'checkbox', help: 'Swap which half shows TX vs RX' },
+                    { name: 'total_leds', label: 'Total LEDs', type: 'number', step: '1', help: 'Total number of LEDs in strip' },
+                    { name: 'rx_split_percent', label: 'RX/TX LED Split', type: 'range', min: '0', max: '100', step: '1', help: 'Percentage of LEDs allocated to RX. TX gets the remainder. (50 = 50/50, 70 = 70/30)' },
+                    { name: 'segments', label: 'Segment Map', type: 'text', help: 'Optional LED runs as first-last:role (tx, rx, status, unused), e.g. 0-59:rx, 60-61:unused, 119-62:tx, 120:status. Replaces direction and split' },
+                    { name: 'matrix_width', label: 'Matrix Width', type: 'number', step: '1', help: 'Width of a 2D panel in LEDs (0 = normal strip)' },
+                    { name: 'matrix_height', label: 'Matrix Height', type: 'number', step: '1', help: 'Height of a 2D panel in LEDs (0 = normal strip)' },
+                    { name: 'matrix_serpentine', label: 'Serpentine Wiring', type: 'checkbox', help: 'Every other row of the panel runs right to left' },
+                    { name: 'matrix_rotation', label: 'Matrix Rotation', type: 'number', step: '90', help: 'Clockwise rotation of the panel as mounted: 0, 90, 180 or 270' },
+                    { name: 'matrix_mode', label: 'Matrix Mode', type: 'select', options: ['bars', 'history', 'spectrum'], help: 'bars: vertical RX/TX bar graphs. history: scrolling columns. spectrum: scrolling rows from the center' },
+                ]
+            },
+            {
+                title: 'Color Settings',
+                fields: [
+                    { name: 'color', label: 'Default Color', type: 'textarea', help: 'Hex color or gradient (e.g., FF0000,00FF00,0000FF)' },
+                    { name: 'tx_color', label: 'TX (Upload) Color', type: 'textarea', help: 'Overrides default color for TX. Leave empty to use default.' },
+                    { name: 'rx_color', label: 'RX (Download) Color', type: 'textarea', help: 'Overrides default color for RX. Leave empty to use default.' },
+                    { name: 'use_gradient', label: 'Use Gradient Blending', type: 'checkbox', help: 'Smooth gradients vs hard color segments' },
+                    { name: 'interpolation', label: 'Gradient Interpolation', type: 'select', options: ['linear', 'basis', 'catmullrom'], help: 'Gradient interpolation algorithm' },
+                    { name: 'color_mode', label: 'Color Mode', type: 'select', options: ['position', 'zones', 'interfaces'], help: 'position: colors by LED position with animation. zones: whole bar colored by its fill level. interfaces: each monitored interface gets its own band' },
+                    { name: 'display_mode', label: 'Display Mode', type: 'select', options: ['bar', 'history'], help: 'bar: current utilization. history: scrolling time series, one sample per LED group' },
+                    { name: 'history_style', label: 'History Style', type: 'select', options: ['brightness', 'hue'], help: 'brightness: color dimmed to utilization. hue: blue (idle) to red (max)' },
+                    { name: 'history_leds_per_sample', label: 'History LEDs per Sample', type: 'number', step: '1', help: 'Width of each sample in history mode' },
+                    { name: 'zone_colors', label: 'Zone Colors', type: 'textarea', help: 'Colors with optional @percent stops, e.g. 00FF00,FFBF00@60,FF0000@85' },
+                    { name: 'interface_colors', label: 'Interface Colors', type: 'textarea', help: 'Band colors for the interfaces color mode, one per interface in order' },
+                    { name: 'gamma', label: 'Gamma', type: 'number', step: '0.1', help: 'Gamma curve for every frame (1.0 = off, 2.2-2.8 looks natural on LEDs)' },
+                    { name: 'brightness_percent', label: 'Brightness', type: 'range', min: '0', max: '100', step: '1', help: 'Global brightness in percent' },
+                    { name: 'power_limit_ma', label: 'Power Limit (mA)', type: 'number', step: '100', help: 'Dim frames that would draw more than this estimated current (0 = disabled)' },
+                    { name: 'milliamps_per_channel', label: 'mA per LED Channel', type: 'number', step: '1', help: 'Current of one LED channel at full brightness, used by the power limit' },
+                ]
+            },
+            {
+                title: 'Schedule',
+                fields: [
+                    { name: 'schedule', label: 'Brightness Schedule', type: 'textarea', help: 'Comma-separated HH:MM=percent entries in local time, e.g. 07:00=100, 22:00=30, 23:30=off. Empty = always full' },
+                    { name: 'schedule_fade_minutes', label: 'Fade (minutes)', type: 'number', step: '1', help: 'Minutes to fade into each new schedule level (0 = instant)' },
+                    { name: 'night_start', label: 'Night Start (HH:MM)', type: 'text', help: 'Start of the night window. Empty disables night mode' },
+                    { name: 'night_end', label: 'Night End (HH:MM)', type: 'text', help: 'End of the night window, may be after midnight' },
+                    { name: 'night_min_utilization_percent', label: 'Night Threshold (%)', type: 'number', step: '1', help: 'At night, only light up while RX or TX is above this percent of max' },
+                ]
+            },
+            {
+                title: 'Animation Settings',
+                fields: [
+                    { name: 'animation_speed', label: 'Animation Speed', type: 'number', step: '0.1', help: 'Speed of gradient animation (0 = disabled)' },
+                    { name: 'scale_animation_speed', label: 'Scale Animation with Bandwidth', type: 'checkbox', help: 'Animation speed scales with bandwidth utilization' },
+                    { name: 'tx_animation_direction', label: 'TX Animation Direction', type: 'radio', options: ['left', 'right'], help: 'Direction TX (upload) animation moves' },
+                    { name: 'rx_animation_direction', label: 'RX Animation Direction', type: 'radio', options: ['left', 'right'], help: 'Direction RX (download) animation moves' },
+                    { name: 'interpolation_time_ms', label: 'Interpolation Time (ms)', type: 'number', step: '10', help: 'Time in milliseconds to smoothly transition between bandwidth readings' },
+                    { name: 'strobe_on_max', label: 'Strobe at Max Bandwidth', type: 'checkbox', help: 'Flash entire RX or TX segment when bandwidth exceeds maximum' },
+                    { name: 'strobe_rate_hz', label: 'Strobe Rate (Hz)', type: 'number', step: '0.1', help: 'Strobe frequency in Hz (flashes per second). Default: 3.0 Hz' },
+                    { name: 'strobe_duration_ms', label: 'Strobe Duration (ms)', type: 'number', step: '1', help: 'Duration of strobe effect in milliseconds. Cannot exceed cycle time (e.g., 3 Hz = 333ms max)' },
+                    { name: 'strobe_color', label: 'Strobe Color (Hex)', type: 'text', help: 'Hex color to display during strobe "off" phase. Default: 000000 (black/off)' },
+                    { name: 'peak_hold', label: 'Peak Hold', type: 'checkbox', help: 'Mark the highest recent TX and RX utilization with a single pixel' },
+                    { name: 'peak_color', label: 'Peak Color (Hex)', type: 'text', help: 'Color of the peak-hold pixel' },
+                    { name: 'tx_peak_color', label: 'TX Peak Color (Hex)', type: 'text', help: 'Overrides peak color for TX. Leave empty to use peak color.' },
+                    { name: 'rx_peak_color', label: 'RX Peak Color (Hex)', type: 'text', help: 'Overrides peak color for RX. Leave empty to use peak color.' },
+                    { name: 'peak_hold_ms', label: 'Peak Hold Time (ms)', type: 'number', step: '100', help: 'How long the marker stays at a new peak before falling' },
+                    { name: 'peak_fall_percent_per_sec', label: 'Peak Fall Rate (%/s)', type: 'number', step: '5', help: 'How fast the marker falls, in percent of the bar per second' },
+                    { name: 'error_sparkle', label: 'Error Sparkle', type: 'checkbox', help: 'Flash sparkle pixels over the RX or TX half for interface errors and drops' },
+                    { name: 'error_color', label: 'Error Color (Hex)', type: 'text', help: 'Color of the error sparkles' },
+                    { name: 'stale_timeout_ms', label: 'Stale Timeout (ms)', type: 'number', step: '1000', help: 'Show the no data pattern after this long without a sample. 0 = never' },
+                    { name: 'no_data_pattern', label: 'No Data Pattern', type: 'select', options: ['breathe', 'blink'], help: 'breathe: the whole strip pulses dimly. blink: only the end pixel of each bar blinks' },
+                    { name: 'no_data_color', label: 'No Data Color (Hex)', type: 'text', help: 'Color of the no data pattern. 000000 turns the strip dark.' },
+                    { name: 'fps', label: 'Frame Rate (FPS)', type: 'number', step: '1', help: 'Rendering frame rate. Try 30, 60, 120, or 144' },
+                ]
+            },
+        ];
+
+        let config = {};
+        let pollingInterval = null;
+
+        async function loadConfig() {
+            try {
+                const res = await fetch('/api/config');
+                const newConfig = await res.json();
+
+                // Check if config actually changed
+                const configChanged = JSON.stringify(newConfig) !== JSON.stringify(config);
+
+                config = newConfig;
+
+                // Only re-render if config changed or this is the initial load
+                if (configChanged || pollingInterval === null) {
+                    renderConfig();
+
+                    // Show notification if config was updated externally (not on initial load)
+                    if (configChanged && pollingInterval !== null) {
+                        showMessage('Config reloaded from file', 'success');
+                    }
+                }
+            } catch (e) {
+                showMessage('Failed to load configuration', 'error');
+            }
+        }
+
+        function renderConfig() {
+            const container = document.getElementById('config-container');
+            container.innerHTML = fieldSections.map(section => {
+                // Special handling for Testing section
+                if (section.isTesting) {
+                    const testingHTML = `
+                        <div style="display: grid; grid-template-columns: 1fr 1fr; gap: 30px;">
+                            <div style="text-align: center;">
+                                <div style="margin-bottom: 15px;">
+                                    <label style="display: inline-flex; align-items: center; gap: 8px; cursor: pointer; font-size: 1em;">
+                                        <input type="checkbox" id="test_tx" onchange="saveField('test_tx', 'checkbox')" ${config.test_tx ? 'checked' : ''}>
+                                        <span>TX (Upload)</span>
+                                    </label>
+                                </div>
+                                <div>
+                                    <label style="display: block; font-size: 0.85em; color: #808080; margin-bottom: 8px;">Utilization: <span id="test_tx_percent_value">${(config.test_tx_percent || 100).toFixed(0)}%</span></label>
+                                    <input type="range" id="test_tx_percent" value="${config.test_tx_percent || 100}"
+                                           min="0" max="100" step="1" style="width: 100%;"
+                                           oninput="updateTestRangeValue('test_tx_percent')"
+                                           onchange="saveField('test_tx_percent', 'range')">
+                                </div>
+                            </div>
+                            <div style="text-align: center;">
+                                <div style="margin-bottom: 15px;">
+                                    <label style="display: inline-flex; align-items: center; gap: 8px; cursor: pointer; font-size: 1em;">
+                                        <input type="checkbox" id="test_rx" onchange="saveField('test_rx', 'checkbox')" ${config.test_rx ? 'checked' : ''}>
+                                        <span>RX (Download)</span>
+                                    </label>
+                                </div>
+                                <div>
+                                    <label style="display: block; font-size: 0.85em; color: #808080; margin-bottom: 8px;">Utilization: <span id="test_rx_percent_value">${(config.test_rx_percent || 100).toFixed(0)}%</span></label>
+                                    <input type="range" id="test_rx_percent" value="${config.test_rx_percent || 100}"
+                                           min="0" max="100" step="1" style="width: 100%;"
+                                           oninput="updateTestRangeValue('test_rx_percent')"
+                                           onchange="saveField('test_rx_percent', 'range')">
+                                </div>
+                            </div>
+                        </div>
+                    `;
+
+                    return `
+                        <div class="section">
+                            <div class="section-header">${section.title}</div>
+                            ${testingHTML}
+                            ${section.help ? `<div class="help-text" style="text-align: center; margin-top: 12px;">${section.help}</div>` : ''}
+                        </div>
+                    `;
+                }
+
+                // Regular sections
+                const fieldsHTML = section.fields.map(field => {
+                    const value = config[field.name];
+                    let inputHTML = '';
+                    let saveButton = '';
+
+                    if (field.type === 'checkbox') {
+                        // Checkboxes auto-save on change, no Save button needed
+                        inputHTML = `<input type="checkbox" id="${field.name}" onchange="saveField('${field.name}', '${field.type}')" ${value ? 'checked' : ''}>`;
+                    } else if (field.type === 'range') {
+                        // Range sliders show current value and auto-save on change
+                        const rxValue = value !== undefined && value !== null ? value : 50;
+                        const txSplit = (100 - rxValue).toFixed(0);
+                        inputHTML = `
+                            <input type="range" id="${field.name}" value="${rxValue}"
+                                   min="${field.min || 0}" max="${field.max || 100}" step="${field.step || 1}"
+                                   oninput="updateRangeValue('${field.name}')"
+                                   onchange="saveField('${field.name}', '${field.type}')">
+                            <div class="range-value" id="${field.name}_value">RX ${rxValue.toFixed(0)}% / TX ${txSplit}%</div>
+                        `;
+                    } else if (field.type === 'radio') {
+                        // Radio buttons auto-save on change, no Save button needed
+                        inputHTML = field.options.map(opt => `
+                            <label style="display: inline-flex; align-items: center; gap: 5px; margin-right: 20px; cursor: pointer;">
+                                <input type="radio" name="${field.name}" value="${opt}"
+                                       onchange="saveField('${field.name}', '${field.type}')"
+                                       ${value === opt ? 'checked' : ''}>
+                                <span>${opt}</span>
+                            </label>
+                        `).join('');
+                    } else if (field.type === 'select') {
+                        inputHTML = `<select id="${field.name}">${field.options.map(opt =>
+                            `<option value="${opt}" ${value === opt ? 'selected' : ''}>${opt}</option>`
+                        ).join('')}</select>`;
+                        saveButton = `<button onclick="saveField('${field.name}', '${field.type}')">Save</button>`;
+                    } else if (field.type === 'password') {
+                        // The server never sends secrets back, so the input starts empty and is only saved once edited
+                        const placeholder = config[`${field.name}_set`] ? '(unchanged)' : '(not set)';
+                        inputHTML = `<input type="password" id="${field.name}" autocomplete="new-password" placeholder="${placeholder}" oninput="this.dataset.edited = 'true'">`;
+                        saveButton = `<button onclick="saveField('${field.name}', '${field.type}')">Save</button>`;
+                    } else if (field.type === 'textarea') {
+                        inputHTML = `<textarea id="${field.name}" rows="2" style="resize: vertical; font-family: monospace; overflow: hidden;" oninput="autoResizeTextarea(this)">${value || ''}</textarea>`;
+                        saveButton = `<button onclick="saveField('${field.name}', '${field.type}')">Save</button>`;
+                    } else {
+                        // Special handling for strobe_duration_ms validation
+                        if (field.name === 'strobe_duration_ms') {
+                            inputHTML = `<input type="${field.type}" id="${field.name}" value="${value || ''}" ${field.step ? `step="${field.step}"` : ''} oninput="validateStrobeDuration()">`;
+                            saveButton = `<button id="save_${field.name}" onclick="saveField('${field.name}', '${field.type}')">Save</button>`;
+                        } else {
+                            inputHTML = `<input type="${field.type}" id="${field.name}" value="${value || ''}" ${field.step ? `step="${field.step}"` : ''}>`;
+                            saveButton = `<button onclick="saveField('${field.name}', '${field.type}')">Save</button>`;
+                        }
+                    }
+
+                    // Dynamic help text for strobe_duration_ms
+                    let helpText = '';
+                    if (field.help) {
+                        if (field.name === 'strobe_duration_ms') {
+                            const maxDuration = config.strobe_rate_hz > 0 ? (1000.0 / config.strobe_rate_hz).toFixed(1) : '1000.0';
+                            helpText = `<div class="help-text" id="help_${field.name}">${field.help} Current max: ${maxDuration}ms</div>`;
+                        } else {
+                            helpText = `<div class="help-text">${field.help}</div>`;
+                        }
+                    }
+
+                    return `
+                        <div class="config-item">
+                            <label for="${field.name}">${field.label}</label>
+                            <div class="input-group">
+                                ${inputHTML}
+                                ${saveButton}
+                            </div>
+                            ${helpText}
+                        </div>
+                    `;
+                }).join('');
+
+                return `
+                    <div class="section">
+                        <div class="section-header">${section.title}</div>
+                        <div class="config-grid">
+                            ${fieldsHTML}
+                        </div>
+                    </div>
+                `;
+            }).join('');
+
+            // After rendering, validate strobe duration and auto-size textareas
+            setTimeout(() => {
+                validateStrobeDuration();
+                document.querySelectorAll('textarea').forEach(ta => autoResizeTextarea(ta));
+            }, 0);
+        }
+
+        function autoResizeTextarea(textarea) {
+            // Reset height to auto to get the correct scrollHeight
+            textarea.style.height = 'auto';
+            // Set height to scrollHeight to fit content
+            textarea.style.height = textarea.scrollHeight + 'px';
+        }
+
+        function updateRangeValue(fieldName) {
+            const input = document.getElementById(fieldName);
+            const display = document.getElementById(fieldName + '_value');
+            const rxValue = parseFloat(input.value);
+            const txValue = 100 - rxValue;
+            display.textContent = `RX ${rxValue.toFixed(0)}% / TX ${txValue.toFixed(0)}%`;
+        }
+
+        function updateTestRangeValue(fieldName) {
+            const input = document.getElementById(fieldName);
+            const display = document.getElementById(fieldName + '_value');
+            const value = parseFloat(input.value);
+            display.textContent = `${value.toFixed(0)}%`;
+        }
+
+        function validateStrobeDuration() {
+            const durationInput = document.getElementById('strobe_duration_ms');
+            const saveButton = document.getElementById('save_strobe_duration_ms');
+            const helpText = document.getElementById('help_strobe_duration_ms');
+
+            if (!durationInput || !saveButton) return;
+
+            const duration = parseFloat(durationInput.value);
+            const strobeRateHz = config.strobe_rate_hz || 3.0;
+            const maxDuration = strobeRateHz > 0 ? (1000.0 / strobeRateHz) : 1000.0;
+
+            if (duration > maxDuration || duration < 0 || isNaN(duration)) {
+                // Invalid - highlight red and disable save
+                durationInput.classList.add('invalid');
+                saveButton.disabled = true;
+                if (helpText) {
+                    helpText.innerHTML = `Duration of strobe effect in milliseconds. Cannot exceed cycle time (e.g., 3 Hz = 333ms max) <span style="color: #ff4444; font-weight: bold;">Current max: ${maxDuration.toFixed(1)}ms - Value exceeds maximum!</span>`;
+                }
+            } else {
+                // Valid - remove red and enable save
+                durationInput.classList.remove('invalid');
+                saveButton.disabled = false;
+                if (helpText) {
+                    helpText.innerHTML = `Duration of strobe effect in milliseconds. Cannot exceed cycle time (e.g., 3 Hz = 333ms max) Current max: ${maxDuration.toFixed(1)}ms`;
+                }
+            }
+        }
+
+        async function saveField(fieldName, fieldType) {
+            let value;
+
+            if (fieldType === 'checkbox') {
+                const input = document.getElementById(fieldName);
+                value = input.checked;
+            } else if (fieldType === 'radio') {
+                const selectedRadio = document.querySelector(`input[name="${fieldName}"]:checked`);
+                value = selectedRadio ? selectedRadio.value : null;
+            } else if (fieldType === 'range') {
+                const input = document.getElementById(fieldName);
+                value = parseFloat(input.value);
+            } else if (fieldType === 'number') {
+                const input = document.getElementById(fieldName);
+                value = parseFloat(input.value);
+            } else if (fieldType === 'password') {
+                const input = document.getElementById(fieldName);
+                if (!input.dataset.edited) {
+                    showMessage(`${fieldName} unchanged`, 'success');
+                    return;
+                }
+                value = input.value;
+            } else {
+                const input = document.getElementById(fieldName);
+                value = input.value;
+            }
+
+            try {
+                const res = await fetch('/api/config', {
+                    method: 'POST',
+                    headers: { 'Content-Type': 'application/json' },
+                    body: JSON.stringify({ field: fieldName, value })
+                });
+
+                if (res.ok) {
+                    showMessage(`${fieldName} updated successfully`, 'success');
+                    if (fieldType === 'password') {
+                        // Mirror the server, which only reports whether the secret is set
+                        config[`${fieldName}_set`] = value !== '';
+                        renderConfig();
+                    } else {
+                        config[fieldName] = value;
+                    }
+
+                    // If strobe_rate_hz changed, revalidate strobe_duration_ms
+                    if (fieldName === 'strobe_rate_hz') {
+                        validateStrobeDuration();
+                    }
+                } else {
+                    showMessage(`Failed to update ${fieldName}`, 'error');
+                }
+            } catch (e) {
+                showMessage(`Error updating ${fieldName}`, 'error');
+            }
+        }
+
+        function showMessage(text, type) {
+            const msg = document.getElementById('message');
+            msg.textContent = text;
+            msg.className = `message ${type} show`;
+            setTimeout(() => msg.className = 'message', 3000);
+        }
+
+        // Initial load
+        loadConfig();
+
+        // Start polling for config changes every 2 seconds
+        pollingInterval = setInterval(loadConfig, 2000);
+    </script>
+</body>
+</html>
+"#;
+
+#[derive(Deserialize)]
+struct UpdateField {
+    field: String,
+    value: serde_json::Value,
+}
+
+async fn serve_index() -> impl IntoResponse {
+    Html(WEB_UI_HTML)
+}
+
+// /api/config returns the saved config plus the values currently in effect.
+// SNMP secrets are left out, only whether they are set is reported
+#[derive(Serialize)]
+struct ConfigResponse {
+    #[serde(flatten)]
+    config: BandwidthConfig,
+    #[serde(flatten)]
+    status: MeterStatus,
+    snmp_community_set: bool,
+    snmp_auth_password_set: bool,
+}
+
+async fn get_config(State(status): State<Arc<Mutex<MeterStatus>>>) -> impl IntoResponse {
+    match BandwidthConfig::load() {
+        Ok(config) => {
+            let mut status = status.lock().unwrap().clone();
+            status.last_sample_age_secs = status.last_sample_at.map(|at| at.elapsed().as_secs_f64());
+            let snmp_community_set = !config.snmp_community.is_empty();
+            let snmp_auth_password_set = !config.snmp_auth_password.is_empty();
+            let response = ConfigResponse {
+                config,
+                status,
+                snmp_community_set,
+                snmp_auth_password_set,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn update_config(Json(payload): Json<UpdateField>) -> impl IntoResponse {
+    let mut config = match BandwidthConfig::load() {
+        Ok(c) => c,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let result = match payload.field.as_str() {
+        "max_gbps" => payload.value.as_f64().map(|v| { config.max_gbps = v; }).ok_or("Invalid value"),
+        "scale" => payload.value.as_str().map(|v| { config.scale = v.to_string(); }).ok_or("Invalid value"),
+        "scale_log_floor_kbps" => payload.value.as_f64().map(|v| { config.scale_log_floor_kbps = v.max(0.001); }).ok_or("Invalid value"),
+        "scale_exponent" => payload.value.as_f64().map(|v| { config.scale_exponent = v.max(0.01); }).ok_or("Invalid value"),
+        "detect_link_speed" => payload.value.as_bool().map(|v| { config.detect_link_speed = v; }).ok_or("Invalid value"),
+        "auto_range" => payload.value.as_bool().map(|v| { config.auto_range = v; }).ok_or("Invalid value"),
+        "auto_range_window_secs" => payload.value.as_f64().map(|v| { config.auto_range_window_secs = v.max(1.0); }).ok_or("Invalid value"),
+        "auto_range_min_mbps" => payload.value.as_f64().map(|v| { config.auto_range_min_mbps = v.max(0.0); }).ok_or("Invalid value"),
+        "fill_metric" => payload.value.as_str().map(|v| { config.fill_metric = v.to_string(); }).ok_or("Invalid value"),
+        "max_pps" => payload.value.as_f64().map(|v| { config.max_pps = v.max(1.0); }).ok_or("Invalid value"),
+        "color" => payload.value.as_str().map(|v| { config.color = v.to_string(); }).ok_or("Invalid value"),
+        "tx_color" => payload.value.as_str().map(|v| { config.tx_color = v.to_string(); }).ok_or("Invalid value"),
+        "rx_color" => payload.value.as_str().map(|v| { config.rx_color = v.to_string(); }).ok_or("Invalid value"),
+        "direction" => payload.value.as_str().map(|v| { config.direction = v.to_string(); }).ok_or("Invalid value"),
+        "swap" => payload.value.as_bool().map(|v| { config.swap = v; }).ok_or("Invalid value"),
+        "rx_split_percent" => payload.value.as_f64().map(|v| { config.rx_split_percent = v.clamp(0.0, 100.0); }).ok_or("Invalid value"),
+        "segments" => payload.value.as_str().map(|v| { config.segments = v.to_string(); }).ok_or("Invalid value"),
+        "matrix_width" => payload.value.as_u64().map(|v| { config.matrix_width = v as usize; }).ok_or("Invalid value"),
+        "matrix_height" => payload.value.as_u64().map(|v| { config.matrix_height = v as usize; }).ok_or("Invalid value"),
+        "matrix_serpentine" => payload.value.as_bool().map(|v| { config.matrix_serpentine = v; }).ok_or("Invalid value"),
+        "matrix_rotation" => payload.value.as_u64().map(|v| { config.matrix_rotation = (v / 90 % 4 * 90) as u16; }).ok_or("Invalid value"),
+        "matrix_mode" => payload.value.as_str().map(|v| { config.matrix_mode = v.to_string(); }).ok_or("Invalid value"),
+        "strobe_on_max" => payload.value.as_bool().map(|v| { config.strobe_on_max = v; }).ok_or("Invalid value"),
+        "strobe_rate_hz" => payload.value.as_f64().map(|v| {
+            config.strobe_rate_hz = v;
+            // Validate strobe_duration_ms doesn't exceed the cycle time
+            if config.strobe_rate_hz > 0.0 {
+                let max_duration = 1000.0 / config.strobe_rate_hz;
+                config.strobe_duration_ms = config.strobe_duration_ms.min(max_duration);
+            }
+        }).ok_or("Invalid value"),
+        "strobe_duration_ms" => payload.value.as_f64().map(|v| {
+            // Clamp to valid range and ensure it doesn't exceed cycle time
+            let max_duration = if config.strobe_rate_hz > 0.0 {
+                1000.0 / config.strobe_rate_hz
+            } else {
+                1000.0
+            };
+            config.strobe_duration_ms = v.max(0.0).min(max_duration);
+        }).ok_or("Invalid value"),
+        "strobe_color" => payload.value.as_str().map(|v| { config.strobe_color = v.to_string(); }).ok_or("Invalid value"),
+        "peak_hold" => payload.value.as_bool().map(|v| { config.peak_hold = v; }).ok_or("Invalid value"),
+        "peak_color" => payload.value.as_str().map(|v| { config.peak_color = v.to_string(); }).ok_or("Invalid value"),
+        "tx_peak_color" => payload.value.as_str().map(|v| { config.tx_peak_color = v.to_string(); }).ok_or("Invalid value"),
+        "rx_peak_color" => payload.value.as_str().map(|v| { config.rx_peak_color = v.to_string(); }).ok_or("Invalid value"),
+        "peak_hold_ms" => payload.value.as_f64().map(|v| { config.peak_hold_ms = v.max(0.0); }).ok_or("Invalid value"),
+        "peak_fall_percent_per_sec" => payload.value.as_f64().map(|v| { config.peak_fall_percent_per_sec = v.max(0.0); }).ok_or("Invalid value"),
+        "error_sparkle" => payload.value.as_bool().map(|v| { config.error_sparkle = v; }).ok_or("Invalid value"),
+        "error_color" => payload.value.as_str().map(|v| { config.error_color = v.to_string(); }).ok_or("Invalid value"),
+        "stale_timeout_ms" => payload.value.as_f64().map(|v| { config.stale_timeout_ms = v.max(0.0); }).ok_or("Invalid value"),
+        "no_data_pattern" => payload.value.as_str().map(|v| { config.no_data_pattern = v.to_string(); }).ok_or("Invalid value"),
+        "no_data_color" => payload.value.as_str().map(|v| { config.no_data_color = v.to_string(); }).ok_or("Invalid value"),
+        "gamma" => payload.value.as_f64().map(|v| { config.gamma = v.clamp(0.1, 5.0); }).ok_or("Invalid value"),
+        "brightness_percent" => payload.value.as_f64().map(|v| { config.brightness_percent = v.clamp(0.0, 100.0); }).ok_or("Invalid value"),
+        "power_limit_ma" => payload.value.as_f64().map(|v| { config.power_limit_ma = v.max(0.0); }).ok_or("Invalid value"),
+        "milliamps_per_channel" => payload.value.as_f64().map(|v| { config.milliamps_per_channel = v.max(0.0); }).ok_or("Invalid value"),
+        "schedule" => payload.value.as_str().map(|v| { config.schedule = v.to_string(); }).ok_or("Invalid value"),
+        "schedule_fade_minutes" => payload.value.as_f64().map(|v| { config.schedule_fade_minutes = v.max(0.0); }).ok_or("Invalid value"),
+        "night_start" => payload.value.as_str().map(|v| { config.night_start = v.to_string(); }).ok_or("Invalid value"),
+        "night_end" => payload.value.as_str().map(|v| { config.night_end = v.to_string(); }).ok_or("Invalid value"),
+        "night_min_utilization_percent" => payload.value.as_f64().map(|v| { config.night_min_utilization_percent = v.clamp(0.0, 100.0); }).ok_or("Invalid value"),
+        "animation_speed" => payload.value.as_f64().map(|v| { config.animation_speed = v; }).ok_or("Invalid value"),
+        "scale_animation_speed" => payload.value.as_bool().map(|v| { config.scale_animation_speed = v; }).ok_or("Invalid value"),
+        "tx_animation_direction" => payload.value.as_str().map(|v| { config.tx_animation_direction = v.to_string(); }).ok_or("Invalid value"),
+        "rx_animation_direction" => payload.value.as_str().map(|v| { config.rx_animation_direction = v.to_string(); }).ok_or("Invalid value"),
+        "interpolation_time_ms" => payload.value.as_f64().map(|v| { config.interpolation_time_ms = v; }).ok_or("Invalid value"),
+        "wled_ip" => payload.value.as_str().map(|v| { config.wled_ip = v.to_string(); }).ok_or("Invalid value"),
+        "protocol" => payload.value.as_str().map(|v| { config.protocol = v.to_string(); }).ok_or("Invalid value"),
+        "universe" => payload.value.as_u64().map(|v| { config.universe = v.min(63999) as u16; }).ok_or("Invalid value"),
+        "start_channel" => payload.value.as_u64().map(|v| { config.start_channel = v.clamp(1, 512) as u16; }).ok_or("Invalid value"),
+        "e131_multicast" => payload.value.as_bool().map(|v| { config.e131_multicast = v; }).ok_or("Invalid value"),
+        "opc_channel" => payload.value.as_u64().map(|v| { config.opc_channel = v.min(255) as u8; }).ok_or("Invalid value"),
+        "pixel_format" => payload.value.as_str().map(|v| { config.pixel_format = v.to_string(); }).ok_or("Invalid value"),
+        "white_mode" => payload.value.as_str().map(|v| { config.white_mode = v.to_string(); }).ok_or("Invalid value"),
+        "interface" => match payload.value.as_str() {
+            // SNMP and Prometheus only match names, the other sources pass them to a shell
+            Some(v) if config.source.eq_ignore_ascii_case("snmp") || config.source.eq_ignore_ascii_case("prometheus") || validate_interface_list(v).is_ok() => {
+                config.interface = v.to_string();
+                Ok(())
+            }
+            Some(_) => Err("Invalid interface name"),
+            None => Err("Invalid value"),
+        },
+        "sample_interval_ms" => payload.value.as_f64().map(|v| { config.sample_interval_ms = v.max(10.0); }).ok_or("Invalid value"),
+        "source" => payload.value.as_str().map(|v| { config.source = v.to_string(); }).ok_or("Invalid value"),
+        "snmp_host" => payload.value.as_str().map(|v| { config.snmp_host = v.to_string(); }).ok_or("Invalid value"),
+        "snmp_port" => payload.value.as_u64().map(|v| { config.snmp_port = v as u16; }).ok_or("Invalid value"),
+        "snmp_version" => payload.value.as_str().map(|v| { config.snmp_version = v.to_string(); }).ok_or("Invalid value"),
+        "snmp_community" => payload.value.as_str().map(|v| { config.snmp_community = v.to_string(); }).ok_or("Invalid value"),
+        "snmp_username" => payload.value.as_str().map(|v| { config.snmp_username = v.to_string(); }).ok_or("Invalid value"),
+        "snmp_auth_protocol" => payload.value.as_str().map(|v| { config.snmp_auth_protocol = v.to_string(); }).ok_or("Invalid value"),
+        "snmp_auth_password" => payload.value.as_str().map(|v| { config.snmp_auth_password = v.to_string(); }).ok_or("Invalid value"),
+        "prometheus_url" => payload.value.as_str().map(|v| { config.prometheus_url = v.to_string(); }).ok_or("Invalid value"),
+        "prometheus_rx_metric" => payload.value.as_str().map(|v| { config.prometheus_rx_metric = v.to_string(); }).ok_or("Invalid value"),
+        "prometheus_tx_metric" => payload.value.as_str().map(|v| { config.prometheus_tx_metric = v.to_string(); }).ok_or("Invalid value"),
+        "prometheus_selector" => payload.value.as_str().map(|v| { config.prometheus_selector = v.to_string(); }).ok_or("Invalid value"),
+        "total_leds" => payload.value.as_u64().map(|v| { config.total_leds = v as usize; }).ok_or("Invalid value"),
+        "display_mode" => payload.value.as_str().map(|v| { config.display_mode = v.to_string(); }).ok_or("Invalid value"),
+        "history_style" => payload.value.as_str().map(|v| { config.history_style = v.to_string(); }).ok_or("Invalid value"),
+        "history_leds_per_sample" => payload.value.as_u64().map(|v| { config.history_leds_per_sample = v.max(1) as usize; }).ok_or("Invalid value"),
+        "color_mode" => payload.value.as_str().map(|v| { config.color_mode = v.to_string(); }).ok_or("Invalid value"),
+        "zone_colors" => payload.value.as_str().map(|v| { config.zone_colors = v.to_string(); }).ok_or("Invalid value"),
+        "interface_colors" => payload.value.as_str().map(|v| { config.interface_colors = v.to_string(); }).ok_or("Invalid value"),
+        "use_gradient" => payload.value.as_bool().map(|v| { config.use_gradient = v; }).ok_or("Invalid value"),
+        "interpolation" => payload.value.as_str().map(|v| { config.interpolation = v.to_string(); }).ok_or("Invalid value"),
+        "fps" => payload.value.as_f64().map(|v| { config.fps = v; }).ok_or("Invalid value"),
+        "httpd_enabled" => payload.value.as_bool().map(|v| { config.httpd_enabled = v; }).ok_or("Invalid value"),
+        "httpd_ip" => payload.value.as_str().map(|v| { config.httpd_ip = v.to_string(); }).ok_or("Invalid value"),
+        "httpd_port" => payload.value.as_u64().map(|v| { config.httpd_port = v as u16; }).ok_or("Invalid value"),
+        "test_tx" => payload.value.as_bool().map(|v| { config.test_tx = v; }).ok_or("Invalid value"),
+        "test_rx" => payload.value.as_bool().map(|v| { config.test_rx = v; }).ok_or("Invalid value"),
+        "test_tx_percent" => payload.value.as_f64().map(|v| { config.test_tx_percent = v.clamp(0.0, 100.0); }).ok_or("Invalid value"),
+        "test_rx_percent" => payload.value.as_f64().map(|v| { config.test_rx_percent = v.clamp(0.0, 100.0); }).ok_or("Invalid value"),
+        _ => Err("Unknown field"),
+    };
+
+    if let Err(e) = result {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    match config.save() {
+        Ok(_) => (StatusCode::OK, "Configuration updated").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn run_http_server(ip: String, port: u16, status: Arc<Mutex<MeterStatus>>) -> Result<()> {
+    let app = Router::new()
+        .route("/", get(serve_index))
+        .route("/api/config", get(get_config))
+        .route("/api/config", post(update_config))
+        .with_state(status);
+
+    let addr = format!("{}:{}", ip, port);
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+// Get available network interfaces from the system
+fn get_network_interfaces() -> Result<Vec<String>> {
+    #[cfg(target_os = "macos")]
+    {
+        // On macOS, use ifconfig to list interfaces
+        let output = StdCommand::new("ifconfig")
+            .arg("-l")
+            .output()?;
+
+        let output_str = String::from_utf8_lossy(&output.stdout);
+        let mut interfaces: Vec<String> = output_str
+            .split_whitespace()
+            .map(|s| s.to_string())
+            .filter(|s| !s.starts_with("lo") && !s.starts_with("gif") && !s.starts_with("stf"))
+            .collect();
+
+        interfaces.sort();
+        return Ok(interfaces);
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        // On Linux, read from /sys/class/net
+        let mut interfaces = Vec::new();
+        if let Ok(entries) = std::fs::read_dir("/sys/class/net") {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !name.starts_with("lo") {
+                    interfaces.push(name);
+                }
+            }
+        }
+
+        interfaces.sort();
+        return Ok(interfaces);
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    {
+        Ok(Vec::new())
+    }
+}
+
+// Run interactive first-time setup
+fn run_first_time_setup() -> Result<BandwidthConfig> {
+    println!("\n=== WLED Bandwidth Meter - First Time Setup ===\n");
+
+    // 1. Query and display network interfaces
+    println!("Detecting network interfaces...\n");
+    let interfaces = get_network_interfaces()?;
+
+    if interfaces.is_empty() {
+        eprintln!("Error: No network interfaces found!");
+        std::process::exit(1);
+    }
+
+    println!("Available network interfaces:");
+    for (i, iface) in interfaces.iter().enumerate() {
+        println!("  {}. {}", i + 1, iface);
+    }
+
+    // Prompt for interface selection
+    let interface = loop {
+        print!("\nSelect interface (1-{}): ", interfaces.len());
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+
+        if let Ok(choice) = input.trim().parse::<usize>() {
+            if choice > 0 && choice <= interfaces.len() {
+                break interfaces[choice - 1].clone();
+            }
+        }
+        println!("Invalid selection. Please enter a number between 1 and {}", interfaces.len());
+    };
+
+    println!("Selected: {}\n", interface);
+
+    // 2. Prompt for WLED IP
+    print!("Enter WLED IP address or hostname (e.g., led.local or 192.168.1.100): ");
+    io::stdout().flush()?;
+    let mut wled_ip = String::new();
+    io::stdin().read_line(&mut wled_ip)?;
+    let wled_ip = wled_ip.trim().to_string();
+
+    if wled_ip.is_empty() {
+        eprintln!("Error: WLED IP address is required!");
+        std::process::exit(1);
+    }
+
+    println!();
+
+    // 3. Prompt for total LEDs
+    let total_leds = loop {
+        print!("Enter total number of LEDs in your strip: ");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+
+        if let Ok(leds) = input.trim().parse::<usize>() {
+            if leds > 0 {
+                break leds;
+            }
+        }
+        println!("Invalid input. Please enter a positive number.");
+    };
+
+    println!();
+
+    // 4. Prompt for max interface speed
+    let max_gbps = loop {
+        print!("Enter maximum interface speed in Gbps (e.g., 1.0, 2.5, 10.0): ");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+
+        if let Ok(speed) = input.trim().parse::<f64>() {
+            if speed > 0.0 {
+                break speed;
+            }
+        }
+        println!("Invalid input. Please enter a positive number.");
+    };
+
+    println!("\n=== Configuration Summary ===");
+    println!("Interface: {}", interface);
+    println!("WLED IP: {}", wled_ip);
+    println!("Total LEDs: {}", total_leds);
+    println!("Max Speed: {} Gbps", max_gbps);
+    println!("\nAll other settings will use default values.");
+    println!("You can modify these later via the config file or web interface at http://localhost:8080\n");
+
+    // Create config with provided values and defaults
+    let mut config = BandwidthConfig::default();
+    config.interface = interface;
+    config.wled_ip = wled_ip;
+    config.total_leds = total_leds;
+    config.max_gbps = max_gbps;
+
+    // Save the config
+    config.save()?;
+    println!("Configuration saved to: {}\n", BandwidthConfig::config_path()?.display());
+    println!("Starting bandwidth meter...\n");
+
+    // Give user a moment to read the summary
+    thread::sleep(Duration::from_secs(2));
+
+    Ok(config)
+}
+
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    if args.test.is_some() {
+        // Test mode needs tokio runtime
+        let rt = tokio::runtime::Runtime::new()?;
+        return rt.block_on(test_mode(&args));
+    }
+
+    // Check for first-run scenario BEFORE setting up terminal
+    // First-run: no config file exists AND no command-line args provided
+    let config_path = BandwidthConfig::config_path()?;
+    let config_file_exists = config_path.exists();
+
+    // Check if any meaningful args were provided (excluding program name)
+    let has_args = std::env::args().len() > 1;
+
+    if !config_file_exists && !has_args {
+        // First run - run interactive setup
+        let _config = run_first_time_setup()?;
+        // Config has been saved by run_first_time_setup, continue to normal startup
+    }
+
+    // Create tokio runtime for bandwidth reading task only - keep it alive for entire session
+    let _rt = tokio::runtime::Runtime::new()?;
+
+    // Load existing config or create default, then merge with command line args
+    // Note: config_file_exists was already checked above for first-run detection
+    let mut config = BandwidthConfig::load_or_default();
+    let args_provided = config.merge_with_args(&args);
+
+    // Only save if command-line args were provided OR if config file doesn't exist
+    // This prevents overwriting existing config values on every launch
+    // After first-run setup, config_file_exists will be true, so this only saves if args provided
+    if args_provided || !config_file_exists {
+        config.save()?;
+    }
+
+    // IMPORTANT: Establish SSH connection BEFORE setting up TUI
+    // This allows SSH to prompt for password using normal stdin/stdout
+    let quiet = args.quiet;
+
+    println!("Connecting to bandwidth monitor...");
+    if args.host.is_some() {
+        println!("Please enter your SSH password when prompted...\n");
+    }
+
+    let source_result = _rt.block_on(create_bandwidth_source(&args, &config));
+    let mut source = match source_result {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error: Failed to start bandwidth monitor: {}", e);
+            if args.host.is_some() {
+                eprintln!("Please check your SSH credentials and try again");
+            }
+            return Err(e);
+        }
+    };
+    let source_metadata = source.metadata();
+
+    println!("Connected successfully!\n");
+
+    // Use the interface link speed instead of max_gbps when requested (not saved to the config file)
+    let mut link_speed_gbps = None;
+    if config.detect_link_speed {
+        if matches!(source_metadata.kind, "procnet" | "netstat" | "ssh") {
+            println!("Detecting link speed...");
+            match _rt.block_on(detect_link_speed_mbps(args.host.as_ref(), &config.interface)) {
+                Some(mbps) => {
+                    println!("Link speed: {} Mbps\n", mbps);
+                    link_speed_gbps = Some(mbps / 1000.0);
+                    config.max_gbps = mbps / 1000.0;
+                }
+                None => println!("Could not detect link speed, using max_gbps = {}\n", config.max_gbps),
+            }
+        } else {
+            println!(
+                "Link speed detection is not available for {} sources, using max_gbps = {}\n",
+                source_metadata.kind, config.max_gbps
+            );
+        }
+    }
+    let max_gbps_source = |config: &BandwidthConfig| {
+        if config.auto_range {
+            "auto-range"
+        } else if link_speed_gbps.is_some() && config.detect_link_speed {
+            "link"
+        } else {
+            "config"
+        }
+    };
+    let meter_status = Arc::new(Mutex::new(MeterStatus {
+        effective_max_gbps: config.max_gbps,
+        max_gbps_source: max_gbps_source(&config),
+        link_speed_gbps,
+        rx_pps: 0.0,
+        tx_pps: 0.0,
+        errors: 0,
+        drops: 0,
+        interfaces: Vec::new(),
+        data_state: DataState::Live.as_str(),
+        last_sample_at: None,
+        last_sample_age_secs: None,
+    }));
+    let mut auto_range = AutoRange::default();
+
+    // Clear the terminal to remove password prompt residue
+    print!("\x1B[2J\x1B[1;1H");
+    io::stdout().flush()?;
+
+    // NOW setup terminal - after SSH connection is established
+    enable_raw_mode()?;
+    let mut stdout_handle = io::stdout();
+    stdout_handle.execute(EnterAlternateScreen)?;
+    stdout_handle.flush()?;
+    let backend = CrosstermBackend::new(stdout_handle);
+    let mut terminal = Terminal::new(backend)?;
+    terminal.clear()?;
+    terminal.hide_cursor()?;
+
+    // Setup panic handler to ensure terminal cleanup
+    let original_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        let _ = disable_raw_mode();
+        let _ = io::stdout().execute(LeaveAlternateScreen);
+        let _ = io::stdout().execute(Show);
+        original_hook(panic_info);
+    }));
+
+    // Create shutdown flag for clean termination
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    // Create shared state for renderer
+    let shared_state = Arc::new(Mutex::new(SharedRenderState::from_config(&config)));
+
+    // Status messages from background threads (e.g. unreachable WLED targets) for the TUI log
+    let (log_tx, log_rx) = mpsc::channel::<String>();
+
+    // Create one sender per target. The first keeps the DDP port, the others get their own ephemeral socket
+    let target_outputs = config.target_outputs();
+    let mut render_targets = Vec::new();
+    for (i, output) in target_outputs.iter().enumerate() {
+        let bind_addr = if i == 0 { "0.0.0.0:4048" } else { "0.0.0.0:0" };
+        let socket = match UdpSocket::bind(bind_addr) {
+            Ok(s) => s,
+            Err(e) => {
+                terminal.show_cursor()?;
+                disable_raw_mode()?;
+                terminal.backend_mut().execute(LeaveAlternateScreen)?;
+                return Err(e.into());
+            }
+        };
+        let frames = spawn_target_sender(output.clone(), socket, log_tx.clone());
+        render_targets.push(RenderTarget::new(frames, output.sent_encoding()));
+    }
+
+    // Create renderer
+    let renderer = match Renderer::new(render_targets, shared_state.clone(), shutdown.clone()) {
+        Ok(r) => r,
+        Err(e) => {
+            terminal.show_cursor()?;
+            disable_raw_mode()?;
+            terminal.backend_mut().execute(LeaveAlternateScreen)?;
+            return Err(e);
+        }
+    };
+
+    // Spawn dedicated render thread - runs at 60 FPS independently
+    thread::spawn(move || {
+        renderer.run();
+    });
+
+    let (bandwidth_tx, bandwidth_rx) = mpsc::channel::<SourceEvent>();
+    let (config_tx, config_rx) = mpsc::channel::<BandwidthConfig>();
+
+    // Message log stored locally
+    let mut messages: Vec<String> = Vec::new();
+
+    let leds_per_direction = config.total_leds / 2;
+
+    // Helper function to calculate LEDs (same logic as renderer)
+    let calculate_leds = |bandwidth_kbps: f64, max_bandwidth_kbps: f64, scale: &BandwidthScale| -> usize {
+        let percentage = scale.fraction(bandwidth_kbps, max_bandwidth_kbps);
+        let leds = (percentage * leds_per_direction as f64) as usize;
+        leds.min(leds_per_direction)
+    };
+
+    // Add initial message
+    if !quiet {
+        messages.push(format!(
+            "[{}] Bandwidth meter started. Max: {} Gbps ({}), Scale: {}",
+            get_timestamp(),
+            config.max_gbps,
+            max_gbps_source(&config),
+            config.scale
+        ));
+        messages.push(format!(
+            "[{}] Interface: {}, LEDs: {}, WLED: {}",
+            get_timestamp(),
+            config.interface,
+            config.total_leds,
+            target_outputs.iter().map(|o| o.describe()).collect::<Vec<_>>().join(", ")
+        ));
+        messages.push(format!(
+            "[{}] Source: {} ({})",
+            get_timestamp(),
+            source_metadata.kind,
+            source_metadata.description
+        ));
+        messages.push(format!("[{}] Config file: {}", get_timestamp(), config_path.display()));
+        messages.push(format!("[{}] Edit config file to change settings while running", get_timestamp()));
+        messages.push(format!("[{}] Debug log: /tmp/bandwidth_debug.log", get_timestamp()));
+    }
+
+    // Spawn bandwidth reader in separate tokio task. Only the detected link speed bounds counter wraps,
+    // max_gbps is just the full scale of the strip and may well be below what the link carries
+    if let Some(gbps) = link_speed_gbps {
+        source.set_link_limit_kbps(gbps * 1_000_000.0);
+    }
+    _rt.spawn(run_bandwidth_source(source, bandwidth_tx, log_tx));
+
+    // Spawn config file watcher thread
+    let config_path_clone = config_path.clone();
+    std::thread::spawn(move || -> Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = match RecommendedWatcher::new(tx, Config::default()) {
+            Ok(w) => w,
+            Err(_) => return Ok(()),
+        };
+
+        if watcher
+            .watch(&config_path_clone, RecursiveMode::NonRecursive)
+            .is_err()
+        {
+            return Ok(());
+        }
 
-                    // Show notification if config was updated externally (not on initial load)
-                    if (configChanged && pollingInterval !== null) {
-                        showMessage('Config reloaded from file', 'success');
+        loop {
+            match rx.recv() {
+                Ok(Ok(NotifyEvent { kind, .. })) => {
+                    // Only respond to modify events
+                    if matches!(kind, notify::EventKind::Modify(_)) {
+                        if let Ok(new_config) = BandwidthConfig::load() {
+                            let _ = config_tx.send(new_config);
+                        }
                     }
                 }
-            } catch (e) {
-                showMessage('Failed to load configuration', 'error');
+                _ => {}
             }
         }
+    });
 
-        function renderConfig() {
-            const container = document.getElementById('config-container');
-            container.innerHTML = fieldSections.map(section => {
-                // Special handling for Testing section
-                if (section.isTesting) {
-                    const testingHTML = `
-                        <div style="display: grid; grid-template-columns: 1fr 1fr; gap: 30px;">
-                            <div style="text-align: center;">
-                                <div style="margin-bottom: 15px;">
-                                    <label style="display: inline-flex; align-items: center; gap: 8px; cursor: pointer; font-size: 1em;">
-                                        <input type="checkbox" id="test_tx" onchange="saveField('test_tx', 'checkbox')" ${config.test_tx ? 'checked' : ''}>
-                                        <span>TX (Upload)</span>
-                                    </label>
-                                </div>
-                                <div>
-                                    <label style="display: block; font-size: 0.85em; color: #808080; margin-bottom: 8px;">Utilization: <span id="test_tx_percent_value">${(config.test_tx_percent || 100).toFixed(0)}%</span></label>
-                                    <input type="range" id="test_tx_percent" value="${config.test_tx_percent || 100}"
-                                           min="0" max="100" step="1" style="width: 100%;"
-                                           oninput="updateTestRangeValue('test_tx_percent')"
-                                           onchange="saveField('test_tx_percent', 'range')">
-                                </div>
-                            </div>
-                            <div style="text-align: center;">
-                                <div style="margin-bottom: 15px;">
-                                    <label style="display: inline-flex; align-items: center; gap: 8px; cursor: pointer; font-size: 1em;">
-                                        <input type="checkbox" id="test_rx" onchange="saveField('test_rx', 'checkbox')" ${config.test_rx ? 'checked' : ''}>
-                                        <span>RX (Download)</span>
-                                    </label>
-                                </div>
-                                <div>
-                                    <label style="display: block; font-size: 0.85em; color: #808080; margin-bottom: 8px;">Utilization: <span id="test_rx_percent_value">${(config.test_rx_percent || 100).toFixed(0)}%</span></label>
-                                    <input type="range" id="test_rx_percent" value="${config.test_rx_percent || 100}"
-                                           min="0" max="100" step="1" style="width: 100%;"
-                                           oninput="updateTestRangeValue('test_rx_percent')"
-                                           onchange="saveField('test_rx_percent', 'range')">
-                                </div>
-                            </div>
-                        </div>
-                    `;
-
-                    return `
-                        <div class="section">
-                            <div class="section-header">${section.title}</div>
-                            ${testingHTML}
-                            ${section.help ? `<div class="help-text" style="text-align: center; margin-top: 12px;">${section.help}</div>` : ''}
-                        </div>
-                    `;
-                }
-
-                // Regular sections
-                const fieldsHTML = section.fields.map(field => {
-                    const value = config[field.name];
-                    let inputHTML = '';
-                    let saveButton = '';
+    // Start HTTP server if enabled
+    if config.httpd_enabled {
+        let httpd_ip = config.httpd_ip.clone();
+        let httpd_port = config.httpd_port;
+        let status = meter_status.clone();
+        
+        _rt.spawn(async move {
+            if let Err(e) = run_http_server(httpd_ip.clone(), httpd_port, status).await {
+                eprintln!("HTTP server error: {}", e);
+            }
+        });
+    }
 
-                    if (field.type === 'checkbox') {
-                        // Checkboxes auto-save on change, no Save button needed
-                        inputHTML = `<input type="checkbox" id="${field.name}" onchange="saveField('${field.name}', '${field.type}')" ${value ? 'checked' : ''}>`;
-                    } else if (field.type === 'range') {
-                        // Range sliders show current value and auto-save on change
-                        const rxValue = value !== undefined && value !== null ? value : 50;
-                        const txSplit = (100 - rxValue).toFixed(0);
-                        inputHTML = `
-                            <input type="range" id="${field.name}" value="${rxValue}"
-                                   min="${field.min || 0}" max="${field.max || 100}" step="${field.step || 1}"
-                                   oninput="updateRangeValue('${field.name}')"
-                                   onchange="saveField('${field.name}', '${field.type}')">
-                            <div class="range-value" id="${field.name}_value">RX ${rxValue.toFixed(0)}% / TX ${txSplit}%</div>
-                        `;
-                    } else if (field.type === 'radio') {
-                        // Radio buttons auto-save on change, no Save button needed
-                        inputHTML = field.options.map(opt => `
-                            <label style="display: inline-flex; align-items: center; gap: 5px; margin-right: 20px; cursor: pointer;">
-                                <input type="radio" name="${field.name}" value="${opt}"
-                                       onchange="saveField('${field.name}', '${field.type}')"
-                                       ${value === opt ? 'checked' : ''}>
-                                <span>${opt}</span>
-                            </label>
-                        `).join('');
-                    } else if (field.type === 'select') {
-                        inputHTML = `<select id="${field.name}">${field.options.map(opt =>
-                            `<option value="${opt}" ${value === opt ? 'selected' : ''}>${opt}</option>`
-                        ).join('')}</select>`;
-                        saveButton = `<button onclick="saveField('${field.name}', '${field.type}')">Save</button>`;
-                    } else if (field.type === 'textarea') {
-                        inputHTML = `<textarea id="${field.name}" rows="2" style="resize: vertical; font-family: monospace; overflow: hidden;" oninput="autoResizeTextarea(this)">${value || ''}</textarea>`;
-                        saveButton = `<button onclick="saveField('${field.name}', '${field.type}')">Save</button>`;
-                    } else {
-                        // Special handling for strobe_duration_ms validation
-                        if (field.name === 'strobe_duration_ms') {
-                            inputHTML = `<input type="${field.type}" id="${field.name}" value="${value || ''}" ${field.step ? `step="${field.step}"` : ''} oninput="validateStrobeDuration()">`;
-                            saveButton = `<button id="save_${field.name}" onclick="saveField('${field.name}', '${field.type}')">Save</button>`;
-                        } else {
-                            inputHTML = `<input type="${field.type}" id="${field.name}" value="${value || ''}" ${field.step ? `step="${field.step}"` : ''}>`;
-                            saveButton = `<button onclick="saveField('${field.name}', '${field.type}')">Save</button>`;
-                        }
-                    }
+    // Force initial render
+    {
+        let status_line = if config.httpd_enabled {
+            format!(
+                "Edit {} to change settings | Web UI: http://{}:{} | Press Ctrl+C to quit",
+                config_path.display(),
+                config.httpd_ip,
+                config.httpd_port
+            )
+        } else {
+            format!(
+                "Edit {} to change settings | Press Ctrl+C to quit",
+                config_path.display()
+            )
+        };
 
-                    // Dynamic help text for strobe_duration_ms
-                    let helpText = '';
-                    if (field.help) {
-                        if (field.name === 'strobe_duration_ms') {
-                            const maxDuration = config.strobe_rate_hz > 0 ? (1000.0 / config.strobe_rate_hz).toFixed(1) : '1000.0';
-                            helpText = `<div class="help-text" id="help_${field.name}">${field.help} Current max: ${maxDuration}ms</div>`;
-                        } else {
-                            helpText = `<div class="help-text">${field.help}</div>`;
-                        }
-                    }
+        terminal.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(3)].as_ref())
+                .split(f.size());
 
-                    return `
-                        <div class="config-item">
-                            <label for="${field.name}">${field.label}</label>
-                            <div class="input-group">
-                                ${inputHTML}
-                                ${saveButton}
-                            </div>
-                            ${helpText}
-                        </div>
-                    `;
-                }).join('');
+            let messages_text: Vec<Line> = messages
+                .iter()
+                .rev()
+                .take(chunks[0].height as usize)
+                .rev()
+                .map(|m| Line::from(m.as_str()))
+                .collect();
 
-                return `
-                    <div class="section">
-                        <div class="section-header">${section.title}</div>
-                        <div class="config-grid">
-                            ${fieldsHTML}
-                        </div>
-                    </div>
-                `;
-            }).join('');
+            let messages_widget = Paragraph::new(messages_text).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Bandwidth Monitor"),
+            );
+            f.render_widget(messages_widget, chunks[0]);
 
-            // After rendering, validate strobe duration and auto-size textareas
-            setTimeout(() => {
-                validateStrobeDuration();
-                document.querySelectorAll('textarea').forEach(ta => autoResizeTextarea(ta));
-            }, 0);
-        }
+            let status = Paragraph::new(status_line)
+                .block(Block::default().borders(Borders::ALL).title("Status"));
+            f.render_widget(status, chunks[1]);
+        })?;
+    }
 
-        function autoResizeTextarea(textarea) {
-            // Reset height to auto to get the correct scrollHeight
-            textarea.style.height = 'auto';
-            // Set height to scrollHeight to fit content
-            textarea.style.height = textarea.scrollHeight + 'px';
-        }
+    let mut needs_render = true;
+    // Freshness of the readings: samples reset the stale timer, the source task reports losing the source
+    let mut data_state = DataState::Live;
+    let mut source_lost = false;
+    let mut last_sample_at = Instant::now();
 
-        function updateRangeValue(fieldName) {
-            const input = document.getElementById(fieldName);
-            const display = document.getElementById(fieldName + '_value');
-            const rxValue = parseFloat(input.value);
-            const txValue = 100 - rxValue;
-            display.textContent = `RX ${rxValue.toFixed(0)}% / TX ${txValue.toFixed(0)}%`;
+    // Initialize test mode bandwidth values if enabled
+    if config.test_tx || config.test_rx {
+        let mut state = shared_state.lock().unwrap();
+        if config.test_rx {
+            let test_rx_kbps = config.fill_max() * (config.test_rx_percent / 100.0);
+            state.current_rx_kbps = test_rx_kbps;
+            state.start_rx_kbps = test_rx_kbps;
+            state.last_bandwidth_update = Some(Instant::now());
         }
-
-        function updateTestRangeValue(fieldName) {
-            const input = document.getElementById(fieldName);
-            const display = document.getElementById(fieldName + '_value');
-            const value = parseFloat(input.value);
-            display.textContent = `${value.toFixed(0)}%`;
+        if config.test_tx {
+            let test_tx_kbps = config.fill_max() * (config.test_tx_percent / 100.0);
+            state.current_tx_kbps = test_tx_kbps;
+            state.start_tx_kbps = test_tx_kbps;
+            state.last_bandwidth_update = Some(Instant::now());
         }
+    }
 
-        function validateStrobeDuration() {
-            const durationInput = document.getElementById('strobe_duration_ms');
-            const saveButton = document.getElementById('save_strobe_duration_ms');
-            const helpText = document.getElementById('help_strobe_duration_ms');
+    // Simple main loop - just handle bandwidth and config updates
+    // Rendering happens in dedicated thread at configurable FPS
+    loop {
+        // Check for Ctrl+C key press (using crossterm events since we're in raw mode)
+        // Use 50ms timeout to make Ctrl+C more responsive
+        if poll(std::time::Duration::from_millis(50))? {
+            if let Event::Key(KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            }) = read()?
+            {
+                // Signal render thread to shut down
+                shutdown.store(true, Ordering::Relaxed);
 
-            if (!durationInput || !saveButton) return;
+                // Give render thread a moment to exit cleanly
+                thread::sleep(Duration::from_millis(100));
 
-            const duration = parseFloat(durationInput.value);
-            const strobeRateHz = config.strobe_rate_hz || 3.0;
-            const maxDuration = strobeRateHz > 0 ? (1000.0 / strobeRateHz) : 1000.0;
+                // Clean up terminal
+                terminal.show_cursor()?;
+                disable_raw_mode()?;
+                terminal.backend_mut().execute(LeaveAlternateScreen)?;
+                break;
+            }
+        }
 
-            if (duration > maxDuration || duration < 0 || isNaN(duration)) {
-                // Invalid - highlight red and disable save
-                durationInput.classList.add('invalid');
-                saveButton.disabled = true;
-                if (helpText) {
-                    helpText.innerHTML = `Duration of strobe effect in milliseconds. Cannot exceed cycle time (e.g., 3 Hz = 333ms max) <span style="color: #ff4444; font-weight: bold;">Current max: ${maxDuration.toFixed(1)}ms - Value exceeds maximum!</span>`;
+        // Check bandwidth updates - drain everything that arrived since the last pass
+        // so fast sample intervals never queue up behind the UI loop
+        while let Ok(event) = bandwidth_rx.try_recv() {
+            let sample = match event {
+                SourceEvent::Sample(sample) => sample,
+                SourceEvent::Lost(reason) => {
+                    if !quiet {
+                        messages.push(format!("[{}] Bandwidth source lost: {}", get_timestamp(), reason));
+                    }
+                    source_lost = true;
+                    continue;
                 }
-            } else {
-                // Valid - remove red and enable save
-                durationInput.classList.remove('invalid');
-                saveButton.disabled = false;
-                if (helpText) {
-                    helpText.innerHTML = `Duration of strobe effect in milliseconds. Cannot exceed cycle time (e.g., 3 Hz = 333ms max) Current max: ${maxDuration.toFixed(1)}ms`;
+                SourceEvent::Restored => {
+                    if !quiet {
+                        messages.push(format!("[{}] Bandwidth source reconnected", get_timestamp()));
+                    }
+                    source_lost = false;
+                    // The stale timer starts over rather than counting the outage
+                    last_sample_at = Instant::now();
+                    continue;
                 }
-            }
-        }
+            };
+            last_sample_at = sample.timestamp;
+            // With fill_metric = "packets" everything downstream works in packets/s instead of kbps
+            let packets = sample.packets;
+            let (sample_rx, sample_tx) = if config.fills_by_packets() {
+                (packets.rx_pps, packets.tx_pps)
+            } else {
+                (sample.rx_kbps, sample.tx_kbps)
+            };
 
-        async function saveField(fieldName, fieldType) {
-            let value;
+            // Override with test values if test mode is enabled for each direction
+            let rx_kbps = if config.test_rx {
+                config.fill_max() * (config.test_rx_percent / 100.0)
+            } else {
+                sample_rx
+            };
 
-            if (fieldType === 'checkbox') {
-                const input = document.getElementById(fieldName);
-                value = input.checked;
-            } else if (fieldType === 'radio') {
-                const selectedRadio = document.querySelector(`input[name="${fieldName}"]:checked`);
-                value = selectedRadio ? selectedRadio.value : null;
-            } else if (fieldType === 'range') {
-                const input = document.getElementById(fieldName);
-                value = parseFloat(input.value);
-            } else if (fieldType === 'number') {
-                const input = document.getElementById(fieldName);
-                value = parseFloat(input.value);
+            let tx_kbps = if config.test_tx {
+                config.fill_max() * (config.test_tx_percent / 100.0)
             } else {
-                const input = document.getElementById(fieldName);
-                value = input.value;
+                sample_tx
+            };
+
+            // Auto-range follows the decaying peak, capped at max_gbps (or the link speed).
+            // Test mode always uses the full max so the simulated percentages stay meaningful
+            let max_kbps = if config.auto_range && !config.fills_by_packets() && !config.test_rx && !config.test_tx {
+                auto_range.range_kbps(rx_kbps.max(tx_kbps), sample.timestamp, &config)
+            } else {
+                config.fill_max()
+            };
+
+            // Update shared state (non-blocking for renderer)
+            {
+                let mut state = shared_state.lock().unwrap();
+                state.max_bandwidth_kbps = max_kbps;
+                // Store current values as the starting point for interpolation
+                state.start_rx_kbps = state.current_rx_kbps;
+                state.start_tx_kbps = state.current_tx_kbps;
+                // Update to new target values
+                state.current_rx_kbps = rx_kbps;
+                state.current_tx_kbps = tx_kbps;
+                // Record the time when this sample was taken
+                state.last_bandwidth_update = Some(sample.timestamp);
+                state.record_history(HistorySample { rx_kbps, tx_kbps });
+                // Errors and drops wait here until the renderer turns them into sparkles
+                state.rx_error_events += packets.rx_errors + packets.rx_drops;
+                state.tx_error_events += packets.tx_errors + packets.tx_drops;
+                // Per-interface levels for color_mode = "interfaces", in the same unit as the bars
+                state.interface_levels = if config.test_rx || config.test_tx {
+                    Vec::new()
+                } else if config.fills_by_packets() {
+                    sample.interfaces.iter().map(|r| (r.packets.rx_pps, r.packets.tx_pps)).collect()
+                } else {
+                    sample.interfaces.iter().map(|r| (r.rx_kbps, r.tx_kbps)).collect()
+                };
             }
 
-            try {
-                const res = await fetch('/api/config', {
-                    method: 'POST',
-                    headers: { 'Content-Type': 'application/json' },
-                    body: JSON.stringify({ field: fieldName, value })
-                });
+            // Generate messages for UI
+            let scale = BandwidthScale::from_config(&config);
+            let rx_leds = calculate_leds(rx_kbps, max_kbps, &scale);
+            let tx_leds = calculate_leds(tx_kbps, max_kbps, &scale);
+            {
+                let mut status = meter_status.lock().unwrap();
+                if !config.fills_by_packets() {
+                    status.effective_max_gbps = max_kbps / 1000.0 / 1000.0;
+                }
+                status.rx_pps = packets.rx_pps;
+                status.tx_pps = packets.tx_pps;
+                status.errors += packets.rx_errors + packets.tx_errors;
+                status.drops += packets.rx_drops + packets.tx_drops;
+                status.interfaces = sample.interfaces.clone();
+                status.last_sample_at = Some(sample.timestamp);
+            }
 
-                if (res.ok) {
-                    showMessage(`${fieldName} updated successfully`, 'success');
-                    config[fieldName] = value;
+            // Always show both RX and TX on every update
+            if !quiet {
+                // With several interfaces, each one's RX/TX follows the total
+                let breakdown = if sample.interfaces.len() > 1 {
+                    let readings = sample
+                        .interfaces
+                        .iter()
+                        .map(|r| {
+                            if config.fills_by_packets() {
+                                format!("{} {:.0}/{:.0}", r.name, r.packets.rx_pps, r.packets.tx_pps)
+                            } else {
+                                format!("{} {:.1}/{:.1}", r.name, r.rx_kbps / 1000.0, r.tx_kbps / 1000.0)
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    format!(" | {}", readings)
+                } else {
+                    String::new()
+                };
 
-                    // If strobe_rate_hz changed, revalidate strobe_duration_ms
-                    if (fieldName === 'strobe_rate_hz') {
-                        validateStrobeDuration();
-                    }
+                if config.fills_by_packets() {
+                    messages.push(format!(
+                        "[{}] RX: {} LEDs ({:.0} pps) | TX: {} LEDs ({:.0} pps){}",
+                        get_timestamp(),
+                        rx_leds,
+                        rx_kbps,
+                        tx_leds,
+                        tx_kbps,
+                        breakdown
+                    ));
                 } else {
-                    showMessage(`Failed to update ${fieldName}`, 'error');
+                    let range = if config.auto_range {
+                        format!(" | Max: {:.1} Mbps", max_kbps / 1000.0)
+                    } else {
+                        String::new()
+                    };
+                    messages.push(format!(
+                        "[{}] RX: {} LEDs ({:.1} Mbps) | TX: {} LEDs ({:.1} Mbps){}{}",
+                        get_timestamp(),
+                        rx_leds,
+                        rx_kbps / 1000.0,
+                        tx_leds,
+                        tx_kbps / 1000.0,
+                        range,
+                        breakdown
+                    ));
                 }
-            } catch (e) {
-                showMessage(`Error updating ${fieldName}`, 'error');
+
+                if packets.rx_errors + packets.rx_drops + packets.tx_errors + packets.tx_drops > 0 {
+                    messages.push(format!(
+                        "[{}] Interface errors: RX {} errors, {} drops | TX {} errors, {} drops",
+                        get_timestamp(),
+                        packets.rx_errors,
+                        packets.rx_drops,
+                        packets.tx_errors,
+                        packets.tx_drops
+                    ));
+                }
+                needs_render = true;
             }
-        }
 
-        function showMessage(text, type) {
-            const msg = document.getElementById('message');
-            msg.textContent = text;
-            msg.className = `message ${type} show`;
-            setTimeout(() => msg.className = 'message', 3000);
+            // Keep message buffer reasonable
+            if messages.len() > 1000 {
+                messages.remove(0);
+            }
         }
 
-        // Initial load
-        loadConfig();
-
-        // Start polling for config changes every 2 seconds
-        pollingInterval = setInterval(loadConfig, 2000);
-    </script>
-</body>
-</html>
-"#;
+        // Status messages from background threads
+        while let Ok(message) = log_rx.try_recv() {
+            if !quiet {
+                messages.push(format!("[{}] {}", get_timestamp(), message));
+                needs_render = true;
+            }
+        }
 
-#[derive(Deserialize)]
-struct UpdateField {
-    field: String,
-    value: serde_json::Value,
-}
+        // Check config file updates
+        match config_rx.try_recv() {
+            Ok(mut new_config) => {
+                // A detected link speed keeps replacing the max_gbps from the file
+                if new_config.detect_link_speed {
+                    if let Some(gbps) = link_speed_gbps {
+                        new_config.max_gbps = gbps;
+                    }
+                }
 
-async fn serve_index() -> impl IntoResponse {
-    Html(WEB_UI_HTML)
-}
+                // Update shared state with new config
+                {
+                    let mut state = shared_state.lock().unwrap();
 
-async fn get_config() -> impl IntoResponse {
-    match BandwidthConfig::load() {
-        Ok(config) => (StatusCode::OK, Json(config)).into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    }
-}
+                    // Handle color updates
+                    let color_changed = new_config.color != config.color;
+                    let tx_color_changed = new_config.tx_color != config.tx_color;
+                    let rx_color_changed = new_config.rx_color != config.rx_color;
 
-async fn update_config(Json(payload): Json<UpdateField>) -> impl IntoResponse {
-    let mut config = match BandwidthConfig::load() {
-        Ok(c) => c,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    };
+                    if tx_color_changed || (color_changed && new_config.tx_color.is_empty()) {
+                        let tx_color_to_use = if new_config.tx_color.is_empty() {
+                            new_config.color.clone()
+                        } else {
+                            new_config.tx_color.clone()
+                        };
+                        state.tx_color = tx_color_to_use.clone();
+                        state.generation += 1;
+                        if !quiet {
+                            if new_config.tx_color.is_empty() {
+                                messages.push(format!(
+                                    "[{}] TX color updated to: {} (from main color)",
+                                    get_timestamp(),
+                                    tx_color_to_use
+                                ));
+                            } else {
+                                messages.push(format!("[{}] TX color updated to: {}", get_timestamp(), new_config.tx_color));
+                            }
+                        }
+                    }
 
-    let result = match payload.field.as_str() {
-        "max_gbps" => payload.value.as_f64().map(|v| { config.max_gbps = v; }).ok_or("Invalid value"),
-        "color" => payload.value.as_str().map(|v| { config.color = v.to_string(); }).ok_or("Invalid value"),
-        "tx_color" => payload.value.as_str().map(|v| { config.tx_color = v.to_string(); }).ok_or("Invalid value"),
-        "rx_color" => payload.value.as_str().map(|v| { config.rx_color = v.to_string(); }).ok_or("Invalid value"),
-        "direction" => payload.value.as_str().map(|v| { config.direction = v.to_string(); }).ok_or("Invalid value"),
-        "swap" => payload.value.as_bool().map(|v| { config.swap = v; }).ok_or("Invalid value"),
-        "rx_split_percent" => payload.value.as_f64().map(|v| { config.rx_split_percent = v.clamp(0.0, 100.0); }).ok_or("Invalid value"),
-        "strobe_on_max" => payload.value.as_bool().map(|v| { config.strobe_on_max = v; }).ok_or("Invalid value"),
-        "strobe_rate_hz" => payload.value.as_f64().map(|v| {
-            config.strobe_rate_hz = v;
-            // Validate strobe_duration_ms doesn't exceed the cycle time
-            if config.strobe_rate_hz > 0.0 {
-                let max_duration = 1000.0 / config.strobe_rate_hz;
-                config.strobe_duration_ms = config.strobe_duration_ms.min(max_duration);
-            }
-        }).ok_or("Invalid value"),
-        "strobe_duration_ms" => payload.value.as_f64().map(|v| {
-            // Clamp to valid range and ensure it doesn't exceed cycle time
-            let max_duration = if config.strobe_rate_hz > 0.0 {
-                1000.0 / config.strobe_rate_hz
-            } else {
-                1000.0
-            };
-            config.strobe_duration_ms = v.max(0.0).min(max_duration);
-        }).ok_or("Invalid value"),
-        "strobe_color" => payload.value.as_str().map(|v| { config.strobe_color = v.to_string(); }).ok_or("Invalid value"),
-        "animation_speed" => payload.value.as_f64().map(|v| { config.animation_speed = v; }).ok_or("Invalid value"),
-        "scale_animation_speed" => payload.value.as_bool().map(|v| { config.scale_animation_speed = v; }).ok_or("Invalid value"),
-        "tx_animation_direction" => payload.value.as_str().map(|v| { config.tx_animation_direction = v.to_string(); }).ok_or("Invalid value"),
-        "rx_animation_direction" => payload.value.as_str().map(|v| { config.rx_animation_direction = v.to_string(); }).ok_or("Invalid value"),
-        "interpolation_time_ms" => payload.value.as_f64().map(|v| { config.interpolation_time_ms = v; }).ok_or("Invalid value"),
-        "wled_ip" => payload.value.as_str().map(|v| { config.wled_ip = v.to_string(); }).ok_or("Invalid value"),
-        "interface" => payload.value.as_str().map(|v| { config.interface = v.to_string(); }).ok_or("Invalid value"),
-        "total_leds" => payload.value.as_u64().map(|v| { config.total_leds = v as usize; }).ok_or("Invalid value"),
-        "use_gradient" => payload.value.as_bool().map(|v| { config.use_gradient = v; }).ok_or("Invalid value"),
-        "interpolation" => payload.value.as_str().map(|v| { config.interpolation = v.to_string(); }).ok_or("Invalid value"),
-        "fps" => payload.value.as_f64().map(|v| { config.fps = v; }).ok_or("Invalid value"),
-        "httpd_enabled" => payload.value.as_bool().map(|v| { config.httpd_enabled = v; }).ok_or("Invalid value"),
-        "httpd_ip" => payload.value.as_str().map(|v| { config.httpd_ip = v.to_string(); }).ok_or("Invalid value"),
-        "httpd_port" => payload.value.as_u64().map(|v| { config.httpd_port = v as u16; }).ok_or("Invalid value"),
-        "test_tx" => payload.value.as_bool().map(|v| { config.test_tx = v; }).ok_or("Invalid value"),
-        "test_rx" => payload.value.as_bool().map(|v| { config.test_rx = v; }).ok_or("Invalid value"),
-        "test_tx_percent" => payload.value.as_f64().map(|v| { config.test_tx_percent = v.clamp(0.0, 100.0); }).ok_or("Invalid value"),
-        "test_rx_percent" => payload.value.as_f64().map(|v| { config.test_rx_percent = v.clamp(0.0, 100.0); }).ok_or("Invalid value"),
-        _ => Err("Unknown field"),
-    };
+                    if rx_color_changed || (color_changed && new_config.rx_color.is_empty()) {
+                        let rx_color_to_use = if new_config.rx_color.is_empty() {
+                            new_config.color.clone()
+                        } else {
+                            new_config.rx_color.clone()
+                        };
+                        state.rx_color = rx_color_to_use.clone();
+                        state.generation += 1;
+                        if !quiet {
+                            if new_config.rx_color.is_empty() {
+                                messages.push(format!(
+                                    "[{}] RX color updated to: {} (from main color)",
+                                    get_timestamp(),
+                                    rx_color_to_use
+                                ));
+                            } else {
+                                messages.push(format!("[{}] RX color updated to: {}", get_timestamp(), new_config.rx_color));
+                            }
+                        }
+                    }
 
-    if let Err(e) = result {
-        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
-    }
+                    // Update max bandwidth
+                    if new_config.max_gbps != config.max_gbps {
+                        state.max_bandwidth_kbps = new_config.fill_max();
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Max bandwidth updated to: {} Gbps",
+                                get_timestamp(),
+                                new_config.max_gbps
+                            ));
+                        }
+                    }
 
-    match config.save() {
-        Ok(_) => (StatusCode::OK, "Configuration updated").into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    }
-}
+                    // Update auto-range, falling back to the full max when it is turned off
+                    if new_config.auto_range != config.auto_range {
+                        auto_range = AutoRange::default();
+                        state.max_bandwidth_kbps = new_config.fill_max();
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Auto-range: {}",
+                                get_timestamp(),
+                                if new_config.auto_range { "enabled" } else { "disabled" }
+                            ));
+                        }
+                    }
 
-async fn run_http_server(ip: String, port: u16) -> Result<()> {
-    let app = Router::new()
-        .route("/", get(serve_index))
-        .route("/api/config", get(get_config))
-        .route("/api/config", post(update_config));
+                    // Update fill metric, starting the auto-range over since the units changed
+                    if new_config.fill_metric != config.fill_metric || new_config.max_pps != config.max_pps {
+                        auto_range = AutoRange::default();
+                        state.max_bandwidth_kbps = new_config.fill_max();
+                        if !quiet {
+                            messages.push(if new_config.fills_by_packets() {
+                                format!("[{}] Fill metric: packets (max {} pps)", get_timestamp(), new_config.max_pps)
+                            } else {
+                                format!("[{}] Fill metric: bandwidth", get_timestamp())
+                            });
+                        }
+                    }
 
-    let addr = format!("{}:{}", ip, port);
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    
-    axum::serve(listener, app).await?;
-    Ok(())
-}
+                    // Update error sparkles
+                    if new_config.error_sparkle != config.error_sparkle || new_config.error_color != config.error_color {
+                        state.error_sparkle = error_sparkle_color(&new_config);
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Error sparkle: {}",
+                                get_timestamp(),
+                                if new_config.error_sparkle { "enabled" } else { "disabled" }
+                            ));
+                        }
+                    }
 
-// Get available network interfaces from the system
-fn get_network_interfaces() -> Result<Vec<String>> {
-    #[cfg(target_os = "macos")]
-    {
-        // On macOS, use ifconfig to list interfaces
-        let output = StdCommand::new("ifconfig")
-            .arg("-l")
-            .output()?;
+                    // Update the no data pattern
+                    if new_config.no_data_pattern != config.no_data_pattern || new_config.no_data_color != config.no_data_color {
+                        state.no_data = NoDataConfig::from_config(&new_config);
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] No data pattern: {} ({})",
+                                get_timestamp(),
+                                new_config.no_data_pattern,
+                                new_config.no_data_color
+                            ));
+                        }
+                    }
 
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        let mut interfaces: Vec<String> = output_str
-            .split_whitespace()
-            .map(|s| s.to_string())
-            .filter(|s| !s.starts_with("lo") && !s.starts_with("gif") && !s.starts_with("stf"))
-            .collect();
+                    // The stale timeout is checked against the current config every pass of the main loop
+                    if new_config.stale_timeout_ms != config.stale_timeout_ms && !quiet {
+                        messages.push(format!(
+                            "[{}] Stale timeout: {} ms",
+                            get_timestamp(),
+                            new_config.stale_timeout_ms
+                        ));
+                    }
 
-        interfaces.sort();
-        return Ok(interfaces);
-    }
+                    // Update bandwidth scale
+                    if new_config.scale != config.scale
+                        || new_config.scale_log_floor_kbps != config.scale_log_floor_kbps
+                        || new_config.scale_exponent != config.scale_exponent
+                    {
+                        state.scale = BandwidthScale::from_config(&new_config);
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Scale updated to: {} (log floor {} kbps, exponent {})",
+                                get_timestamp(),
+                                new_config.scale,
+                                new_config.scale_log_floor_kbps,
+                                new_config.scale_exponent
+                            ));
+                        }
+                    }
 
-    #[cfg(target_os = "linux")]
-    {
-        // On Linux, read from /sys/class/net
-        let mut interfaces = Vec::new();
-        if let Ok(entries) = std::fs::read_dir("/sys/class/net") {
-            for entry in entries.flatten() {
-                let name = entry.file_name().to_string_lossy().to_string();
-                if !name.starts_with("lo") {
-                    interfaces.push(name);
-                }
-            }
-        }
+                    // Update direction
+                    if new_config.direction != config.direction {
+                        state.generation += 1;
+                        if !quiet {
+                            messages.push(format!("[{}] Direction updated to: {}", get_timestamp(), new_config.direction));
+                        }
+                    }
 
-        interfaces.sort();
-        return Ok(interfaces);
-    }
+                    // Update swap
+                    if new_config.swap != config.swap {
+                        state.generation += 1;
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Swap: {}",
+                                get_timestamp(),
+                                if new_config.swap { "enabled" } else { "disabled" }
+                            ));
+                        }
+                    }
 
-    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
-    {
-        Ok(Vec::new())
-    }
-}
+                    // Update RX/TX split percentage
+                    if new_config.rx_split_percent != config.rx_split_percent && !quiet {
+                        let tx_split = 100.0 - new_config.rx_split_percent;
+                        messages.push(format!(
+                            "[{}] LED split updated to: RX {:.0}% / TX {:.0}%",
+                            get_timestamp(),
+                            new_config.rx_split_percent,
+                            tx_split
+                        ));
+                    }
 
-// Run interactive first-time setup
-fn run_first_time_setup() -> Result<BandwidthConfig> {
-    println!("\n=== WLED Bandwidth Meter - First Time Setup ===\n");
+                    if new_config.segments != config.segments && !quiet {
+                        messages.push(if new_config.segments.trim().is_empty() {
+                            format!("[{}] Segment map cleared, using direction and split", get_timestamp())
+                        } else {
+                            format!("[{}] Segment map updated to: {}", get_timestamp(), new_config.segments)
+                        });
+                    }
 
-    // 1. Query and display network interfaces
-    println!("Detecting network interfaces...\n");
-    let interfaces = get_network_interfaces()?;
+                    // Update strobe on max
+                    if new_config.strobe_on_max != config.strobe_on_max {
+                        state.strobe_on_max = new_config.strobe_on_max;
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Strobe on max: {}",
+                                get_timestamp(),
+                                if new_config.strobe_on_max { "enabled" } else { "disabled" }
+                            ));
+                        }
+                    }
 
-    if interfaces.is_empty() {
-        eprintln!("Error: No network interfaces found!");
-        std::process::exit(1);
-    }
+                    // Update strobe rate
+                    if new_config.strobe_rate_hz != config.strobe_rate_hz {
+                        state.strobe_rate_hz = new_config.strobe_rate_hz;
+                        // Also validate strobe_duration_ms doesn't exceed new cycle time
+                        if new_config.strobe_rate_hz > 0.0 {
+                            let max_duration = 1000.0 / new_config.strobe_rate_hz;
+                            if state.strobe_duration_ms > max_duration {
+                                state.strobe_duration_ms = max_duration;
+                            }
+                        }
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Strobe rate updated to: {:.1} Hz",
+                                get_timestamp(),
+                                new_config.strobe_rate_hz
+                            ));
+                        }
+                    }
 
-    println!("Available network interfaces:");
-    for (i, iface) in interfaces.iter().enumerate() {
-        println!("  {}. {}", i + 1, iface);
-    }
+                    // Update strobe duration
+                    if new_config.strobe_duration_ms != config.strobe_duration_ms {
+                        state.strobe_duration_ms = new_config.strobe_duration_ms;
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Strobe duration updated to: {:.0} ms",
+                                get_timestamp(),
+                                new_config.strobe_duration_ms
+                            ));
+                        }
+                    }
 
-    // Prompt for interface selection
-    let interface = loop {
-        print!("\nSelect interface (1-{}): ", interfaces.len());
-        io::stdout().flush()?;
+                    // Update strobe color
+                    if new_config.strobe_color != config.strobe_color {
+                        state.strobe_color = new_config.strobe_color.clone();
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Strobe color updated to: {}",
+                                get_timestamp(),
+                                new_config.strobe_color
+                            ));
+                        }
+                    }
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
+                    // Update peak-hold markers
+                    if new_config.peak_hold != config.peak_hold
+                        || new_config.peak_color != config.peak_color
+                        || new_config.tx_peak_color != config.tx_peak_color
+                        || new_config.rx_peak_color != config.rx_peak_color
+                        || new_config.peak_hold_ms != config.peak_hold_ms
+                        || new_config.peak_fall_percent_per_sec != config.peak_fall_percent_per_sec
+                    {
+                        state.peak_hold = PeakHoldConfig::from_config(&new_config);
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Peak hold: {} (hold {} ms, fall {}%/s)",
+                                get_timestamp(),
+                                if new_config.peak_hold { "enabled" } else { "disabled" },
+                                new_config.peak_hold_ms,
+                                new_config.peak_fall_percent_per_sec
+                            ));
+                        }
+                    }
 
-        if let Ok(choice) = input.trim().parse::<usize>() {
-            if choice > 0 && choice <= interfaces.len() {
-                break interfaces[choice - 1].clone();
-            }
-        }
-        println!("Invalid selection. Please enter a number between 1 and {}", interfaces.len());
-    };
+                    // Update gamma, brightness and current limit
+                    if new_config.gamma != config.gamma
+                        || new_config.brightness_percent != config.brightness_percent
+                        || new_config.milliamps_per_channel != config.milliamps_per_channel
+                    {
+                        state.correction = OutputCorrection::from_config(&new_config);
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Output correction updated: gamma {}, brightness {}%, {} mA per channel",
+                                get_timestamp(),
+                                new_config.gamma,
+                                new_config.brightness_percent,
+                                new_config.milliamps_per_channel
+                            ));
+                        }
+                    }
+                    // Update schedule and night mode
+                    if new_config.schedule != config.schedule
+                        || new_config.schedule_fade_minutes != config.schedule_fade_minutes
+                        || new_config.night_start != config.night_start
+                        || new_config.night_end != config.night_end
+                        || new_config.night_min_utilization_percent != config.night_min_utilization_percent
+                    {
+                        // Keep the night fade where it is so a reload doesn't snap the strip
+                        let (night_lit, night_gate) = (state.schedule.night_lit, state.schedule.night_gate);
+                        state.schedule = Schedule { night_lit, night_gate, ..Schedule::from_config(&new_config) };
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Schedule updated: {} entries, night {}",
+                                get_timestamp(),
+                                state.schedule.entries.len(),
+                                if state.schedule.night.is_some() {
+                                    format!(
+                                        "{}-{} above {}%",
+                                        new_config.night_start, new_config.night_end, new_config.night_min_utilization_percent
+                                    )
+                                } else {
+                                    "off".to_string()
+                                }
+                            ));
+                        }
+                    }
+                    if new_config.power_limit_ma != config.power_limit_ma && !quiet {
+                        messages.push(format!("[{}] Power limit updated to: {} mA", get_timestamp(), new_config.power_limit_ma));
+                    }
 
-    println!("Selected: {}\n", interface);
+                    // Update animation speed
+                    if new_config.animation_speed != config.animation_speed {
+                        state.animation_speed = new_config.animation_speed;
+                        if !quiet && new_config.animation_speed > 0.0 {
+                            messages.push(format!(
+                                "[{}] Animation speed: {:.3}",
+                                get_timestamp(),
+                                new_config.animation_speed
+                            ));
+                        }
+                    }
 
-    // 2. Prompt for WLED IP
-    print!("Enter WLED IP address or hostname (e.g., led.local or 192.168.1.100): ");
-    io::stdout().flush()?;
-    let mut wled_ip = String::new();
-    io::stdin().read_line(&mut wled_ip)?;
-    let wled_ip = wled_ip.trim().to_string();
+                    // Update animation speed scaling
+                    if new_config.scale_animation_speed != config.scale_animation_speed {
+                        state.scale_animation_speed = new_config.scale_animation_speed;
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Animation speed scaling: {}",
+                                get_timestamp(),
+                                if new_config.scale_animation_speed {
+                                    "enabled (scales with bandwidth)"
+                                } else {
+                                    "disabled (constant speed)"
+                                }
+                            ));
+                        }
+                    }
 
-    if wled_ip.is_empty() {
-        eprintln!("Error: WLED IP address is required!");
-        std::process::exit(1);
-    }
+                    // Update TX animation direction
+                    if new_config.tx_animation_direction != config.tx_animation_direction {
+                        state.tx_animation_direction = new_config.tx_animation_direction.clone();
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] TX animation direction: {}",
+                                get_timestamp(),
+                                new_config.tx_animation_direction
+                            ));
+                        }
+                    }
 
-    println!();
+                    // Update RX animation direction
+                    if new_config.rx_animation_direction != config.rx_animation_direction {
+                        state.rx_animation_direction = new_config.rx_animation_direction.clone();
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] RX animation direction: {}",
+                                get_timestamp(),
+                                new_config.rx_animation_direction
+                            ));
+                        }
+                    }
 
-    // 3. Prompt for total LEDs
-    let total_leds = loop {
-        print!("Enter total number of LEDs in your strip: ");
-        io::stdout().flush()?;
+                    // Update interpolation time
+                    if new_config.interpolation_time_ms != config.interpolation_time_ms {
+                        state.interpolation_time_ms = new_config.interpolation_time_ms;
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Interpolation time: {} ms",
+                                get_timestamp(),
+                                new_config.interpolation_time_ms
+                            ));
+                        }
+                    }
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
+                    // Update interpolation
+                    if new_config.interpolation != config.interpolation {
+                        let interpolation_mode = match new_config.interpolation.to_lowercase().as_str() {
+                            "basis" => InterpolationMode::Basis,
+                            "catmullrom" | "catmull-rom" => InterpolationMode::CatmullRom,
+                            _ => InterpolationMode::Linear,
+                        };
+                        state.interpolation_mode = interpolation_mode;
+                        state.generation += 1;
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Interpolation updated to: {}",
+                                get_timestamp(),
+                                new_config.interpolation
+                            ));
+                        }
+                    }
 
-        if let Ok(leds) = input.trim().parse::<usize>() {
-            if leds > 0 {
-                break leds;
-            }
-        }
-        println!("Invalid input. Please enter a positive number.");
-    };
+                    // Update display mode
+                    if new_config.display_mode != config.display_mode
+                        || new_config.history_style != config.history_style
+                        || new_config.history_leds_per_sample != config.history_leds_per_sample
+                    {
+                        state.history = HistoryConfig::from_config(&new_config);
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Display mode updated to: {} (history style {}, {} LEDs per sample)",
+                                get_timestamp(),
+                                new_config.display_mode,
+                                new_config.history_style,
+                                state.history.leds_per_sample
+                            ));
+                        }
+                    }
 
-    println!();
+                    // Update color mode, zones and interface bands
+                    if new_config.color_mode != config.color_mode
+                        || new_config.zone_colors != config.zone_colors
+                        || new_config.interface_colors != config.interface_colors
+                    {
+                        state.color_zones = new_config.color_mode.eq_ignore_ascii_case("zones");
+                        state.zone_colors = new_config.zone_colors.clone();
+                        state.interface_bands = InterfaceBandConfig::from_config(&new_config);
+                        state.generation += 1;
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Color mode updated to: {} (zones {})",
+                                get_timestamp(),
+                                new_config.color_mode,
+                                new_config.zone_colors
+                            ));
+                        }
+                    }
 
-    // 4. Prompt for max interface speed
-    let max_gbps = loop {
-        print!("Enter maximum interface speed in Gbps (e.g., 1.0, 2.5, 10.0): ");
-        io::stdout().flush()?;
+                    // Update gradient mode
+                    if new_config.use_gradient != config.use_gradient {
+                        state.use_gradient = new_config.use_gradient;
+                        state.generation += 1;
+                        if !quiet {
+                            messages.push(format!(
+                                "[{}] Gradient mode: {}",
+                                get_timestamp(),
+                                if new_config.use_gradient {
+                                    "enabled (smooth gradients)"
+                                } else {
+                                    "disabled (hard segments)"
+                                }
+                            ));
+                        }
+                    }
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
+                    // Update FPS
+                    if new_config.fps != config.fps {
+                        state.fps = new_config.fps;
+                        if !quiet {
+                            messages.push(format!("[{}] FPS updated to: {}", get_timestamp(), new_config.fps));
+                        }
+                    }
 
-        if let Ok(speed) = input.trim().parse::<f64>() {
-            if speed > 0.0 {
-                break speed;
-            }
-        }
-        println!("Invalid input. Please enter a positive number.");
-    };
+                    // Update matrix layout
+                    if (new_config.matrix_width != config.matrix_width
+                        || new_config.matrix_height != config.matrix_height
+                        || new_config.matrix_serpentine != config.matrix_serpentine
+                        || new_config.matrix_rotation != config.matrix_rotation
+                        || new_config.matrix_mode != config.matrix_mode)
+                        && !quiet
+                    {
+                        messages.push(format!(
+                            "[{}] Matrix updated to: {}x{} {} (rotation {}, {})",
+                            get_timestamp(),
+                            new_config.matrix_width,
+                            new_config.matrix_height,
+                            new_config.matrix_mode,
+                            new_config.matrix_rotation,
+                            if new_config.matrix_serpentine { "serpentine" } else { "progressive" }
+                        ));
+                    }
 
-    println!("\n=== Configuration Summary ===");
-    println!("Interface: {}", interface);
-    println!("WLED IP: {}", wled_ip);
-    println!("Total LEDs: {}", total_leds);
-    println!("Max Speed: {} Gbps", max_gbps);
-    println!("\nAll other settings will use default values.");
-    println!("You can modify these later via the config file or web interface at http://localhost:8080\n");
+                    // Update total_leds
+                    if new_config.total_leds != config.total_leds && !quiet {
+                        messages.push(format!("[{}] Total LEDs updated to: {}", get_timestamp(), new_config.total_leds));
+                    }
 
-    // Create config with provided values and defaults
-    let mut config = BandwidthConfig::default();
-    config.interface = interface;
-    config.wled_ip = wled_ip;
-    config.total_leds = total_leds;
-    config.max_gbps = max_gbps;
+                    // Additional targets inherit unset layout fields, so rebuild every layout.
+                    // The renderer keeps the targets it started with, extra layouts are ignored until restart
+                    state.targets = new_config.target_layouts();
+                    if new_config.targets != config.targets && !quiet {
+                        messages.push(format!("[{}] Target layouts updated", get_timestamp()));
+                    }
+                }
 
-    // Save the config
-    config.save()?;
-    println!("Configuration saved to: {}\n", BandwidthConfig::config_path()?.display());
-    println!("Starting bandwidth meter...\n");
+                // Note: Changes to WLED targets, interface and bandwidth source settings require restart
+                if new_config.target_outputs() != config.target_outputs()
+                    || new_config.interface != config.interface
+                    || new_config.sample_interval_ms != config.sample_interval_ms
+                    || new_config.detect_link_speed != config.detect_link_speed
+                    || new_config.source != config.source
+                    || new_config.snmp_host != config.snmp_host
+                    || new_config.snmp_port != config.snmp_port
+                    || new_config.snmp_version != config.snmp_version
+                    || new_config.snmp_community != config.snmp_community
+                    || new_config.snmp_username != config.snmp_username
+                    || new_config.snmp_auth_protocol != config.snmp_auth_protocol
+                    || new_config.snmp_auth_password != config.snmp_auth_password
+                    || new_config.prometheus_url != config.prometheus_url
+                    || new_config.prometheus_rx_metric != config.prometheus_rx_metric
+                    || new_config.prometheus_tx_metric != config.prometheus_tx_metric
+                    || new_config.prometheus_selector != config.prometheus_selector
+                {
+                    if !quiet {
+                        messages.push(format!("[{}] Note: WLED targets, interface or source settings changed - restart required", get_timestamp()));
+                    }
+                }
 
-    // Give user a moment to read the summary
-    thread::sleep(Duration::from_secs(2));
+                // Update test mode - immediately update bandwidth values and tracking vars
+                if new_config.test_tx != config.test_tx
+                    || new_config.test_rx != config.test_rx
+                    || new_config.test_tx_percent != config.test_tx_percent
+                    || new_config.test_rx_percent != config.test_rx_percent {
 
-    Ok(config)
-}
+                    // Calculate test bandwidth values
+                    let test_rx_kbps = if new_config.test_rx {
+                        new_config.fill_max() * (new_config.test_rx_percent / 100.0)
+                    } else {
+                        0.0
+                    };
 
+                    let test_tx_kbps = if new_config.test_tx {
+                        new_config.fill_max() * (new_config.test_tx_percent / 100.0)
+                    } else {
+                        0.0
+                    };
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+                    // Update shared state only if test mode is enabled
+                    let mut state = shared_state.lock().unwrap();
 
-    if args.test.is_some() {
-        // Test mode needs tokio runtime
-        let rt = tokio::runtime::Runtime::new()?;
-        return rt.block_on(test_mode(&args));
-    }
+                    if new_config.test_rx {
+                        state.start_rx_kbps = state.current_rx_kbps;
+                        state.current_rx_kbps = test_rx_kbps;
+                        state.last_bandwidth_update = Some(Instant::now());
+                    }
 
-    // Check for first-run scenario BEFORE setting up terminal
-    // First-run: no config file exists AND no command-line args provided
-    let config_path = BandwidthConfig::config_path()?;
-    let config_file_exists = config_path.exists();
+                    if new_config.test_tx {
+                        state.start_tx_kbps = state.current_tx_kbps;
+                        state.current_tx_kbps = test_tx_kbps;
+                        state.last_bandwidth_update = Some(Instant::now());
+                    }
 
-    // Check if any meaningful args were provided (excluding program name)
-    let has_args = std::env::args().len() > 1;
+                    drop(state);
 
-    if !config_file_exists && !has_args {
-        // First run - run interactive setup
-        let _config = run_first_time_setup()?;
-        // Config has been saved by run_first_time_setup, continue to normal startup
-    }
+                    if !quiet {
+                        if new_config.test_tx != config.test_tx {
+                            messages.push(format!(
+                                "[{}] Test TX: {}",
+                                get_timestamp(),
+                                if new_config.test_tx { "enabled" } else { "disabled" }
+                            ));
+                        }
+                        if new_config.test_rx != config.test_rx {
+                            messages.push(format!(
+                                "[{}] Test RX: {}",
+                                get_timestamp(),
+                                if new_config.test_rx { "enabled" } else { "disabled" }
+                            ));
+                        }
+                        if new_config.test_tx_percent != config.test_tx_percent && new_config.test_tx {
+                            messages.push(format!(
+                                "[{}] Test TX utilization: {:.0}%",
+                                get_timestamp(),
+                                new_config.test_tx_percent
+                            ));
+                        }
+                        if new_config.test_rx_percent != config.test_rx_percent && new_config.test_rx {
+                            messages.push(format!(
+                                "[{}] Test RX utilization: {:.0}%",
+                                get_timestamp(),
+                                new_config.test_rx_percent
+                            ));
+                        }
+                    }
+                }
 
-    // Create tokio runtime for bandwidth reading task only - keep it alive for entire session
-    let _rt = tokio::runtime::Runtime::new()?;
+                {
+                    let mut status = meter_status.lock().unwrap();
+                    status.max_gbps_source = max_gbps_source(&new_config);
+                    if !new_config.auto_range {
+                        status.effective_max_gbps = new_config.max_gbps;
+                    }
+                }
 
-    // Load existing config or create default, then merge with command line args
-    // Note: config_file_exists was already checked above for first-run detection
-    let mut config = BandwidthConfig::load_or_default();
-    let args_provided = config.merge_with_args(&args);
+                // Update config for future comparisons
+                config = new_config;
 
-    // Only save if command-line args were provided OR if config file doesn't exist
-    // This prevents overwriting existing config values on every launch
-    // After first-run setup, config_file_exists will be true, so this only saves if args provided
-    if args_provided || !config_file_exists {
-        config.save()?;
-    }
+                needs_render = true;
+            }
+            Err(_) => {
+                // No config update
+            }
+        }
 
-    // IMPORTANT: Establish SSH connection BEFORE setting up TUI
-    // This allows SSH to prompt for password using normal stdin/stdout
-    let quiet = args.quiet;
+        // Switch the strip, the status bar and the HTTP API between live, stale and lost readings
+        let new_data_state = DataState::from_readings(source_lost, last_sample_at, stale_timeout(&config), Instant::now());
+        if new_data_state != data_state {
+            shared_state.lock().unwrap().data_state = new_data_state;
+            meter_status.lock().unwrap().data_state = new_data_state.as_str();
+            if !quiet {
+                if new_data_state == DataState::Stale {
+                    messages.push(format!(
+                        "[{}] No data for {:.0}s, showing the no data pattern",
+                        get_timestamp(),
+                        last_sample_at.elapsed().as_secs_f64()
+                    ));
+                } else if new_data_state == DataState::Live && data_state == DataState::Stale {
+                    messages.push(format!("[{}] Data resumed", get_timestamp()));
+                }
+            }
+            data_state = new_data_state;
+            needs_render = true;
+        }
 
-    println!("Connecting to bandwidth monitor...");
-    if args.host.is_some() {
-        println!("Please enter your SSH password when prompted...\n");
-    }
+        // Render only when something changed
+        if needs_render {
+            let mut status_text = if config.httpd_enabled {
+                format!(
+                    "Edit {} to change settings | Web UI: http://{}:{} | Press Ctrl+C to quit",
+                    config_path.display(),
+                    config.httpd_ip,
+                    config.httpd_port
+                )
+            } else {
+                format!(
+                    "Edit {} to change settings | Press Ctrl+C to quit",
+                    config_path.display()
+                )
+            };
+            match data_state {
+                DataState::Lost => status_text.insert_str(0, "SOURCE LOST | "),
+                DataState::Stale => status_text.insert_str(0, "NO DATA | "),
+                DataState::Live => {}
+            }
 
-    let child_result = _rt.block_on(spawn_bandwidth_monitor(&args, &config));
-    let mut child = match child_result {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("Error: Failed to start bandwidth monitor: {}", e);
-            return Err(e);
-        }
-    };
+            terminal.draw(|f| {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(1), Constraint::Length(3)].as_ref())
+                    .split(f.size());
 
-    // For remote connections, wait for first line of output to ensure connection succeeded
-    if args.host.is_some() {
-        println!("Waiting for connection to establish...");
+                // Messages area
+                let messages_text: Vec<Line> = messages
+                    .iter()
+                    .rev()
+                    .take(chunks[0].height as usize)
+                    .rev()
+                    .map(|m| Line::from(m.as_str()))
+                    .collect();
 
-        let wait_result = _rt.block_on(async {
-            if let Some(stdout) = child.stdout.take() {
-                let mut reader = BufReader::new(stdout);
-                let mut first_line = String::new();
+                let messages_widget = Paragraph::new(messages_text).block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title("Bandwidth Monitor"),
+                );
+                f.render_widget(messages_widget, chunks[0]);
 
-                match reader.read_line(&mut first_line).await {
-                    Ok(0) => {
-                        Err(anyhow::anyhow!("SSH connection failed or closed immediately"))
-                    }
-                    Ok(_) => {
-                        println!("Connection established!");
-                        // Put stdout back for later use
-                        child.stdout = Some(reader.into_inner());
-                        Ok(())
-                    }
-                    Err(e) => {
-                        Err(anyhow::anyhow!("Error reading from SSH: {}", e))
-                    }
-                }
-            } else {
-                Err(anyhow::anyhow!("No stdout available"))
-            }
-        });
+                // Status/Input area
+                let status = Paragraph::new(status_text.clone())
+                    .block(Block::default().borders(Borders::ALL).title("Status"));
+                f.render_widget(status, chunks[1]);
+            })?;
 
-        if let Err(e) = wait_result {
-            eprintln!("Error: {}", e);
-            eprintln!("Please check your SSH credentials and try again");
-            return Err(e);
+            needs_render = false;
         }
+
+        // Small sleep to avoid busy-waiting CPU at 100%
+        // Renderer runs in separate thread, so main loop can sleep longer
+        std::thread::sleep(std::time::Duration::from_millis(10));
     }
 
-    println!("Connected successfully!\n");
+    Ok(())
+}
 
-    // Clear the terminal to remove password prompt residue
-    print!("\x1B[2J\x1B[1;1H");
-    io::stdout().flush()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_file_round_trips_secrets_with_quotes() {
+        let config = BandwidthConfig {
+            snmp_community: r#"pub"lic\"#.to_string(),
+            snmp_username: "o'brien".to_string(),
+            snmp_auth_password: r#"p\a"s's\"word"#.to_string(),
+            prometheus_selector: r#"device="eth0""#.to_string(),
+            ..BandwidthConfig::default()
+        };
+        let loaded: BandwidthConfig = toml::from_str(&config.to_toml().unwrap()).unwrap();
+        assert_eq!(loaded.snmp_community, config.snmp_community);
+        assert_eq!(loaded.snmp_username, config.snmp_username);
+        assert_eq!(loaded.snmp_auth_password, config.snmp_auth_password);
+        assert_eq!(loaded.prometheus_selector, config.prometheus_selector);
+    }
 
-    // NOW setup terminal - after SSH connection is established
-    enable_raw_mode()?;
-    let mut stdout_handle = io::stdout();
-    stdout_handle.execute(EnterAlternateScreen)?;
-    stdout_handle.flush()?;
-    let backend = CrosstermBackend::new(stdout_handle);
-    let mut terminal = Terminal::new(backend)?;
-    terminal.clear()?;
-    terminal.hide_cursor()?;
+    #[test]
+    fn opc_targets_send_rgb_triplets_whatever_the_pixel_format() {
+        let config = BandwidthConfig {
+            protocol: "opc".to_string(),
+            pixel_format: "grbw".to_string(),
+            targets: vec![TargetConfig {
+                wled_ip: "10.0.0.2".to_string(),
+                protocol: Some("ddp".to_string()),
+                ..TargetConfig::default()
+            }],
+            ..BandwidthConfig::default()
+        };
+        let outputs = config.target_outputs();
+        let frame = vec![200, 100, 50, 255, 255, 255];
 
-    // Setup panic handler to ensure terminal cleanup
-    let original_hook = std::panic::take_hook();
-    std::panic::set_hook(Box::new(move |panic_info| {
-        let _ = disable_raw_mode();
-        let _ = io::stdout().execute(LeaveAlternateScreen);
-        let _ = io::stdout().execute(Show);
-        original_hook(panic_info);
-    }));
+        assert_eq!(outputs[0].encode_frame(frame.clone()), frame);
+        assert!(!outputs[0].describe().contains("grbw"));
+        // The DDP target with the same pixel format gets its 4 channels
+        assert_eq!(outputs[1].encode_frame(frame), [50, 150, 0, 50, 0, 0, 0, 255]);
+    }
 
-    // Create shared state for renderer
-    let tx_color = if config.tx_color.is_empty() {
-        config.color.clone()
-    } else {
-        config.tx_color.clone()
-    };
-    let rx_color = if config.rx_color.is_empty() {
-        config.color.clone()
-    } else {
-        config.rx_color.clone()
-    };
+    // Records every frame it's given
+    struct RecordingSink(Arc<Mutex<Vec<Vec<u8>>>>);
 
-    let interpolation_mode = match config.interpolation.to_lowercase().as_str() {
-        "basis" => InterpolationMode::Basis,
-        "catmullrom" | "catmull-rom" => InterpolationMode::CatmullRom,
-        _ => InterpolationMode::Linear,
-    };
+    impl PixelSink for RecordingSink {
+        fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+            self.0.lock().unwrap().push(frame.to_vec());
+            Ok(())
+        }
+    }
 
-    let direction = match config.direction.to_lowercase().as_str() {
-        "mirrored" => DirectionMode::Mirrored,
-        "opposing" => DirectionMode::Opposing,
-        "left" => DirectionMode::Left,
-        "right" => DirectionMode::Right,
-        _ => DirectionMode::Mirrored,
-    };
+    // Hangs on the first frame until the test ends, then fails
+    struct BlockingSink(mpsc::Receiver<()>);
 
-    // Create shutdown flag for clean termination
-    let shutdown = Arc::new(AtomicBool::new(false));
+    impl PixelSink for BlockingSink {
+        fn send_frame(&mut self, _frame: &[u8]) -> Result<()> {
+            let _ = self.0.recv();
+            anyhow::bail!("stalled")
+        }
+    }
 
-    let shared_state = Arc::new(Mutex::new(SharedRenderState {
-        current_rx_kbps: 0.0,
-        current_tx_kbps: 0.0,
-        start_rx_kbps: 0.0,
-        start_tx_kbps: 0.0,
-        last_bandwidth_update: None,
-        animation_speed: config.animation_speed,
-        scale_animation_speed: config.scale_animation_speed,
-        tx_animation_direction: config.tx_animation_direction.clone(),
-        rx_animation_direction: config.rx_animation_direction.clone(),
-        interpolation_time_ms: config.interpolation_time_ms,
-        max_bandwidth_kbps: config.max_gbps * 1000.0 * 1000.0,
-        tx_color,
-        rx_color,
-        use_gradient: config.use_gradient,
-        interpolation_mode,
-        direction,
-        swap: config.swap,
-        fps: config.fps,
-        total_leds: config.total_leds,
-        rx_split_percent: config.rx_split_percent,
-        strobe_on_max: config.strobe_on_max,
-        strobe_rate_hz: config.strobe_rate_hz,
-        strobe_duration_ms: config.strobe_duration_ms,
-        strobe_color: config.strobe_color.clone(),
-        generation: 0,
-    }));
+    #[test]
+    fn a_stuck_or_unreachable_target_does_not_cost_the_others_frames() {
+        let target = |address: &str| TargetConfig {
+            wled_ip: address.to_string(),
+            ..TargetConfig::default()
+        };
+        let config = BandwidthConfig {
+            total_leds: 10,
+            targets: vec![target("10.0.0.2"), target("10.0.0.3")],
+            ..BandwidthConfig::default()
+        };
+        let outputs = config.target_outputs();
+        let (log_tx, _log_rx) = mpsc::channel();
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let healthy_frames = received.clone();
+        let healthy = spawn_sink_sender(
+            outputs[0].clone(),
+            move || Ok(Box::new(RecordingSink(healthy_frames.clone())) as Box<dyn PixelSink>),
+            log_tx.clone(),
+        );
+        let (_unblock, blocked) = mpsc::channel();
+        let mut blocked = Some(blocked);
+        let stuck = spawn_sink_sender(
+            outputs[1].clone(),
+            move || Ok(Box::new(BlockingSink(blocked.take().unwrap())) as Box<dyn PixelSink>),
+            log_tx.clone(),
+        );
+        let unreachable = spawn_sink_sender(outputs[2].clone(), || anyhow::bail!("no route to host"), log_tx);
 
-    // Create DDP connection for renderer
-    let dest_addr = format!("{}:4048", config.wled_ip);
-    let socket = match UdpSocket::bind("0.0.0.0:4048") {
-        Ok(s) => s,
-        Err(e) => {
-            terminal.show_cursor()?;
-            disable_raw_mode()?;
-            terminal.backend_mut().execute(LeaveAlternateScreen)?;
-            return Err(e.into());
+        let encoding = PixelEncoding::parse("rgb", "none");
+        let targets = [healthy, stuck, unreachable]
+            .into_iter()
+            .map(|frames| RenderTarget::new(frames, encoding.clone()))
+            .collect();
+        let shared_state = Arc::new(Mutex::new(SharedRenderState::from_config(&config)));
+        let mut renderer = Renderer::new(targets, shared_state, Arc::new(AtomicBool::new(false))).unwrap();
+
+        // Each frame reaches the healthy target before the next one is rendered
+        for i in 1..=20 {
+            renderer.render_frame(0.016).unwrap();
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while received.lock().unwrap().len() < i {
+                assert!(Instant::now() < deadline, "frame {} never reached the healthy target", i);
+                thread::sleep(Duration::from_millis(1));
+            }
         }
-    };
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 20);
+        assert!(received.iter().all(|frame| frame.len() == 30));
+    }
 
-    let ddp_conn = match DDPConnection::try_new(&dest_addr, PixelConfig::default(), ID::Default, socket) {
-        Ok(conn) => conn,
-        Err(e) => {
-            terminal.show_cursor()?;
-            disable_raw_mode()?;
-            terminal.backend_mut().execute(LeaveAlternateScreen)?;
-            return Err(e.into());
-        }
-    };
+    #[test]
+    fn opc_messages_frame_pixels_for_the_target_channel() {
+        assert_eq!(OpcSink::message(0, &[1, 2, 3, 4, 5, 6]), [0, 0, 0, 6, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(OpcSink::message(7, &[]), [7, 0, 0, 0]);
+        // Cut to the last whole pixel that fits the 16-bit length
+        let message = OpcSink::message(1, &numbered_frame(22_000));
+        assert_eq!(message[..4], [1, 0, 0xff, 0xff]);
+        assert_eq!(message.len(), 4 + 65535);
+
+        // Each target uses its own opc_channel, and a port in wled_ip replaces 7890
+        let config = BandwidthConfig {
+            wled_ip: "10.0.0.1".to_string(),
+            protocol: "opc".to_string(),
+            opc_channel: 2,
+            targets: vec![TargetConfig {
+                wled_ip: "10.0.0.2:7891".to_string(),
+                opc_channel: Some(5),
+                ..TargetConfig::default()
+            }],
+            ..BandwidthConfig::default()
+        };
+        let sinks: Vec<_> = config.target_outputs().iter().map(OpcSink::new).collect();
+        assert_eq!((sinks[0].address.as_str(), sinks[0].channel), ("10.0.0.1:7890", 2));
+        assert_eq!((sinks[1].address.as_str(), sinks[1].channel), ("10.0.0.2:7891", 5));
+    }
 
-    // Create renderer
-    let renderer = match Renderer::new(ddp_conn, shared_state.clone(), shutdown.clone()) {
-        Ok(r) => r,
-        Err(e) => {
-            terminal.show_cursor()?;
-            disable_raw_mode()?;
-            terminal.backend_mut().execute(LeaveAlternateScreen)?;
-            return Err(e);
+    #[test]
+    fn opc_sink_reconnects_after_the_server_drops_it() {
+        use std::io::Read;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (message_tx, message_rx) = mpsc::channel();
+        thread::spawn(move || {
+            // The first connection is closed straight away, the second one gets read
+            drop(listener.accept().unwrap());
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut message = [0; 10];
+            stream.read_exact(&mut message).unwrap();
+            message_tx.send(message).unwrap();
+        });
+
+        let config = BandwidthConfig {
+            wled_ip: format!("127.0.0.1:{}", port),
+            protocol: "opc".to_string(),
+            opc_channel: 3,
+            ..BandwidthConfig::default()
+        };
+        let mut sink = OpcSink::new(&config.target_outputs()[0]);
+        let frame = [1, 2, 3, 4, 5, 6];
+
+        // Writes into the closed connection fail once the server's reset comes back
+        let mut failed = false;
+        for _ in 0..100 {
+            if sink.send_frame(&frame).is_err() {
+                failed = true;
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
         }
-    };
+        assert!(failed, "writes to the dropped connection kept succeeding");
+
+        // Frames in between fail without a connection attempt, the next one after the interval reconnects
+        assert!(sink.send_frame(&frame).unwrap_err().to_string().contains("not connected"));
+        thread::sleep(OpcSink::RECONNECT_INTERVAL);
+        sink.send_frame(&frame).unwrap();
+        let message = message_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(message, [3, 0, 0, 6, 1, 2, 3, 4, 5, 6]);
+    }
 
-    // Spawn dedicated render thread - runs at 60 FPS independently
-    thread::spawn(move || {
-        renderer.run();
-    });
+    #[test]
+    fn pixel_encoding_reorders_channels() {
+        let frame = vec![10, 20, 30, 255, 0, 128];
+        assert_eq!(PixelEncoding::parse("rgb", "accurate").encode(frame.clone()), frame);
+        assert_eq!(PixelEncoding::parse("GRB", "accurate").encode(frame.clone()), [20, 10, 30, 0, 255, 128]);
+        assert_eq!(PixelEncoding::parse("bgr", "accurate").encode(frame.clone()), [30, 20, 10, 128, 0, 255]);
+        assert_eq!(PixelEncoding::parse("grb", "accurate").name(), "grb");
+    }
 
-    let (bandwidth_tx, bandwidth_rx) = mpsc::channel::<String>();
-    let (config_tx, config_rx) = mpsc::channel::<BandwidthConfig>();
+    #[test]
+    fn pixel_encoding_extracts_white() {
+        let frame = vec![200, 100, 50, 0, 0, 0];
+        assert_eq!(PixelEncoding::parse("rgbw", "accurate").encode(frame.clone()), [150, 50, 0, 50, 0, 0, 0, 0]);
+        assert_eq!(PixelEncoding::parse("rgbw", "brighter").encode(frame.clone()), [200, 100, 50, 50, 0, 0, 0, 0]);
+        assert_eq!(PixelEncoding::parse("rgbw", "none").encode(frame.clone()), [200, 100, 50, 0, 0, 0, 0, 0]);
+        assert_eq!(PixelEncoding::parse("wrgb", "accurate").encode(frame), [50, 150, 50, 0, 0, 0, 0, 0]);
+    }
 
-    // Message log stored locally
-    let mut messages: Vec<String> = Vec::new();
+    #[test]
+    fn invalid_pixel_formats_fall_back_to_rgb() {
+        for format in ["", "rg", "rrgb", "rgbww", "xyz"] {
+            let encoding = PixelEncoding::parse(format, "accurate");
+            assert!(encoding.is_rgb(), "{:?}", format);
+            assert_eq!(encoding.channels(), 3);
+        }
+        // Unknown white modes extract white accurately
+        assert_eq!(PixelEncoding::parse("rgbw", "bogus").white_mode, WhiteMode::Accurate);
+    }
 
-    let leds_per_direction = config.total_leds / 2;
+    #[test]
+    fn ddp_pixel_config_matches_the_channel_count() {
+        use ddp_rs::protocol::{DataType, PixelFormat};
+        let rgbw = PixelEncoding::parse("grbw", "accurate").ddp_config();
+        assert!(matches!(rgbw.data_type, DataType::RGBW));
+        assert!(matches!(rgbw.data_size, PixelFormat::Pixel32Bits));
+        let rgb = PixelEncoding::parse("bgr", "accurate").ddp_config();
+        assert!(matches!(rgb.data_type, DataType::RGB));
+        assert!(matches!(rgb.data_size, PixelFormat::Pixel24Bits));
+    }
 
-    // Helper function to calculate LEDs (same logic as renderer)
-    let calculate_leds = |bandwidth_kbps: f64, max_bandwidth_kbps: f64| -> usize {
-        let percentage = bandwidth_kbps / max_bandwidth_kbps;
-        let leds = (percentage * leds_per_direction as f64) as usize;
-        leds.min(leds_per_direction)
-    };
+    #[test]
+    fn power_estimate_counts_the_white_channel() {
+        let correction = OutputCorrection::from_config(&BandwidthConfig {
+            milliamps_per_channel: 20.0,
+            ..BandwidthConfig::default()
+        });
+        let white = vec![255, 255, 255];
+        let orange = vec![255, 102, 0];
+        let estimate = |frame: &[u8], format: &str, white_mode: &str| {
+            correction.channel_milliamps(frame, &PixelEncoding::parse(format, white_mode))
+        };
 
-    // Add initial message
-    if !quiet {
-        messages.push(format!(
-            "[{}] Bandwidth meter started. Max: {} Gbps",
-            get_timestamp(),
-            config.max_gbps
-        ));
-        messages.push(format!(
-            "[{}] Interface: {}, LEDs: {}, WLED: {}",
-            get_timestamp(),
-            config.interface, config.total_leds, config.wled_ip
-        ));
-        messages.push(format!("[{}] Config file: {}", get_timestamp(), config_path.display()));
-        messages.push(format!("[{}] Edit config file to change settings while running", get_timestamp()));
-        messages.push(format!("[{}] Debug log: /tmp/bandwidth_debug.log", get_timestamp()));
+        assert_eq!(estimate(&white, "rgb", "accurate"), 60.0);
+        assert_eq!(estimate(&white, "grb", "accurate"), 60.0);
+        // Accurate moves the common part to the white LED, brighter adds it on top
+        assert_eq!(estimate(&white, "rgbw", "accurate"), 20.0);
+        assert_eq!(estimate(&white, "rgbw", "brighter"), 80.0);
+        assert_eq!(estimate(&white, "rgbw", "none"), 60.0);
+        assert_eq!(estimate(&orange, "rgbw", "brighter"), 28.0);
     }
 
-    // Spawn bandwidth reader in separate tokio task
-    let stdout = child.stdout.take().expect("Failed to capture stdout");
-    _rt.spawn(async move {
-        let reader = BufReader::new(stdout);
-        let mut lines = reader.lines();
+    #[test]
+    fn power_limit_keeps_brighter_rgbw_within_budget() {
+        let correction = OutputCorrection::from_config(&BandwidthConfig {
+            milliamps_per_channel: 20.0,
+            ..BandwidthConfig::default()
+        });
+        let encoding = PixelEncoding::parse("grbw", "brighter");
+        // 100 LEDs of full white: 100 mA idle plus 8000 mA with the white channel
+        let mut frame = vec![255; 300];
+        correction.apply(&mut frame, 1.0, 2100.0, &encoding);
+        let drawn = 100.0 * OutputCorrection::IDLE_MILLIAMPS_PER_LED + correction.channel_milliamps(&frame, &encoding);
+        assert!(drawn <= 2100.0 && drawn > 2000.0, "{}", drawn);
+
+        // The same frame sent as RGB only needs 6000 mA at full brightness, so it is dimmed less
+        let mut rgb_frame = vec![255; 300];
+        correction.apply(&mut rgb_frame, 1.0, 2100.0, &PixelEncoding::parse("rgb", "none"));
+        assert!(rgb_frame[0] > frame[0]);
+    }
 
-        // Always create debug log file
-        let mut debug_log = std::fs::File::create("/tmp/bandwidth_debug.log").ok();
+    #[test]
+    fn snmp_secrets_are_not_serialized() {
+        let config = BandwidthConfig {
+            snmp_community: "s3cret-community".to_string(),
+            snmp_auth_password: "s3cret-password".to_string(),
+            ..BandwidthConfig::default()
+        };
+        let json = serde_json::to_value(&config).unwrap();
+        assert!(json.get("snmp_community").is_none());
+        assert!(json.get("snmp_auth_password").is_none());
+        assert_eq!(json["snmp_username"], "");
+        assert!(!json.to_string().contains("s3cret"));
+
+        // The config file still keeps them
+        let contents = config.to_toml().unwrap();
+        assert!(contents.contains("snmp_community = \"s3cret-community\""));
+        assert!(contents.contains("snmp_auth_password = \"s3cret-password\""));
+    }
 
-        while let Ok(Some(line)) = lines.next_line().await {
-            // Debug: write raw line with timestamp to file when received from SSH
-            if let Some(ref mut log) = debug_log {
-                use std::io::Write;
-                let _ = writeln!(log, "[{}] SSH OUTPUT: {}", get_timestamp(), line);
-                let _ = log.flush(); // Flush immediately so tail -f works
-            }
+    // A renderer whose single target sends its frames to the returned receiver instead of a controller
+    fn test_renderer(config: &BandwidthConfig) -> (Renderer, mpsc::Receiver<Vec<u8>>, Arc<Mutex<SharedRenderState>>) {
+        let (frame_tx, frame_rx) = mpsc::sync_channel(1);
+        let target = RenderTarget::new(frame_tx, PixelEncoding::parse("rgb", "none"));
+        let shared_state = Arc::new(Mutex::new(SharedRenderState::from_config(config)));
+        let renderer = Renderer::new(vec![target], shared_state.clone(), Arc::new(AtomicBool::new(false))).unwrap();
+        (renderer, frame_rx, shared_state)
+    }
 
-            if bandwidth_tx.send(line).is_err() {
-                break; // Main thread dropped receiver, time to exit
-            }
+    // Indexes of the LEDs that are on in an RGB frame
+    fn lit_leds(frame: &[u8]) -> Vec<usize> {
+        frame.chunks_exact(3).enumerate().filter(|(_, rgb)| rgb.iter().any(|&v| v > 0)).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn segment_maps_parse_runs_in_fill_order() {
+        let map = SegmentMap::parse("0-3:rx, 4:unused, 9-6:tx, 5:status", 10).unwrap();
+        assert_eq!(map.rx, [0, 1, 2, 3]);
+        assert_eq!(map.tx, [9, 8, 7, 6]);
+        assert_eq!(map.status, [5]);
+
+        // Reversed runs, several runs per role joining up in order, and case and spacing don't matter
+        let map = SegmentMap::parse(" 0-2 : RX : reverse ,7-9:rx, 3-5:Tx:reverse", 10).unwrap();
+        assert_eq!(map.rx, [2, 1, 0, 7, 8, 9]);
+        assert_eq!(map.tx, [5, 4, 3]);
+        assert!(map.status.is_empty());
+
+        // An LED listed twice belongs to the first segment, unused ones included
+        let map = SegmentMap::parse("0-5:tx, 3-8:rx, 9:unused, 9:status", 10).unwrap();
+        assert_eq!(map.tx, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(map.rx, [6, 7, 8]);
+        assert!(map.status.is_empty());
+
+        // LEDs past total_leds are skipped, a segment entirely past it leaves its role empty
+        let map = SegmentMap::parse("8-12:rx, 20-29:tx", 10).unwrap();
+        assert_eq!(map.rx, [8, 9]);
+        assert!(map.tx.is_empty());
+
+        // Huge ranges are clamped to the strip instead of being built in full
+        let map = SegmentMap::parse("0-4000000000:tx, 4000000000-0:rx", 10).unwrap();
+        assert_eq!(map.tx, (0..10).collect::<Vec<_>>());
+        assert!(map.rx.is_empty());
+        let map = SegmentMap::parse("4000000000-7:rx:reverse, 18446744073709551615:tx", 10).unwrap();
+        assert_eq!(map.rx, [7, 8, 9]);
+        assert!(map.tx.is_empty());
+
+        // Malformed entries are skipped, a spec without any valid entry means no segment map
+        let map = SegmentMap::parse("0-4:rx, five-9:tx, 5-:tx, 6-7, 8:blue, 5-9:tx", 10).unwrap();
+        assert_eq!(map.rx, [0, 1, 2, 3, 4]);
+        assert_eq!(map.tx, [5, 6, 7, 8, 9]);
+        for spec in ["", " , ", "rx", "0-4", "a-b:tx", "0-4:sideways"] {
+            assert!(SegmentMap::parse(spec, 10).is_none(), "{:?}", spec);
         }
-    });
+    }
 
-    // Spawn config file watcher thread
-    let config_path_clone = config_path.clone();
-    std::thread::spawn(move || -> Result<()> {
-        let (tx, rx) = mpsc::channel();
-        let mut watcher = match RecommendedWatcher::new(tx, Config::default()) {
-            Ok(w) => w,
-            Err(_) => return Ok(()),
+    #[test]
+    fn segment_maps_place_the_bars() {
+        let config = BandwidthConfig {
+            total_leds: 10,
+            segments: "0-3:rx, 4:unused, 9-6:tx, 5:status".to_string(),
+            ..BandwidthConfig::default()
         };
-
-        if watcher
-            .watch(&config_path_clone, RecursiveMode::NonRecursive)
-            .is_err()
+        let (mut renderer, frames, state) = test_renderer(&config);
+        let layout = config.target_layouts().remove(0);
+        assert_eq!(renderer.calculate_led_positions(2, 3, &layout), (vec![9, 8], vec![0, 1, 2]));
+        // swap exchanges the runs
+        let swapped = TargetLayout { swap: true, ..layout.clone() };
+        assert_eq!(renderer.calculate_led_positions(2, 3, &swapped), (vec![0, 1], vec![9, 8, 7]));
+
+        // Half of max on TX and all of it on RX: half the TX run, the whole RX run and the status pixel
         {
-            return Ok(());
+            let mut state = state.lock().unwrap();
+            state.current_tx_kbps = state.max_bandwidth_kbps / 2.0;
+            state.current_rx_kbps = state.max_bandwidth_kbps;
         }
+        renderer.render_frame(0.016).unwrap();
+        assert_eq!(lit_leds(&frames.try_recv().unwrap()), [0, 1, 2, 3, 5, 8, 9]);
+    }
 
-        loop {
-            match rx.recv() {
-                Ok(Ok(NotifyEvent { kind, .. })) => {
-                    // Only respond to modify events
-                    if matches!(kind, notify::EventKind::Modify(_)) {
-                        if let Ok(new_config) = BandwidthConfig::load() {
-                            let _ = config_tx.send(new_config);
-                        }
-                    }
+    #[test]
+    fn matrix_index_follows_rotation_and_serpentine_wiring() {
+        // A 3x2 panel wired from the top left, rows left to right (or alternating with serpentine):
+        //   0 1 2        0 1 2
+        //   3 4 5        5 4 3
+        // and the LED indexes seen on the wall once it is turned clockwise, row by row
+        let cases: [(u16, bool, &[&[usize]]); 8] = [
+            (0, false, &[&[0, 1, 2], &[3, 4, 5]]),
+            (90, false, &[&[3, 0], &[4, 1], &[5, 2]]),
+            (180, false, &[&[5, 4, 3], &[2, 1, 0]]),
+            (270, false, &[&[2, 5], &[1, 4], &[0, 3]]),
+            (0, true, &[&[0, 1, 2], &[5, 4, 3]]),
+            (90, true, &[&[5, 0], &[4, 1], &[3, 2]]),
+            (180, true, &[&[3, 4, 5], &[2, 1, 0]]),
+            (270, true, &[&[2, 3], &[1, 4], &[0, 5]]),
+        ];
+        for (rotation, serpentine, rows) in cases {
+            let matrix = MatrixLayout::parse(3, 2, serpentine, rotation, "bars").unwrap();
+            assert_eq!(matrix.display_size(), (rows[0].len(), rows.len()), "{} {}", rotation, serpentine);
+            for (y, row) in rows.iter().enumerate() {
+                for (x, &led) in row.iter().enumerate() {
+                    assert_eq!(matrix.index(x, y), led, "rotation {} serpentine {} at ({}, {})", rotation, serpentine, x, y);
                 }
-                _ => {}
             }
         }
-    });
 
-    // Start HTTP server if enabled
-    if config.httpd_enabled {
-        let httpd_ip = config.httpd_ip.clone();
-        let httpd_port = config.httpd_port;
-        
-        _rt.spawn(async move {
-            if let Err(e) = run_http_server(httpd_ip.clone(), httpd_port).await {
-                eprintln!("HTTP server error: {}", e);
-            }
-        });
+        // Rotations snap down to quarter turns, and an empty panel is no matrix
+        assert_eq!(MatrixLayout::parse(3, 2, false, 450, "bars").unwrap().rotation, 90);
+        assert_eq!(MatrixLayout::parse(3, 2, false, 45, "bars").unwrap().rotation, 0);
+        assert!(MatrixLayout::parse(0, 8, false, 0, "bars").is_none());
     }
 
-    // Force initial render
-    {
-        let status_line = if config.httpd_enabled {
-            format!(
-                "Edit {} to change settings | Web UI: http://{}:{} | Press Ctrl+C to quit",
-                config_path.display(),
-                config.httpd_ip,
-                config.httpd_port
-            )
-        } else {
-            format!(
-                "Edit {} to change settings | Press Ctrl+C to quit",
-                config_path.display()
-            )
-        };
-
-        terminal.draw(|f| {
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Min(1), Constraint::Length(3)].as_ref())
-                .split(f.size());
+    #[test]
+    fn calculate_leds_splits_off_the_partial_leading_led() {
+        let (renderer, _frames, _state) = test_renderer(&BandwidthConfig::default());
+        let linear = BandwidthScale::from_config(&BandwidthConfig::default());
+        let leds = |kbps: f64| renderer.calculate_leds(kbps, 1600.0, &linear, 16);
+
+        assert_eq!(leds(0.0), (0, 0.0));
+        assert_eq!(leds(50.0), (0, 0.5));
+        // Exactly on a pixel boundary there is nothing partial
+        assert_eq!(leds(100.0), (1, 0.0));
+        assert_eq!(leds(150.0), (1, 0.5));
+        assert_eq!(leds(1575.0), (15, 0.75));
+        // A full bar, and anything over max, has no partial LED past its end
+        assert_eq!(leds(1600.0), (16, 0.0));
+        assert_eq!(leds(5000.0), (16, 0.0));
+    }
 
-            let messages_text: Vec<Line> = messages
-                .iter()
-                .rev()
-                .take(chunks[0].height as usize)
-                .rev()
-                .map(|m| Line::from(m.as_str()))
-                .collect();
+    #[test]
+    fn leading_led_is_dimmed_to_its_partial_fill() {
+        // 30 LEDs split 15/15 with a solid 0099FF bar; 250 of 1500 kbps is 2.5 LEDs of TX
+        for (direction, full, partial) in [("mirrored", [15, 16], 17), ("opposing", [29, 28], 27)] {
+            let config = BandwidthConfig {
+                total_leds: 30,
+                direction: direction.to_string(),
+                ..BandwidthConfig::default()
+            };
+            let (mut renderer, frames, state) = test_renderer(&config);
+            let mut draw = |tx_kbps: f64| {
+                {
+                    let mut state = state.lock().unwrap();
+                    state.max_bandwidth_kbps = 1500.0;
+                    state.current_tx_kbps = tx_kbps;
+                }
+                renderer.render_frame(0.016).unwrap();
+                frames.try_recv().unwrap()
+            };
 
-            let messages_widget = Paragraph::new(messages_text).block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .title("Bandwidth Monitor"),
-            );
-            f.render_widget(messages_widget, chunks[0]);
+            let frame = draw(250.0);
+            let mut lit = full.to_vec();
+            lit.push(partial);
+            lit.sort();
+            assert_eq!(lit_leds(&frame), lit, "{}", direction);
+            for led in full {
+                assert_eq!(frame[led * 3..led * 3 + 3], [0x00, 0x99, 0xFF], "{}", direction);
+            }
+            let leading = &frame[partial * 3..partial * 3 + 3];
+            assert_eq!(leading[0], 0);
+            assert!((75..=77).contains(&leading[1]) && (126..=128).contains(&leading[2]), "{} {:?}", direction, leading);
+
+            // On a pixel boundary the next LED stays dark, and a little more traffic shows as a faint one
+            assert_eq!(lit_leds(&draw(200.0)).len(), 2);
+            let frame = draw(210.0);
+            assert_eq!(lit_leds(&frame).len(), 3);
+            assert!(frame[partial * 3 + 2] > 0 && frame[partial * 3 + 2] < 40, "{}", direction);
+        }
+    }
 
-            let status = Paragraph::new(status_line)
-                .block(Block::default().borders(Borders::ALL).title("Status"));
-            f.render_widget(status, chunks[1]);
-        })?;
+    #[test]
+    fn peak_hold_holds_then_falls_to_the_live_value() {
+        let config = PeakHoldConfig {
+            hold_secs: 1.0,
+            fall_per_sec: 0.5,
+            ..PeakHoldConfig::from_config(&BandwidthConfig::default())
+        };
+        let mut peak = PeakHold::default();
+        let mut update = |fraction: f64, delta_seconds: f64| peak.update(fraction, delta_seconds, &config);
+
+        assert_eq!(update(0.5, 0.25), 0.5);
+        assert_eq!(update(0.25, 0.5), 0.5);
+        // A new high resets the hold time
+        assert_eq!(update(0.75, 0.25), 0.75);
+        assert_eq!(update(0.25, 0.5), 0.75);
+        assert_eq!(update(0.25, 0.5), 0.75);
+        // Past the hold time the marker falls at fall_per_sec
+        assert_eq!(update(0.25, 0.25), 0.625);
+        assert_eq!(update(0.25, 0.25), 0.5);
+        // and stops at the live value instead of passing it
+        assert_eq!(update(0.25, 1.0), 0.25);
+        assert_eq!(update(0.25, 1.0), 0.25);
+        assert_eq!(update(0.125, 0.5), 0.25);
     }
 
-    let mut needs_render = true;
+    #[test]
+    fn peak_markers_follow_direction_and_swap() {
+        // 20 LEDs split 10/10 with nothing flowing: TX peak at half its bar (5 LEDs), RX at 30% (3 LEDs)
+        let cases = [
+            ("mirrored", false, 14, 7),
+            ("opposing", false, 15, 2),
+            ("left", false, 15, 7),
+            ("right", false, 14, 2),
+            ("mirrored", true, 5, 12),
+            ("opposing", true, 4, 17),
+            ("left", true, 5, 17),
+            ("right", true, 4, 12),
+        ];
+        for (direction, swap, tx_marker, rx_marker) in cases {
+            let config = BandwidthConfig {
+                total_leds: 20,
+                direction: direction.to_string(),
+                swap,
+                peak_hold: true,
+                tx_peak_color: "FF0000".to_string(),
+                rx_peak_color: "0000FF".to_string(),
+                ..BandwidthConfig::default()
+            };
+            let (mut renderer, frames, _state) = test_renderer(&config);
+            renderer.tx_peak.fraction = 0.5;
+            renderer.rx_peak.fraction = 0.3;
+            renderer.render_frame(0.016).unwrap();
+
+            let frame = frames.try_recv().unwrap();
+            let mut expected = vec![tx_marker, rx_marker];
+            expected.sort();
+            assert_eq!(lit_leds(&frame), expected, "{} swap {}", direction, swap);
+            assert_eq!(frame[tx_marker * 3..tx_marker * 3 + 3], [255, 0, 0], "{} swap {}", direction, swap);
+            assert_eq!(frame[rx_marker * 3..rx_marker * 3 + 3], [0, 0, 255], "{} swap {}", direction, swap);
+        }
+    }
 
-    // Initialize bandwidth tracker for Linux /proc/net/dev parsing
-    let mut bandwidth_tracker: Option<BandwidthTracker> = Some(BandwidthTracker::new());
+    #[test]
+    fn color_zones_parse_stops_and_pick_by_fill() {
+        let color_at = |zones: &[ColorZone], fraction: f64| zone_color(zones, fraction).map(|c| (c.r, c.g, c.b));
+        let starts = |zones: &[ColorZone]| zones.iter().map(|z| z.start).collect::<Vec<_>>();
+        let (green, amber, red) = (Some((0, 255, 0)), Some((255, 191, 0)), Some((255, 0, 0)));
+
+        let zones = build_color_zones("00FF00,FFBF00@60,FF0000@85").unwrap();
+        assert_eq!(starts(&zones), [0.0, 60.0, 85.0]);
+        assert_eq!(color_at(&zones, 0.5), green);
+        assert_eq!(color_at(&zones, 0.7), amber);
+        assert_eq!(color_at(&zones, 0.9), red);
+        assert_eq!(color_at(&zones, 1.5), red);
+
+        // Without stops the colors split the bar evenly
+        let zones = build_color_zones("00FF00,FFBF00,FF0000,0000FF").unwrap();
+        assert_eq!(starts(&zones), [0.0, 25.0, 50.0, 75.0]);
+        assert_eq!(color_at(&zones, 0.3), amber);
+
+        // Stops are sorted, and the lowest zone always starts at 0%
+        let zones = build_color_zones("FF0000@85,00FF00@20,FFBF00@60").unwrap();
+        assert_eq!(starts(&zones), [0.0, 60.0, 85.0]);
+        assert_eq!(color_at(&zones, 0.0), green);
+        assert_eq!(color_at(&zones, 0.1), green);
+        assert_eq!(color_at(&zones, 0.86), red);
+
+        assert!(zone_color(&[], 0.5).is_none());
+    }
 
-    // Initialize test mode bandwidth values if enabled
-    if config.test_tx || config.test_rx {
-        let mut state = shared_state.lock().unwrap();
-        if config.test_rx {
-            let test_rx_kbps = config.max_gbps * 1000.0 * 1000.0 * (config.test_rx_percent / 100.0);
-            state.current_rx_kbps = test_rx_kbps;
-            state.start_rx_kbps = test_rx_kbps;
-            state.last_bandwidth_update = Some(Instant::now());
+    #[test]
+    fn history_scrolls_out_from_the_bar_start() {
+        let config = BandwidthConfig {
+            total_leds: 20,
+            display_mode: "history".to_string(),
+            history_leds_per_sample: 2,
+            ..BandwidthConfig::default()
+        };
+        let (mut renderer, frames, state) = test_renderer(&config);
+        {
+            let mut state = state.lock().unwrap();
+            state.max_bandwidth_kbps = 1000.0;
+            state.record_history(HistorySample { rx_kbps: 0.0, tx_kbps: 250.0 });
+            state.record_history(HistorySample { rx_kbps: 500.0, tx_kbps: 1000.0 });
         }
-        if config.test_tx {
-            let test_tx_kbps = config.max_gbps * 1000.0 * 1000.0 * (config.test_tx_percent / 100.0);
-            state.current_tx_kbps = test_tx_kbps;
-            state.start_tx_kbps = test_tx_kbps;
-            state.last_bandwidth_update = Some(Instant::now());
+        renderer.render_frame(0.016).unwrap();
+        let frame = frames.try_recv().unwrap();
+        let led = |i: usize| [frame[i * 3], frame[i * 3 + 1], frame[i * 3 + 2]];
+
+        // Mirrored TX starts at LED 10: the newest sample takes LEDs 10-11, the one before it 12-13
+        assert_eq!(lit_leds(&frame), [8, 9, 10, 11, 12, 13]);
+        assert_eq!(led(10), [0x00, 0x99, 0xFF]);
+        assert_eq!(led(11), led(10));
+        assert_eq!(led(12), [0x00, 0x26, 0x40]);
+        assert_eq!(led(13), led(12));
+        // RX runs down from LED 9, and the older idle sample leaves LEDs 7-6 dark
+        assert_eq!(led(9), [0x00, 0x4d, 0x80]);
+        assert_eq!(led(8), led(9));
+
+        // The buffer keeps only as many samples as the widest target has LEDs
+        let config = BandwidthConfig {
+            total_leds: 20,
+            targets: vec![TargetConfig {
+                wled_ip: "10.0.0.2".to_string(),
+                total_leds: Some(30),
+                ..TargetConfig::default()
+            }],
+            ..config
+        };
+        let mut state = SharedRenderState::from_config(&config);
+        for i in 0..50 {
+            state.record_history(HistorySample { rx_kbps: i as f64, tx_kbps: 0.0 });
+        }
+        assert_eq!(state.history_samples.len(), 30);
+        assert_eq!(state.history_samples.front().unwrap().rx_kbps, 49.0);
+        assert_eq!(state.history_samples.back().unwrap().rx_kbps, 20.0);
+    }
+
+    #[test]
+    fn error_sparkles_only_follow_new_errors_and_drops() {
+        let config = BandwidthConfig {
+            total_leds: 20,
+            error_sparkle: true,
+            ..BandwidthConfig::default()
+        };
+        let (mut renderer, frames, state) = test_renderer(&config);
+
+        // No traffic and no errors: the strip stays dark
+        renderer.render_frame(0.016).unwrap();
+        assert!(lit_leds(&frames.try_recv().unwrap()).is_empty());
+
+        // Two TX errors or drops sparkle red somewhere on the TX half (LEDs 10-19 when mirrored)
+        state.lock().unwrap().tx_error_events = 2;
+        renderer.render_frame(0.016).unwrap();
+        let frame = frames.try_recv().unwrap();
+        let lit = lit_leds(&frame);
+        assert!((1..=2).contains(&lit.len()), "{:?}", lit);
+        for led in lit {
+            assert!((10..20).contains(&led), "{}", led);
+            assert_eq!(frame[led * 3..led * 3 + 3], [255, 0, 0]);
         }
+        assert_eq!(state.lock().unwrap().tx_error_events, 0);
+
+        // Without new events the sparkles fade out and none take their place
+        renderer.render_frame(Sparkle::LIFETIME_SECS).unwrap();
+        assert!(lit_leds(&frames.try_recv().unwrap()).is_empty());
     }
 
-    // Simple main loop - just handle bandwidth and config updates
-    // Rendering happens in dedicated thread at configurable FPS
-    loop {
-        // Check for Ctrl+C key press (using crossterm events since we're in raw mode)
-        // Use 50ms timeout to make Ctrl+C more responsive
-        if poll(std::time::Duration::from_millis(50))? {
-            if let Event::Key(KeyEvent {
-                code: KeyCode::Char('c'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            }) = read()?
-            {
-                // Signal render thread to shut down
-                shutdown.store(true, Ordering::Relaxed);
+    #[test]
+    fn bandwidth_scales_map_readings_to_fractions() {
+        let scale = |name: &str, floor: f64, exponent: f64| {
+            BandwidthScale::from_config(&BandwidthConfig {
+                scale: name.to_string(),
+                scale_log_floor_kbps: floor,
+                scale_exponent: exponent,
+                ..BandwidthConfig::default()
+            })
+        };
+        let linear = scale("linear", 10.0, 1.0);
+        let log = scale("log", 10.0, 1.0);
+        let sqrt = scale("sqrt", 10.0, 3.0);
+        let square = scale("power", 10.0, 2.0);
+
+        // (scale, kbps, max kbps, fraction)
+        let cases = [
+            (linear, 2_500.0, 10_000.0, 0.25),
+            (linear, 20_000.0, 10_000.0, 1.0),
+            (linear, 0.0, 10_000.0, 0.0),
+            (linear, -5.0, 10_000.0, 0.0),
+            (linear, 100.0, 0.0, 0.0),
+            // Three decades from the 10 kbps floor to max
+            (log, 100.0, 10_000.0, 1.0 / 3.0),
+            (log, 1_000.0, 10_000.0, 2.0 / 3.0),
+            (log, 10_000.0, 10_000.0, 1.0),
+            (log, 50_000.0, 10_000.0, 1.0),
+            (log, 10.0, 10_000.0, 0.0),
+            (log, 5.0, 10_000.0, 0.0),
+            (log, 0.0, 10_000.0, 0.0),
+            // A floor at or above max falls back to linear
+            (log, 2.5, 5.0, 0.5),
+            (sqrt, 2_500.0, 10_000.0, 0.5),
+            (sqrt, 0.0, 10_000.0, 0.0),
+            (sqrt, 40_000.0, 10_000.0, 1.0),
+            (square, 2_500.0, 10_000.0, 0.0625),
+            (square, 10_000.0, 10_000.0, 1.0),
+        ];
+        for (scale, kbps, max_kbps, expected) in cases {
+            let fraction = scale.fraction(kbps, max_kbps);
+            assert!((fraction - expected).abs() < 1e-9, "{:?} {} of {}: {} != {}", scale.mode, kbps, max_kbps, fraction, expected);
+        }
 
-                // Give render thread a moment to exit cleanly
-                thread::sleep(Duration::from_millis(100));
+        // sqrt ignores scale_exponent, a non-positive exponent is linear and the log floor stays positive
+        assert_eq!(sqrt.exponent, 0.5);
+        assert_eq!(scale("power", 10.0, 0.0).exponent, 1.0);
+        assert_eq!(scale("log", 0.0, 1.0).log_floor_kbps, 0.001);
+        assert_eq!(scale("bogus", 10.0, 2.0).mode, ScaleMode::Linear);
+    }
 
-                // Clean up terminal
-                terminal.show_cursor()?;
-                disable_raw_mode()?;
-                terminal.backend_mut().execute(LeaveAlternateScreen)?;
-                break;
-            }
+    // Frame whose pixel i is (i >> 8, i & 0xff, 7), so every pixel in a packet can be traced back
+    fn numbered_frame(pixels: usize) -> Vec<u8> {
+        (0..pixels).flat_map(|i| [(i >> 8) as u8, i as u8, 7]).collect()
+    }
+
+    #[test]
+    fn wled_udp_picks_drgb_or_chunked_dnrgb() {
+        // 490 pixels still fit one DRGB packet
+        let packets = WledUdpSink::packets(WledUdpMode::Auto, &numbered_frame(490));
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0][..2], [2, WledUdpSink::TIMEOUT_SECS]);
+        assert_eq!(packets[0].len(), 2 + 490 * 3);
+
+        // 600 pixels switch to DNRGB: 489 from LED 0, then 111 from LED 489
+        let packets = WledUdpSink::packets(WledUdpMode::Auto, &numbered_frame(600));
+        let headers: Vec<&[u8]> = packets.iter().map(|p| &p[..4]).collect();
+        assert_eq!(headers, [[4, 2, 0, 0], [4, 2, 0x01, 0xe9]]);
+        assert_eq!(packets.iter().map(|p| (p.len() - 4) / 3).collect::<Vec<_>>(), [489, 111]);
+        assert_eq!(packets[1][4..7], [0x01, 0xe9, 7]);
+
+        // 1200 pixels take three packets starting at 0, 489 and 978, and every pixel is sent once
+        let frame = numbered_frame(1200);
+        let packets = WledUdpSink::packets(WledUdpMode::Auto, &frame);
+        let starts: Vec<u16> = packets.iter().map(|p| u16::from_be_bytes([p[2], p[3]])).collect();
+        assert_eq!(starts, [0, 489, 978]);
+        for (packet, start) in packets.iter().zip(starts) {
+            let start = start as usize * 3;
+            assert_eq!(packet[4..], frame[start..start + packet.len() - 4]);
         }
+        assert_eq!(packets.iter().map(|p| p.len() - 4).sum::<usize>(), frame.len());
 
-        // Check bandwidth updates - update shared state
-        match bandwidth_rx.try_recv() {
-            Ok(line) => {
-                if let Some((rx_kbps, tx_kbps)) = parse_bandwidth_line(&line, &mut bandwidth_tracker) {
-                    // Override with test values if test mode is enabled for each direction
-                    let rx_kbps = if config.test_rx {
-                        config.max_gbps * 1000.0 * 1000.0 * (config.test_rx_percent / 100.0)
-                    } else {
-                        rx_kbps
-                    };
+        // Forcing DRGB truncates to one packet, forcing DNRGB chunks even short strips
+        let packets = WledUdpSink::packets(WledUdpMode::Drgb, &numbered_frame(600));
+        assert_eq!((packets.len(), packets[0].len()), (1, 2 + 490 * 3));
+        assert_eq!(WledUdpSink::packets(WledUdpMode::Dnrgb, &numbered_frame(30))[0][..4], [4, 2, 0, 0]);
 
-                    let tx_kbps = if config.test_tx {
-                        config.max_gbps * 1000.0 * 1000.0 * (config.test_tx_percent / 100.0)
-                    } else {
-                        tx_kbps
-                    };
+        // WARLS sends index and color per LED, up to LED 254
+        let packets = WledUdpSink::packets(WledUdpMode::Warls, &numbered_frame(300));
+        assert_eq!(packets[0].len(), 2 + 255 * 4);
+        assert_eq!(packets[0][2 + 254 * 4..], [254, 0, 254, 7]);
+    }
 
-                    // Update shared state (non-blocking for renderer)
-                    {
-                        let mut state = shared_state.lock().unwrap();
-                        // Store current values as the starting point for interpolation
-                        state.start_rx_kbps = state.current_rx_kbps;
-                        state.start_tx_kbps = state.current_tx_kbps;
-                        // Update to new target values
-                        state.current_rx_kbps = rx_kbps;
-                        state.current_tx_kbps = tx_kbps;
-                        // Record the time when this update happened
-                        state.last_bandwidth_update = Some(Instant::now());
-                    }
+    #[test]
+    fn e131_splits_frames_across_universes() {
+        let sink = |start_channel: u16, channels_per_pixel: usize| E131Sink {
+            socket: UdpSocket::bind("127.0.0.1:0").unwrap(),
+            unicast_addr: None,
+            universe: 7,
+            start_channel,
+            channels_per_pixel,
+            cid: [0xcd; 16],
+            sequence: 42,
+        };
 
-                    // Generate messages for UI
-                    let rx_leds = calculate_leds(rx_kbps, config.max_gbps * 1000.0 * 1000.0);
-                    let tx_leds = calculate_leds(tx_kbps, config.max_gbps * 1000.0 * 1000.0);
+        // 200 RGB pixels: 170 fill universe 7, the other 30 start universe 8 at channel 1
+        let frame = numbered_frame(200);
+        let packets = sink(1, 3).packets(&frame);
+        assert_eq!(packets.iter().map(|(universe, _)| *universe).collect::<Vec<_>>(), [7, 8]);
+        assert_eq!(packets.iter().map(|(_, p)| p.len()).collect::<Vec<_>>(), [126 + 510, 126 + 90]);
+
+        let packet = &packets[0].1;
+        assert_eq!(packet[..16], *b"\0\x10\0\0ASC-E1.17\0\0\0");
+        assert_eq!(packet[16..22], [0x72, 0x6c, 0, 0, 0, 4]); // Root flags/length 620, VECTOR_ROOT_E131_DATA
+        assert_eq!(packet[22..38], [0xcd; 16]);
+        assert_eq!(packet[38..44], [0x72, 0x56, 0, 0, 0, 2]); // Framing flags/length 598, VECTOR_E131_DATA_PACKET
+        assert_eq!(packet[44..59], *b"bandwidth_meter");
+        assert_eq!(packet[108..115], [100, 0, 0, 42, 0, 0, 7]); // Priority, sync address, sequence, options, universe
+        assert_eq!(packet[115..126], [0x72, 0x09, 0x02, 0xa1, 0, 0, 0, 1, 0x01, 0xff, 0]); // DMP flags/length 521, 511 properties, start code
+        assert_eq!(packet[126..], frame[..510]);
+        assert_eq!(packets[1].1[123..125], [0, 91]);
+        assert_eq!(packets[1].1[126..], frame[510..]);
+
+        // RGBW from channel 5: 127 pixels fit behind the offset, the other 3 go to the next universe
+        let frame: Vec<u8> = (0..130 * 4).map(|i| i as u8).collect();
+        let packets = sink(5, 4).packets(&frame);
+        assert_eq!(packets.iter().map(|(universe, _)| *universe).collect::<Vec<_>>(), [7, 8]);
+        assert_eq!(packets[0].1[123..125], [0x02, 0x01]); // 512 slots + start code
+        assert_eq!(packets[0].1[126..130], [0; 4]);
+        assert_eq!(packets[0].1[130..], frame[..508]);
+        assert_eq!(packets[1].1.len(), 126 + 12);
+        assert_eq!(packets[1].1[126..], frame[508..]);
+    }
 
-                    // Always show both RX and TX on every update
-                    if !quiet {
-                        messages.push(format!(
-                            "[{}] RX: {} LEDs ({:.1} Mbps) | TX: {} LEDs ({:.1} Mbps)",
-                            get_timestamp(),
-                            rx_leds,
-                            rx_kbps / 1000.0,
-                            tx_leds,
-                            tx_kbps / 1000.0
-                        ));
-                        needs_render = true;
-                    }
+    #[test]
+    fn art_net_splits_frames_across_universes() {
+        let sink = |start_channel: u16, channels_per_pixel: usize| ArtNetSink {
+            socket: UdpSocket::bind("127.0.0.1:0").unwrap(),
+            addr: "127.0.0.1:6454".parse().unwrap(),
+            universe: 0x0123,
+            start_channel,
+            channels_per_pixel,
+            sequence: 9,
+        };
 
-                    // Keep message buffer reasonable
-                    if messages.len() > 1000 {
-                        messages.remove(0);
-                    }
-                }
-            }
-            Err(_) => {
-                // No new bandwidth data
-            }
-        }
+        // 200 RGB pixels: 170 in the first universe, 30 in the next
+        let frame = numbered_frame(200);
+        let packets = sink(1, 3).packets(&frame);
+        assert_eq!(packets.iter().map(|p| p.len()).collect::<Vec<_>>(), [18 + 510, 18 + 90]);
+        assert_eq!(packets[0][..18], *b"Art-Net\0\x00\x50\x00\x0e\x09\x00\x23\x01\x01\xfe");
+        assert_eq!(packets[1][14..18], [0x24, 0x01, 0x00, 0x5a]); // Next universe, 90 channels
+        assert_eq!(packets[0][18..], frame[..510]);
+        assert_eq!(packets[1][18..], frame[510..]);
+
+        // RGBW from channel 2: 127 pixels behind the offset, lengths padded to an even count
+        let frame: Vec<u8> = (0..128 * 4).map(|i| i as u8).collect();
+        let packets = sink(2, 4).packets(&frame);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0][16..18], [0x01, 0xfe]); // 1 + 508 channels, padded to 510
+        assert_eq!(packets[0][18], 0);
+        assert_eq!(packets[0][19..19 + 508], frame[..508]);
+        assert_eq!(packets[1][14..18], [0x24, 0x01, 0x00, 0x04]);
+        assert_eq!(packets[1][18..], frame[508..]);
+    }
 
-        // Check config file updates
-        match config_rx.try_recv() {
-            Ok(new_config) => {
-                // Update shared state with new config
-                {
-                    let mut state = shared_state.lock().unwrap();
+    #[test]
+    fn data_state_follows_sample_age_and_source() {
+        let last_sample = Instant::now();
+        let after = |ms: u64| last_sample + Duration::from_millis(ms);
+        let timeout = Some(Duration::from_secs(5));
+
+        assert_eq!(DataState::from_readings(false, last_sample, timeout, after(0)), DataState::Live);
+        assert_eq!(DataState::from_readings(false, last_sample, timeout, after(5_000)), DataState::Live);
+        assert_eq!(DataState::from_readings(false, last_sample, timeout, after(5_001)), DataState::Stale);
+        // A lost source wins over everything, a disabled timeout never goes stale
+        assert_eq!(DataState::from_readings(true, last_sample, timeout, after(0)), DataState::Lost);
+        assert_eq!(DataState::from_readings(true, last_sample, None, after(60_000)), DataState::Lost);
+        assert_eq!(DataState::from_readings(false, last_sample, None, after(60_000)), DataState::Live);
+        // A sample stamped after now (the clock read before it arrived) is fresh
+        assert_eq!(DataState::from_readings(false, after(100), timeout, after(0)), DataState::Live);
+
+        // The timeout covers at least three sample intervals, and is off at 0 or in test mode
+        let config = |stale_timeout_ms: f64, sample_interval_ms: f64, test_tx: bool| BandwidthConfig {
+            stale_timeout_ms,
+            sample_interval_ms,
+            test_tx,
+            ..BandwidthConfig::default()
+        };
+        assert_eq!(stale_timeout(&config(5000.0, 1000.0, false)), Some(Duration::from_secs(5)));
+        assert_eq!(stale_timeout(&config(5000.0, 2500.0, false)), Some(Duration::from_millis(7500)));
+        assert_eq!(stale_timeout(&config(0.0, 1000.0, false)), None);
+        assert_eq!(stale_timeout(&config(5000.0, 1000.0, true)), None);
+    }
 
-                    // Handle color updates
-                    let color_changed = new_config.color != config.color;
-                    let tx_color_changed = new_config.tx_color != config.tx_color;
-                    let rx_color_changed = new_config.rx_color != config.rx_color;
+    #[test]
+    fn no_data_patterns_replace_the_bar() {
+        let config = BandwidthConfig {
+            total_leds: 30,
+            ..BandwidthConfig::default()
+        };
+        let (mut renderer, frames, state) = test_renderer(&config);
+        let layout = config.target_layouts().remove(0);
+        let orange = Rgb { r: 255, g: 128, b: 0 };
+
+        // Breathe fills the whole strip, blink only the end of each bar
+        let mut frame = vec![0; 90];
+        renderer.render_no_data(&mut frame, &layout, &NoDataConfig { pattern: NoDataPattern::Breathe, color: orange }, 15, 15);
+        assert_eq!(frame, [255, 128, 0].repeat(30));
+        let mut frame = vec![0; 90];
+        renderer.render_no_data(&mut frame, &layout, &NoDataConfig { pattern: NoDataPattern::Blink, color: orange }, 15, 15);
+        assert_eq!(lit_leds(&frame), [0, 29]);
+
+        // A segment map keeps its unused LEDs dark while breathing
+        let segmented = TargetLayout {
+            segments: SegmentMap::parse("0-9:rx, 10-19:unused, 29-20:tx", 30),
+            ..layout
+        };
+        let mut frame = vec![0; 90];
+        renderer.render_no_data(&mut frame, &segmented, &NoDataConfig { pattern: NoDataPattern::Breathe, color: orange }, 10, 10);
+        assert_eq!(lit_leds(&frame), (0..10).chain(20..30).collect::<Vec<_>>());
 
-                    if tx_color_changed || (color_changed && new_config.tx_color.is_empty()) {
-                        let tx_color_to_use = if new_config.tx_color.is_empty() {
-                            new_config.color.clone()
-                        } else {
-                            new_config.tx_color.clone()
-                        };
-                        state.tx_color = tx_color_to_use.clone();
-                        state.generation += 1;
-                        if !quiet {
-                            if new_config.tx_color.is_empty() {
-                                messages.push(format!(
-                                    "[{}] TX color updated to: {} (from main color)",
-                                    get_timestamp(),
-                                    tx_color_to_use
-                                ));
-                            } else {
-                                messages.push(format!("[{}] TX color updated to: {}", get_timestamp(), new_config.tx_color));
-                            }
-                        }
-                    }
+        // Once the readings go stale a full bar gives way to an evenly dimmed no data color
+        {
+            let mut state = state.lock().unwrap();
+            state.current_tx_kbps = state.max_bandwidth_kbps;
+            state.current_rx_kbps = state.max_bandwidth_kbps;
+        }
+        renderer.render_frame(0.016).unwrap();
+        assert_eq!(lit_leds(&frames.try_recv().unwrap()).len(), 30);
+        state.lock().unwrap().data_state = DataState::Stale;
+        renderer.render_frame(0.016).unwrap();
+        let frame = frames.try_recv().unwrap();
+        let first = &frame[..3];
+        assert!(frame.chunks_exact(3).all(|rgb| rgb == first));
+        assert!(first[0] > 0 && first[0] < 255 && first[2] == 0, "{:?}", first);
+    }
 
-                    if rx_color_changed || (color_changed && new_config.rx_color.is_empty()) {
-                        let rx_color_to_use = if new_config.rx_color.is_empty() {
-                            new_config.color.clone()
-                        } else {
-                            new_config.rx_color.clone()
-                        };
-                        state.rx_color = rx_color_to_use.clone();
-                        state.generation += 1;
-                        if !quiet {
-                            if new_config.rx_color.is_empty() {
-                                messages.push(format!(
-                                    "[{}] RX color updated to: {} (from main color)",
-                                    get_timestamp(),
-                                    rx_color_to_use
-                                ));
-                            } else {
-                                messages.push(format!("[{}] RX color updated to: {}", get_timestamp(), new_config.rx_color));
-                            }
-                        }
-                    }
+    // Counter32/Counter64 as an agent sends them, with a leading zero when the top bit is set
+    fn ber_unsigned(tag: u8, value: u64) -> Vec<u8> {
+        let bytes = value.to_be_bytes();
+        let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len() - 1);
+        let mut content = Vec::new();
+        if bytes[start] & 0x80 != 0 {
+            content.push(0);
+        }
+        content.extend_from_slice(&bytes[start..]);
+        ber_tlv(tag, &content)
+    }
 
-                    // Update max bandwidth
-                    if new_config.max_gbps != config.max_gbps {
-                        state.max_bandwidth_kbps = new_config.max_gbps * 1000.0 * 1000.0;
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] Max bandwidth updated to: {} Gbps",
-                                get_timestamp(),
-                                new_config.max_gbps
-                            ));
-                        }
-                    }
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
 
-                    // Update direction
-                    if new_config.direction != config.direction {
-                        let direction = match new_config.direction.to_lowercase().as_str() {
-                            "mirrored" => DirectionMode::Mirrored,
-                            "opposing" => DirectionMode::Opposing,
-                            "left" => DirectionMode::Left,
-                            "right" => DirectionMode::Right,
-                            _ => DirectionMode::Mirrored,
-                        };
-                        state.direction = direction;
-                        state.generation += 1;
-                        if !quiet {
-                            messages.push(format!("[{}] Direction updated to: {}", get_timestamp(), new_config.direction));
-                        }
-                    }
+    #[test]
+    fn ber_integers_round_trip() {
+        for value in [0, 1, 127, 128, 255, 256, -1, -128, -129, 65507, i32::MAX as i64, i32::MIN as i64, i64::MAX, i64::MIN] {
+            let encoded = ber_integer(value);
+            let mut reader = BerReader::new(&encoded);
+            assert_eq!(reader.read_integer().unwrap(), value, "{value}");
+            assert!(reader.is_empty());
+        }
+        // Minimal encoding: one byte up to 127, a leading zero keeps 128 positive
+        assert_eq!(ber_integer(127), [0x02, 0x01, 0x7F]);
+        assert_eq!(ber_integer(128), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(ber_integer(-129), [0x02, 0x02, 0xFF, 0x7F]);
+    }
 
-                    // Update swap
-                    if new_config.swap != config.swap {
-                        state.swap = new_config.swap;
-                        state.generation += 1;
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] Swap: {}",
-                                get_timestamp(),
-                                if new_config.swap { "enabled" } else { "disabled" }
-                            ));
-                        }
-                    }
+    #[test]
+    fn ber_oids_round_trip() {
+        for oid in [
+            OID_IF_HC_IN_OCTETS.to_vec(),
+            [OID_IF_NAME, &[7]].concat(),
+            vec![1, 3, 6, 1, 4, 1, 2021, 4294967295],
+            vec![2, 999, 16383, 16384],
+        ] {
+            let encoded = ber_oid(&oid);
+            let (tag, content) = BerReader::new(&encoded).read_tlv().unwrap();
+            assert_eq!(tag, 0x06);
+            assert_eq!(ber_decode_oid(content), oid);
+        }
+        // 2021 takes two base-128 bytes
+        assert_eq!(ber_oid(&[1, 3, 6, 1, 4, 1, 2021]), [0x06, 0x07, 0x2B, 0x06, 0x01, 0x04, 0x01, 0x8F, 0x65]);
+    }
 
-                    // Update RX/TX split percentage
-                    if new_config.rx_split_percent != config.rx_split_percent {
-                        state.rx_split_percent = new_config.rx_split_percent;
-                        if !quiet {
-                            let tx_split = 100.0 - new_config.rx_split_percent;
-                            messages.push(format!(
-                                "[{}] LED split updated to: RX {:.0}% / TX {:.0}%",
-                                get_timestamp(),
-                                new_config.rx_split_percent,
-                                tx_split
-                            ));
-                        }
-                    }
+    #[test]
+    fn ber_counters_decode_as_unsigned() {
+        for (tag, value) in [(0x41, 0), (0x41, 0x7FFF_FFFF), (0x41, u32::MAX as u64), (0x46, 1 << 40), (0x46, u64::MAX)] {
+            let encoded = ber_unsigned(tag, value);
+            let (read_tag, content) = BerReader::new(&encoded).read_tlv().unwrap();
+            assert_eq!(ber_decode_value(read_tag, content), SnmpValue::Unsigned(value));
+            assert_eq!(ber_decode_value(read_tag, content).as_counter(), Some(value));
+        }
+        assert_eq!(ber_decode_value(0x80, &[]), SnmpValue::NoSuchObject);
+    }
 
-                    // Update strobe on max
-                    if new_config.strobe_on_max != config.strobe_on_max {
-                        state.strobe_on_max = new_config.strobe_on_max;
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] Strobe on max: {}",
-                                get_timestamp(),
-                                if new_config.strobe_on_max { "enabled" } else { "disabled" }
-                            ));
-                        }
-                    }
+    #[test]
+    fn ber_long_lengths_round_trip() {
+        for len in [127, 128, 300, 70_000] {
+            let content: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let encoded = ber_tlv(0x04, &content);
+            let header = match len {
+                0..=127 => vec![0x04, len as u8],
+                128..=255 => vec![0x04, 0x81, len as u8],
+                256..=65535 => vec![0x04, 0x82, (len >> 8) as u8, len as u8],
+                _ => vec![0x04, 0x83, (len >> 16) as u8, (len >> 8) as u8, len as u8],
+            };
+            assert_eq!(encoded[..header.len()], header[..], "{len}");
+            let mut reader = BerReader::new(&encoded);
+            assert_eq!(reader.expect(0x04).unwrap(), &content[..]);
+            assert!(reader.is_empty());
+        }
+        assert!(BerReader::new(&[0x04, 0x82, 0x01]).read_tlv().is_err());
+        assert!(BerReader::new(&[0x04, 0x05, 0x00]).read_tlv().is_err());
+    }
 
-                    // Update strobe rate
-                    if new_config.strobe_rate_hz != config.strobe_rate_hz {
-                        state.strobe_rate_hz = new_config.strobe_rate_hz;
-                        // Also validate strobe_duration_ms doesn't exceed new cycle time
-                        if new_config.strobe_rate_hz > 0.0 {
-                            let max_duration = 1000.0 / new_config.strobe_rate_hz;
-                            if state.strobe_duration_ms > max_duration {
-                                state.strobe_duration_ms = max_duration;
-                            }
-                        }
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] Strobe rate updated to: {:.1} Hz",
-                                get_timestamp(),
-                                new_config.strobe_rate_hz
-                            ));
-                        }
-                    }
+    #[test]
+    fn snmp_pdu_round_trips() {
+        let oids = vec![OID_IF_HC_IN_OCTETS.to_vec(), OID_IF_HC_OUT_OCTETS.to_vec()];
+        let encoded = encode_snmp_pdu(SNMP_GET, 4242, &oids);
+        let pdu = decode_snmp_pdu(&mut BerReader::new(&encoded)).unwrap();
+        assert_eq!(pdu.pdu_type, SNMP_GET);
+        assert_eq!(pdu.request_id, 4242);
+        assert_eq!(pdu.error_status, 0);
+        assert_eq!(pdu.varbinds, oids.into_iter().map(|oid| (oid, SnmpValue::Null)).collect::<Vec<_>>());
+    }
 
-                    // Update strobe duration
-                    if new_config.strobe_duration_ms != config.strobe_duration_ms {
-                        state.strobe_duration_ms = new_config.strobe_duration_ms;
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] Strobe duration updated to: {:.0} ms",
-                                get_timestamp(),
-                                new_config.strobe_duration_ms
-                            ));
-                        }
-                    }
+    // RFC 3414 Appendix A.3: password "maplesyrup", engine ID 00 00 00 00 00 00 00 00 00 00 00 02
+    #[test]
+    fn snmp_key_localization_matches_rfc_3414() {
+        let engine_id = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+        let md5 = snmp_localize_key(SnmpAuthProtocol::Md5, "maplesyrup", &engine_id).unwrap();
+        assert_eq!(hex(&md5), "526f5eed9fcce26f8964c2930787d82b");
+        let sha = snmp_localize_key(SnmpAuthProtocol::Sha1, "maplesyrup", &engine_id).unwrap();
+        assert_eq!(hex(&sha), "6695febc9288e36282235fc7151f128497b38f3f");
+        assert!(snmp_localize_key(SnmpAuthProtocol::Md5, "short", &engine_id).is_err());
+    }
 
-                    // Update strobe color
-                    if new_config.strobe_color != config.strobe_color {
-                        state.strobe_color = new_config.strobe_color.clone();
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] Strobe color updated to: {}",
-                                get_timestamp(),
-                                new_config.strobe_color
-                            ));
-                        }
-                    }
+    // RFC 2202 test case 2
+    #[test]
+    fn snmp_hmac_matches_rfc_2202() {
+        let data = b"what do ya want for nothing?";
+        assert_eq!(hex(&snmp_hmac(SnmpAuthProtocol::Md5, b"Jefe", data)), "750c783e6ab0b503eaa86e310a5db738");
+        assert_eq!(hex(&snmp_hmac(SnmpAuthProtocol::Sha1, b"Jefe", data)), "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79");
+    }
 
-                    // Update animation speed
-                    if new_config.animation_speed != config.animation_speed {
-                        state.animation_speed = new_config.animation_speed;
-                        if !quiet && new_config.animation_speed > 0.0 {
-                            messages.push(format!(
-                                "[{}] Animation speed: {:.3}",
-                                get_timestamp(),
-                                new_config.animation_speed
-                            ));
-                        }
-                    }
+    #[test]
+    fn link_speeds_parse_from_sysfs_and_media_lines() {
+        assert_eq!(parse_link_speed_mbps("1000"), Some(1000.0));
+        assert_eq!(parse_link_speed_mbps("10000\n"), Some(10000.0));
+        // Down links and unknown speeds
+        for unknown in ["-1", "0", "4294967295", "65535", "", "Unknown!", "nan", "inf"] {
+            assert_eq!(parse_link_speed_mbps(unknown), None, "{:?}", unknown);
+        }
 
-                    // Update animation speed scaling
-                    if new_config.scale_animation_speed != config.scale_animation_speed {
-                        state.scale_animation_speed = new_config.scale_animation_speed;
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] Animation speed scaling: {}",
-                                get_timestamp(),
-                                if new_config.scale_animation_speed {
-                                    "enabled (scales with bandwidth)"
-                                } else {
-                                    "disabled (constant speed)"
-                                }
-                            ));
-                        }
-                    }
+        // ifconfig, networksetup -getMedia and ethtool link modes
+        assert_eq!(parse_link_speed_mbps("media: autoselect (1000baseT <full-duplex>)"), Some(1000.0));
+        assert_eq!(parse_link_speed_mbps("media: autoselect (10GbaseT <full-duplex,flow-control>)"), Some(10000.0));
+        assert_eq!(parse_media_speed_mbps("Current: 2500Base-T"), Some(2500.0));
+        assert_eq!(parse_media_speed_mbps("Active: 100baseTX <full-duplex>"), Some(100.0));
+        assert_eq!(parse_media_speed_mbps("Advertised link modes: 1000baseT/Full"), Some(1000.0));
+        assert_eq!(parse_media_speed_mbps("(2.5Gbase-T)"), Some(2500.0));
+        assert_eq!(parse_media_speed_mbps("media: autoselect (none)"), None);
+        assert_eq!(parse_media_speed_mbps("media: autoselect <full-duplex>"), None);
+
+        let values = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        assert_eq!(total_link_speed_mbps(&values(&["1000", "-1", "media: autoselect (2500baseT)"])), Some(3500.0));
+        assert_eq!(total_link_speed_mbps(&values(&["-1", "media: autoselect (none)"])), None);
+        assert_eq!(total_link_speed_mbps(&[]), None);
+    }
 
-                    // Update TX animation direction
-                    if new_config.tx_animation_direction != config.tx_animation_direction {
-                        state.tx_animation_direction = new_config.tx_animation_direction.clone();
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] TX animation direction: {}",
-                                get_timestamp(),
-                                new_config.tx_animation_direction
-                            ));
-                        }
-                    }
+    #[tokio::test]
+    async fn link_speed_detection_refuses_unsafe_interface_names() {
+        assert_eq!(detect_link_speed_mbps(None, "eth0;reboot").await, None);
+        assert_eq!(detect_link_speed_mbps(Some(&"router".to_string()), "eth0,$(id)").await, None);
+    }
+
+    #[test]
+    fn auto_range_decays_after_the_window_down_to_the_floor() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut auto_range = AutoRange::default();
+        let decayed = |peak: f64, secs: f64| peak * (-secs / 60.0_f64).exp();
+
+        // A burst sets the peak, quieter traffic lets it decay with a time constant of the window
+        assert_eq!(auto_range.update(500_000.0, at(0), 60.0), 500_000.0);
+        assert!((auto_range.update(1_000.0, at(60), 60.0) - decayed(500_000.0, 60.0)).abs() < 1e-6);
+        assert!((auto_range.update(1_000.0, at(120), 60.0) - decayed(500_000.0, 120.0)).abs() < 1e-6);
+        // Traffic above the decayed peak takes over right away
+        assert_eq!(auto_range.update(200_000.0, at(121), 60.0), 200_000.0);
+
+        // The bar's max never goes below auto_range_min_mbps, or above max_gbps
+        let config = BandwidthConfig {
+            max_gbps: 1.0,
+            auto_range_min_mbps: 10.0,
+            ..BandwidthConfig::default()
+        };
+        let mut auto_range = AutoRange::default();
+        assert_eq!(auto_range.range_kbps(5_000_000.0, at(0), &config), 1_000_000.0);
+        assert!(auto_range.range_kbps(0.0, at(60), &config) > 10_000.0);
+        assert_eq!(auto_range.range_kbps(0.0, at(3600), &config), 10_000.0);
+        assert_eq!(auto_range.range_kbps(100.0, at(7200), &config), 10_000.0);
+    }
 
-                    // Update RX animation direction
-                    if new_config.rx_animation_direction != config.rx_animation_direction {
-                        state.rx_animation_direction = new_config.rx_animation_direction.clone();
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] RX animation direction: {}",
-                                get_timestamp(),
-                                new_config.rx_animation_direction
-                            ));
-                        }
-                    }
+    #[test]
+    fn counter_delta_tells_wraps_from_resets() {
+        assert_eq!(counter_delta(10, 30, 32), Some(20));
+        assert_eq!(counter_delta(30, 30, 32), Some(0));
+        // Just past the top of a 32-bit counter
+        assert_eq!(counter_delta(u32::MAX as u64 - 5, 10, 32), Some(16));
+        // Dropping from a billion to nearly nothing is a reset, not a 3 GB wrap
+        assert_eq!(counter_delta(1_000_000_000, 5, 32), None);
+        // A 64-bit counter never wraps in practice
+        assert_eq!(counter_delta(5_000_000_000, 10, 64), None);
+        assert_eq!(counter_delta(u64::MAX - 1, 3, 64), Some(5));
+        // A "32-bit" counter that was already past 2^32 can't have wrapped at 32 bits
+        assert_eq!(counter_delta(1 << 33, 5, 32), None);
+        // A reset from the upper half of the range is indistinguishable from a wrap here,
+        // BandwidthTracker checks the implied rate against the link
+        assert_eq!(counter_delta(3_000_000_000, 1_000, 32), Some(1_294_968_296));
+    }
 
-                    // Update interpolation time
-                    if new_config.interpolation_time_ms != config.interpolation_time_ms {
-                        state.interpolation_time_ms = new_config.interpolation_time_ms;
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] Interpolation time: {} ms",
-                                get_timestamp(),
-                                new_config.interpolation_time_ms
-                            ));
-                        }
-                    }
+    fn procnet_line(iface: &str, rx_bytes: u64, tx_bytes: u64) -> String {
+        format!("  {}: {} 10 0 0 0 0 0 0 {} 10 0 0 0 0 0 0", iface, rx_bytes, tx_bytes)
+    }
 
-                    // Update interpolation
-                    if new_config.interpolation != config.interpolation {
-                        let interpolation_mode = match new_config.interpolation.to_lowercase().as_str() {
-                            "basis" => InterpolationMode::Basis,
-                            "catmullrom" | "catmull-rom" => InterpolationMode::CatmullRom,
-                            _ => InterpolationMode::Linear,
-                        };
-                        state.interpolation_mode = interpolation_mode;
-                        state.generation += 1;
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] Interpolation updated to: {}",
-                                get_timestamp(),
-                                new_config.interpolation
-                            ));
-                        }
-                    }
+    #[test]
+    fn sample_window_sums_interfaces_and_flushes_partial_windows() {
+        let start = Instant::now();
+        let mut tracker = BandwidthTracker::new(1000.0);
+        let mut window = SampleWindow::new(parse_interface_list("eth0,eth1"));
+        let mut feed = |secs: u64, iface: &str, rx_bytes: u64, tx_bytes: u64| {
+            let line = procnet_line(iface, rx_bytes, tx_bytes);
+            parse_bandwidth_line(&line, start + Duration::from_secs(secs), &mut tracker).and_then(|sample| window.push(sample))
+        };
+        let names = |sample: &BandwidthSample| sample.interfaces.iter().map(|r| r.name.clone()).collect::<Vec<_>>();
 
-                    // Update gradient mode
-                    if new_config.use_gradient != config.use_gradient {
-                        state.use_gradient = new_config.use_gradient;
-                        state.generation += 1;
-                        if !quiet {
-                            messages.push(format!(
-                                "[{}] Gradient mode: {}",
-                                get_timestamp(),
-                                if new_config.use_gradient {
-                                    "enabled (smooth gradients)"
-                                } else {
-                                    "disabled (hard segments)"
-                                }
-                            ));
-                        }
-                    }
+        // The remote egrep for eth1 also matches eth10, which has to stay out of the totals
+        for iface in ["eth0", "eth1", "eth10"] {
+            assert!(feed(0, iface, 0, 0).is_none());
+        }
+        assert!(feed(1, "eth1", 250_000, 125_000).is_none());
+        assert!(feed(1, "eth10", 1_250_000, 0).is_none());
+        let total = feed(1, "eth0", 125_000, 0).unwrap();
+        assert_eq!((total.rx_kbps, total.tx_kbps), (3_000.0, 1_000.0));
+        assert_eq!(names(&total), ["eth0", "eth1"]);
+        assert_eq!((total.interfaces[1].rx_kbps, total.interfaces[1].tx_kbps), (2_000.0, 1_000.0));
+
+        // eth1 misses a window, so eth0 reporting again sends eth0 on its own
+        assert!(feed(2, "eth0", 250_000, 0).is_none());
+        let partial = feed(3, "eth0", 375_000, 0).unwrap();
+        assert_eq!((partial.rx_kbps, partial.tx_kbps), (1_000.0, 0.0));
+        assert_eq!(names(&partial), ["eth0"]);
+
+        // Its next reading covers the two seconds since the last one
+        let total = feed(3, "eth1", 500_000, 125_000).unwrap();
+        assert_eq!((total.rx_kbps, total.tx_kbps), (2_000.0, 0.0));
+        assert_eq!(names(&total), ["eth0", "eth1"]);
+    }
 
-                    // Update FPS
-                    if new_config.fps != config.fps {
-                        state.fps = new_config.fps;
-                        if !quiet {
-                            messages.push(format!("[{}] FPS updated to: {}", get_timestamp(), new_config.fps));
-                        }
-                    }
+    #[test]
+    fn ssh_batches_use_the_remote_clock() {
+        let start = Instant::now();
+        let at_ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut tracker = BandwidthTracker::new(100.0);
+
+        // Lines arrive 30 ms late and then on time, but the remote host read them 100 ms apart
+        assert!(parse_bandwidth_line("@1712345678.000000000", at_ms(30), &mut tracker).is_none());
+        assert!(parse_bandwidth_line(&procnet_line("eth0", 0, 0), at_ms(30), &mut tracker).is_none());
+        parse_bandwidth_line("@1712345678.100000000", at_ms(100), &mut tracker);
+        let sample = parse_bandwidth_line(&procnet_line("eth0", 12_500, 2_500), at_ms(100), &mut tracker).unwrap();
+        // Epoch seconds in an f64 still resolve to well under a microsecond
+        assert!((sample.rx_kbps - 1_000.0).abs() < 0.01);
+        assert!((sample.tx_kbps - 200.0).abs() < 0.01);
+        assert_eq!(sample.timestamp, at_ms(100));
+
+        // A date without %N leaves the batch on local arrival time
+        parse_bandwidth_line("@1712345678.%N", at_ms(170), &mut tracker);
+        assert_eq!(tracker.remote_time, None);
+        let sample = parse_bandwidth_line(&procnet_line("eth0", 21_250, 2_500), at_ms(170), &mut tracker).unwrap();
+        assert!((sample.rx_kbps - 1_000.0).abs() < 1e-6);
+    }
 
-                    // Update total_leds
-                    if new_config.total_leds != config.total_leds {
-                        state.total_leds = new_config.total_leds;
-                        if !quiet {
-                            messages.push(format!("[{}] Total LEDs updated to: {}", get_timestamp(), new_config.total_leds));
-                        }
-                    }
-                }
+    #[test]
+    fn tracker_counts_packets_errors_and_drops() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        // /proc/net/dev: bytes packets errs drop ... for RX, then the same for TX, 2 seconds apart
+        let mut tracker = BandwidthTracker::new(1000.0);
+        assert!(parse_bandwidth_line("  eth0: 1000 100 1 2 0 0 0 0 2000 200 3 4 0 0 0 0", at(0), &mut tracker).is_none());
+        let sample = parse_bandwidth_line("  eth0: 1000 300 1 5 0 0 0 0 2000 600 4 4 0 0 0 0", at(2), &mut tracker).unwrap();
+        let expected = PacketStats {
+            rx_pps: 100.0,
+            tx_pps: 200.0,
+            rx_errors: 0,
+            tx_errors: 1,
+            rx_drops: 3,
+            tx_drops: 0,
+        };
+        assert_eq!(sample.packets, expected);
+        assert_eq!(sample.interfaces[0].packets, expected);
 
-                // Note: Changes to wled_ip and interface require restart
-                if new_config.wled_ip != config.wled_ip
-                    || new_config.interface != config.interface
-                {
-                    if !quiet {
-                        messages.push(format!("[{}] Note: wled_ip or interface changed - restart required", get_timestamp()));
-                    }
-                }
+        // netstat -ibn: Ipkts Ierrs Ibytes Opkts Oerrs Obytes Coll, cumulative, without drop columns
+        let mut tracker = BandwidthTracker::new(1000.0);
+        let line = |ipkts: u64, ierrs: u64, opkts: u64, oerrs: u64| {
+            format!("en0 1500 <Link#6> aa:bb:cc:dd:ee:ff {} {} 5000 {} {} 6000 0", ipkts, ierrs, opkts, oerrs)
+        };
+        assert!(parse_bandwidth_line(&line(100, 0, 50, 0), at(0), &mut tracker).is_none());
+        let sample = parse_bandwidth_line(&line(400, 2, 150, 1), at(1), &mut tracker).unwrap();
+        assert_eq!(
+            sample.packets,
+            PacketStats {
+                rx_pps: 300.0,
+                tx_pps: 100.0,
+                rx_errors: 2,
+                tx_errors: 1,
+                ..PacketStats::default()
+            }
+        );
 
-                // Update test mode - immediately update bandwidth values and tracking vars
-                if new_config.test_tx != config.test_tx
-                    || new_config.test_rx != config.test_rx
-                    || new_config.test_tx_percent != config.test_tx_percent
-                    || new_config.test_rx_percent != config.test_rx_percent {
+        // netstat -w already reports per interval: packets errs bytes packets errs bytes colls
+        let mut tracker = BandwidthTracker::new(500.0);
+        let sample = parse_bandwidth_line("  100 2 5000 50 1 6000 0", at(0), &mut tracker).unwrap();
+        assert_eq!(
+            sample.packets,
+            PacketStats {
+                rx_pps: 200.0,
+                tx_pps: 100.0,
+                rx_errors: 2,
+                tx_errors: 1,
+                ..PacketStats::default()
+            }
+        );
+    }
 
-                    // Calculate test bandwidth values
-                    let test_rx_kbps = if new_config.test_rx {
-                        new_config.max_gbps * 1000.0 * 1000.0 * (new_config.test_rx_percent / 100.0)
-                    } else {
-                        0.0
-                    };
+    #[test]
+    fn tracker_rejects_wraps_faster_than_the_link() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        // A detected 1 Gbps link
+        let mut tracker = BandwidthTracker::new(1000.0);
+        tracker.link_limit_kbps = 1_000_000.0;
+
+        // A reset of an unknown-width counter sitting between 2^31 and 2^32 would be a 1.3 GB wrap in one second
+        assert!(tracker.update_from_procnet_line(&procnet_line("eth0", 3_000_000_000, 3_000_000_000), at(0)).is_none());
+        assert!(tracker.update_from_procnet_line(&procnet_line("eth0", 1_000, 2_000), at(1)).is_none());
+        assert_eq!(tracker.take_notices(), ["eth0: counters were reset, sample skipped"]);
+
+        // The readings after the reset are the new baseline
+        let sample = tracker.update_from_procnet_line(&procnet_line("eth0", 126_000, 2_000), at(2)).unwrap();
+        assert_eq!(sample.rx_kbps, 1_000.0);
+        assert_eq!(sample.tx_kbps, 0.0);
+
+        // A real wrap near the top of the range still counts across it
+        let near_top = u32::MAX as u64 - 999;
+        tracker.update_from_procnet_line(&procnet_line("eth1", near_top, near_top), at(0));
+        let sample = tracker.update_from_procnet_line(&procnet_line("eth1", 124_000, near_top), at(1)).unwrap();
+        assert_eq!(sample.rx_kbps, 1_000.0);
+        assert_eq!(tracker.take_notices(), ["eth1: RX byte counter wrapped at 32 bits"]);
+
+        // So does a busy one, at 800 Mbps, far above a max_gbps of 0.1 used to see light traffic
+        tracker.update_from_procnet_line(&procnet_line("eth2", near_top, near_top), at(0));
+        let sample = tracker.update_from_procnet_line(&procnet_line("eth2", 99_999_000, near_top), at(1)).unwrap();
+        assert_eq!(sample.rx_kbps, 800_000.0);
+
+        // Without a detected link speed only wraps beyond 100 Gbps count as resets,
+        // which still catches a reset between two 100 ms samples
+        let at_ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut tracker = BandwidthTracker::new(100.0);
+        tracker.update_from_procnet_line(&procnet_line("eth0", 3_000_000_000, 3_000_000_000), at_ms(0));
+        assert!(tracker.update_from_procnet_line(&procnet_line("eth0", 1_000, 2_000), at_ms(100)).is_none());
+        tracker.update_from_procnet_line(&procnet_line("eth1", near_top, near_top), at(0));
+        let sample = tracker.update_from_procnet_line(&procnet_line("eth1", 624_999_000, near_top), at(1)).unwrap();
+        assert_eq!(sample.rx_kbps, 5_000_000.0);
+    }
 
-                    let test_tx_kbps = if new_config.test_tx {
-                        new_config.max_gbps * 1000.0 * 1000.0 * (new_config.test_tx_percent / 100.0)
+    // Stand-in agent on a loopback socket: SNMPv2c GET and GETNEXT over ifName and the 64-bit octet counters.
+    // Every GET of the counters moves eth0 on by 1,000,000 bytes in and 250,000 out
+    async fn spawn_snmp_responder() -> u16 {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut polls = 0u64;
+            let mut buf = vec![0u8; 65535];
+            loop {
+                let Ok((len, peer)) = socket.recv_from(&mut buf).await else {
+                    return;
+                };
+                let mut message = BerReader::new(&buf[..len]).read_sequence().unwrap();
+                assert_eq!(message.read_integer().unwrap(), 1, "expected SNMPv2c");
+                let community = message.expect(0x04).unwrap().to_vec();
+                let request = decode_snmp_pdu(&mut message).unwrap();
+
+                let mut mib = std::collections::BTreeMap::new();
+                mib.insert([OID_IF_NAME, &[1]].concat(), ber_tlv(0x04, b"lo"));
+                mib.insert([OID_IF_NAME, &[2]].concat(), ber_tlv(0x04, b"eth0"));
+                if request.varbinds.iter().any(|(oid, _)| oid.starts_with(OID_IF_HC_OUT_OCTETS)) {
+                    polls += 1;
+                }
+                mib.insert([OID_IF_HC_IN_OCTETS, &[2]].concat(), ber_unsigned(0x46, (1 << 40) + polls * 1_000_000));
+                mib.insert([OID_IF_HC_OUT_OCTETS, &[2]].concat(), ber_unsigned(0x46, (1 << 40) + polls * 250_000));
+
+                let mut varbinds = Vec::new();
+                for (oid, _) in &request.varbinds {
+                    let (oid, value) = if request.pdu_type == SNMP_GET_NEXT {
+                        match mib.range::<Vec<u32>, _>((std::ops::Bound::Excluded(oid), std::ops::Bound::Unbounded)).next() {
+                            Some((next, value)) => (next.clone(), value.clone()),
+                            None => (oid.clone(), ber_tlv(0x82, &[])),
+                        }
                     } else {
-                        0.0
+                        (oid.clone(), mib.get(oid).cloned().unwrap_or_else(|| ber_tlv(0x81, &[])))
                     };
+                    let mut varbind = ber_oid(&oid);
+                    varbind.extend(value);
+                    varbinds.extend(ber_tlv(0x30, &varbind));
+                }
 
-                    // Update shared state only if test mode is enabled
-                    let mut state = shared_state.lock().unwrap();
+                let mut pdu = ber_integer(request.request_id);
+                pdu.extend(ber_integer(0));
+                pdu.extend(ber_integer(0));
+                pdu.extend(ber_tlv(0x30, &varbinds));
+                let mut content = ber_integer(1);
+                content.extend(ber_tlv(0x04, &community));
+                content.extend(ber_tlv(SNMP_RESPONSE, &pdu));
+                socket.send_to(&ber_tlv(0x30, &content), peer).await.unwrap();
+            }
+        });
+        port
+    }
 
-                    if new_config.test_rx {
-                        state.start_rx_kbps = state.current_rx_kbps;
-                        state.current_rx_kbps = test_rx_kbps;
-                        state.last_bandwidth_update = Some(Instant::now());
-                    }
+    #[tokio::test]
+    async fn snmp_source_reads_counters_from_an_agent() {
+        let config = BandwidthConfig {
+            source: "snmp".to_string(),
+            snmp_host: "127.0.0.1".to_string(),
+            snmp_port: spawn_snmp_responder().await,
+            interface: "eth0".to_string(),
+            sample_interval_ms: 20.0,
+            ..BandwidthConfig::default()
+        };
+        let mut source = SnmpSource::connect(&config).await.unwrap();
+        // ifName lookup found eth0 at ifIndex 2 and its 64-bit counters
+        assert_eq!(source.interfaces[0].in_oid, [OID_IF_HC_IN_OCTETS, &[2]].concat());
+        assert_eq!(source.interfaces[0].counter_bits, 64);
+
+        for _ in 0..2 {
+            let sample = source.next_sample().await.unwrap().unwrap();
+            assert!(sample.rx_kbps > 0.0);
+            assert!((sample.tx_kbps / sample.rx_kbps - 0.25).abs() < 1e-9);
+            assert_eq!(sample.interfaces.len(), 1);
+            assert_eq!(sample.interfaces[0].name, "eth0");
+        }
+        assert!(source.take_notices().is_empty());
+    }
 
-                    if new_config.test_tx {
-                        state.start_tx_kbps = state.current_tx_kbps;
-                        state.current_tx_kbps = test_tx_kbps;
-                        state.last_bandwidth_update = Some(Instant::now());
-                    }
+    #[tokio::test]
+    async fn snmp_source_reports_repeated_poll_failures_and_reconnects() {
+        let agent_port = spawn_snmp_responder().await;
+        let config = BandwidthConfig {
+            source: "snmp".to_string(),
+            snmp_host: "127.0.0.1".to_string(),
+            snmp_port: agent_port,
+            interface: "eth0".to_string(),
+            sample_interval_ms: 20.0,
+            ..BandwidthConfig::default()
+        };
+        let mut source = SnmpSource::connect(&config).await.unwrap();
+        source.next_sample().await.unwrap().unwrap();
+
+        // A closed port refuses every poll, which ends the source instead of leaving the strip stale
+        let closed_port = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        source.client.socket.connect(("127.0.0.1", closed_port)).await.unwrap();
+        assert!(source.next_sample().await.is_err());
+        assert!(source.can_reconnect());
+        assert!(source.reconnect().await.is_err());
+
+        source.client.socket.connect(("127.0.0.1", agent_port)).await.unwrap();
+        source.reconnect().await.unwrap();
+        let sample = source.next_sample().await.unwrap().unwrap();
+        assert!(sample.rx_kbps > 0.0);
+    }
 
-                    drop(state);
+    #[tokio::test]
+    async fn snmp_v3_client_rejects_unsigned_responses_when_authenticating() {
+        let engine = || SnmpEngine {
+            id: vec![0x80, 0, 0x1f, 0x88, 1],
+            boots: 3,
+            time: 1000,
+        };
+        let session = UsmSession {
+            engine: engine(),
+            synced_at: Instant::now(),
+            auth_key: snmp_localize_key(SnmpAuthProtocol::Md5, "maplesyrup", &engine().id).unwrap(),
+        };
+        let client = SnmpClient {
+            socket: tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+            security: SnmpSecurity::Usm {
+                username: "meter".to_string(),
+                auth: SnmpAuthProtocol::Md5,
+                password: "maplesyrup".to_string(),
+                session: Some(session),
+            },
+            next_id: 0,
+        };
+        let SnmpSecurity::Usm { session: Some(session), .. } = &client.security else {
+            unreachable!()
+        };
+        let oids = [OID_IF_HC_IN_OCTETS.to_vec()];
+
+        let response = encode_snmp_pdu(SNMP_RESPONSE, 7, &oids);
+        let signed = encode_v3_message(7, &response, "meter", SnmpAuthProtocol::Md5, Some(session));
+        assert_eq!(client.decode_message(&signed).unwrap().pdu.request_id, 7);
+
+        let unsigned = encode_v3_message(7, &response, "meter", SnmpAuthProtocol::None, Some(session));
+        let err = client.decode_message(&unsigned).err().unwrap();
+        assert_eq!(err.to_string(), "Unauthenticated SNMPv3 response rejected");
+
+        // Flip the last OID sub-identifier, just ahead of the trailing NULL value
+        let mut tampered = signed.clone();
+        let at = tampered.len() - 3;
+        tampered[at] ^= 1;
+        let err = client.decode_message(&tampered).err().unwrap();
+        assert_eq!(err.to_string(), "SNMPv3 response failed authentication");
+
+        // Reports about our credentials can't be signed by the agent
+        let report = encode_snmp_pdu(SNMP_REPORT, 7, &[OID_USM_STATS_WRONG_DIGESTS.to_vec()]);
+        let unsigned = encode_v3_message(7, &report, "meter", SnmpAuthProtocol::None, Some(session));
+        assert_eq!(client.decode_message(&unsigned).unwrap().pdu.pdu_type, SNMP_REPORT);
+    }
 
-                    if !quiet {
-                        if new_config.test_tx != config.test_tx {
-                            messages.push(format!(
-                                "[{}] Test TX: {}",
-                                get_timestamp(),
-                                if new_config.test_tx { "enabled" } else { "disabled" }
-                            ));
-                        }
-                        if new_config.test_rx != config.test_rx {
-                            messages.push(format!(
-                                "[{}] Test RX: {}",
-                                get_timestamp(),
-                                if new_config.test_rx { "enabled" } else { "disabled" }
-                            ));
-                        }
-                        if new_config.test_tx_percent != config.test_tx_percent && new_config.test_tx {
-                            messages.push(format!(
-                                "[{}] Test TX utilization: {:.0}%",
-                                get_timestamp(),
-                                new_config.test_tx_percent
-                            ));
-                        }
-                        if new_config.test_rx_percent != config.test_rx_percent && new_config.test_rx {
-                            messages.push(format!(
-                                "[{}] Test RX utilization: {:.0}%",
-                                get_timestamp(),
-                                new_config.test_rx_percent
-                            ));
-                        }
-                    }
-                }
+    #[tokio::test]
+    async fn snmp_source_rejects_unknown_version_and_auth_protocol() {
+        let config = |version: &str, auth: &str| BandwidthConfig {
+            source: "snmp".to_string(),
+            snmp_host: "127.0.0.1".to_string(),
+            snmp_version: version.to_string(),
+            snmp_auth_protocol: auth.to_string(),
+            ..BandwidthConfig::default()
+        };
+        let err = SnmpSource::connect(&config("1", "none")).await.err().unwrap();
+        assert!(err.to_string().contains("\"1\""), "{}", err);
+        let err = SnmpSource::connect(&config("3", "sha256")).await.err().unwrap();
+        assert!(err.to_string().contains("\"sha256\""), "{}", err);
+        let err = SnmpSource::connect(&config("3", "SHA-1")).await.err().unwrap();
+        assert!(err.to_string().contains("\"sha-1\""), "{}", err);
+    }
 
-                // Update config for future comparisons
-                config = new_config;
+    fn sample(rx_kbps: f64, tx_kbps: f64) -> BandwidthSample {
+        BandwidthSample {
+            timestamp: Instant::now(),
+            rx_kbps,
+            tx_kbps,
+            packets: PacketStats::default(),
+            interfaces: Vec::new(),
+        }
+    }
 
-                needs_render = true;
+    // Hands out scripted samples with a notice after each one, then ends
+    struct StubSource {
+        samples: VecDeque<BandwidthSample>,
+        notices: Vec<String>,
+    }
+
+    impl StubSource {
+        fn new(samples: Vec<BandwidthSample>) -> Self {
+            StubSource {
+                samples: samples.into(),
+                notices: Vec::new(),
             }
-            Err(_) => {
-                // No config update
+        }
+    }
+
+    impl BandwidthSource for StubSource {
+        fn metadata(&self) -> SourceMetadata {
+            SourceMetadata {
+                kind: "stub",
+                description: "scripted samples".to_string(),
             }
         }
 
-        // Render only when something changed
-        if needs_render {
-            let status_text = if config.httpd_enabled {
-                format!(
-                    "Edit {} to change settings | Web UI: http://{}:{} | Press Ctrl+C to quit",
-                    config_path.display(),
-                    config.httpd_ip,
-                    config.httpd_port
-                )
+        fn next_sample(&mut self) -> SampleFuture<'_> {
+            let sample = self.samples.pop_front();
+            if let Some(ref sample) = sample {
+                self.notices.push(format!("delivered {}", sample.rx_kbps));
+            }
+            Box::pin(async move { Ok(sample) })
+        }
+
+        fn take_notices(&mut self) -> Vec<String> {
+            std::mem::take(&mut self.notices)
+        }
+    }
+
+    #[tokio::test]
+    async fn run_bandwidth_source_delivers_samples_then_reports_the_end() {
+        let (bandwidth_tx, bandwidth_rx) = mpsc::channel();
+        let (log_tx, log_rx) = mpsc::channel();
+        let source = StubSource::new(vec![sample(100.0, 10.0), sample(200.0, 20.0)]);
+        run_bandwidth_source(Box::new(source), bandwidth_tx, log_tx).await;
+
+        let events: Vec<SourceEvent> = bandwidth_rx.try_iter().collect();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(&events[0], SourceEvent::Sample(s) if s.rx_kbps == 100.0 && s.tx_kbps == 10.0));
+        assert!(matches!(&events[1], SourceEvent::Sample(s) if s.rx_kbps == 200.0 && s.tx_kbps == 20.0));
+        assert!(matches!(&events[2], SourceEvent::Lost(reason) if reason == "output ended"));
+        let log: Vec<String> = log_rx.try_iter().collect();
+        assert_eq!(log, ["delivered 100", "delivered 200", "stub source ended and can't reconnect, restart to resume"]);
+    }
+
+    // Delivers one batch of samples per session; reconnects fail `failures` times before the next batch
+    struct ReconnectingStub {
+        samples: VecDeque<BandwidthSample>,
+        batches: VecDeque<Vec<BandwidthSample>>,
+        failures: u32,
+    }
+
+    impl BandwidthSource for ReconnectingStub {
+        fn metadata(&self) -> SourceMetadata {
+            SourceMetadata {
+                kind: "stub",
+                description: "reconnecting samples".to_string(),
+            }
+        }
+
+        fn next_sample(&mut self) -> SampleFuture<'_> {
+            let sample = self.samples.pop_front();
+            Box::pin(async move { Ok(sample) })
+        }
+
+        fn can_reconnect(&self) -> bool {
+            !self.batches.is_empty()
+        }
+
+        fn reconnect(&mut self) -> ConnectFuture<'_> {
+            let result = if self.failures > 0 {
+                self.failures -= 1;
+                Err(anyhow::anyhow!("agent unreachable"))
             } else {
-                format!(
-                    "Edit {} to change settings | Press Ctrl+C to quit",
-                    config_path.display()
-                )
+                self.samples = self.batches.pop_front().unwrap_or_default().into();
+                Ok(())
             };
+            Box::pin(async move { result })
+        }
+    }
 
-            terminal.draw(|f| {
-                let chunks = Layout::default()
-                    .direction(Direction::Vertical)
-                    .constraints([Constraint::Min(1), Constraint::Length(3)].as_ref())
-                    .split(f.size());
+    #[tokio::test(start_paused = true)]
+    async fn run_bandwidth_source_reconnects_with_capped_backoff() {
+        let (bandwidth_tx, bandwidth_rx) = mpsc::channel();
+        let (log_tx, log_rx) = mpsc::channel();
+        let source = ReconnectingStub {
+            samples: vec![sample(100.0, 10.0)].into(),
+            batches: vec![vec![sample(200.0, 20.0)]].into(),
+            failures: 7,
+        };
+        let started = tokio::time::Instant::now();
+        run_bandwidth_source(Box::new(source), bandwidth_tx, log_tx).await;
+
+        let events: Vec<SourceEvent> = bandwidth_rx.try_iter().collect();
+        assert_eq!(events.len(), 5);
+        assert!(matches!(&events[0], SourceEvent::Sample(s) if s.rx_kbps == 100.0));
+        assert!(matches!(&events[1], SourceEvent::Lost(reason) if reason == "output ended"));
+        assert!(matches!(&events[2], SourceEvent::Restored));
+        assert!(matches!(&events[3], SourceEvent::Sample(s) if s.rx_kbps == 200.0));
+        assert!(matches!(&events[4], SourceEvent::Lost(reason) if reason == "output ended"));
+
+        // The delay doubles after every failed attempt and stops at 60 seconds
+        let log: Vec<String> = log_rx.try_iter().collect();
+        let delays: Vec<&str> = log.iter().filter_map(|line| line.strip_prefix("Reconnecting in ")).collect();
+        assert_eq!(delays, ["1s...", "2s...", "4s...", "8s...", "16s...", "32s...", "60s...", "60s..."]);
+        assert_eq!(log.iter().filter(|line| *line == "Reconnect failed: agent unreachable").count(), 7);
+        assert_eq!(log.last().unwrap(), "stub source ended and can't reconnect, restart to resume");
+        let waited = started.elapsed();
+        assert!(waited >= Duration::from_secs(183) && waited < Duration::from_secs(184), "{:?}", waited);
+    }
 
-                // Messages area
-                let messages_text: Vec<Line> = messages
-                    .iter()
-                    .rev()
-                    .take(chunks[0].height as usize)
-                    .rev()
-                    .map(|m| Line::from(m.as_str()))
-                    .collect();
+    #[test]
+    fn prometheus_lines_parse_labels_values_and_timestamps() {
+        let series = parse_prometheus_line(r#"node_network_receive_bytes_total{device="eth0",job="node"} 1.5e+06"#).unwrap();
+        assert_eq!(series.name, "node_network_receive_bytes_total");
+        assert_eq!(series.labels, [("device".to_string(), "eth0".to_string()), ("job".to_string(), "node".to_string())]);
+        assert_eq!(series.value, 1.5e6);
+
+        // Trailing millisecond timestamp
+        let series = parse_prometheus_line("ifHCInOctets{ifName=\"ge-0/0/1\"} 123456 1712345678901").unwrap();
+        assert_eq!(series.value, 123456.0);
+
+        // Escaped quotes, backslashes and newlines, and commas or braces inside values
+        let series = parse_prometheus_line(r#"x{a="b,c}",b="say \"hi\"",c="C:\\net\n"} 3"#).unwrap();
+        assert_eq!(series.labels[0].1, "b,c}");
+        assert_eq!(series.labels[1].1, r#"say "hi""#);
+        assert_eq!(series.labels[2].1, "C:\\net\n");
+        assert_eq!(series.value, 3.0);
+
+        assert_eq!(parse_prometheus_line("up +Inf").unwrap().value, f64::INFINITY);
+        assert_eq!(parse_prometheus_line("up -Inf").unwrap().value, f64::NEG_INFINITY);
+        assert!(parse_prometheus_line("up{} NaN").unwrap().value.is_nan());
+        assert!(parse_prometheus_line("up{job=\"x\",} 1").unwrap().labels.len() == 1);
+
+        assert!(parse_prometheus_line("# HELP up Whether the target is up").is_none());
+        assert!(parse_prometheus_line("").is_none());
+        assert!(parse_prometheus_line(r#"x{a="unterminated} 1"#).is_none());
+        assert!(parse_prometheus_line("x 1.2.3").is_none());
+    }
 
-                let messages_widget = Paragraph::new(messages_text).block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title("Bandwidth Monitor"),
-                );
-                f.render_widget(messages_widget, chunks[0]);
+    #[tokio::test]
+    async fn prometheus_source_reports_a_failing_exporter() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Stand-in exporter that answers every scrape with a 404
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .await;
+            }
+        });
 
-                // Status/Input area
-                let status = Paragraph::new(status_text.clone())
-                    .block(Block::default().borders(Borders::ALL).title("Status"));
-                f.render_widget(status, chunks[1]);
-            })?;
+        let config = BandwidthConfig {
+            source: "prometheus".to_string(),
+            prometheus_url: format!("http://127.0.0.1:{}/metrics", port),
+            interface: "eth0".to_string(),
+            sample_interval_ms: 10.0,
+            ..BandwidthConfig::default()
+        };
+        let mut source = PrometheusSource::new(&config).unwrap();
+        let err = source.next_sample().await.err().unwrap();
+        assert!(err.to_string().contains("404"), "{}", err);
+        assert!(source.can_reconnect());
+        assert!(source.reconnect().await.is_err());
+
+        // The detected link speed reaches the tracker, so counter resets are told from wraps
+        source.set_link_limit_kbps(2_500_000.0);
+        assert_eq!(source.tracker.link_limit_kbps, 2_500_000.0);
+    }
 
-            needs_render = false;
+    #[test]
+    fn prometheus_selectors_handle_negation_and_quoted_commas() {
+        let matchers = parse_label_selector(r#"device="eth0", instance!="backup:9100""#).unwrap();
+        assert_eq!(matchers.len(), 2);
+        assert_eq!((matchers[0].label.as_str(), matchers[0].value.as_str(), matchers[0].negate), ("device", "eth0", false));
+        assert_eq!((matchers[1].label.as_str(), matchers[1].value.as_str(), matchers[1].negate), ("instance", "backup:9100", true));
+
+        let labels = |device: &str| vec![("device".to_string(), device.to_string()), ("instance".to_string(), "main:9100".to_string())];
+        assert!(matchers.iter().all(|m| m.matches(&labels("eth0"))));
+        assert!(!matchers.iter().all(|m| m.matches(&labels("eth1"))));
+
+        let matchers = parse_label_selector(r#"{device="a,b"}"#).unwrap();
+        assert_eq!(matchers.len(), 1);
+        assert_eq!(matchers[0].value, "a,b");
+
+        // Unquoted values are still accepted
+        assert_eq!(parse_label_selector("device=eth0").unwrap()[0].value, "eth0");
+        assert!(parse_label_selector("").unwrap().is_empty());
+
+        assert!(parse_label_selector(r#"device=~"eth.*""#).is_err());
+        assert!(parse_label_selector(r#"device!~"lo""#).is_err());
+        assert!(parse_label_selector("device").is_err());
+        assert!(parse_label_selector(r#"device="eth0"} extra"#).is_err());
+    }
+
+    fn night_schedule(fade_minutes: f64) -> Schedule {
+        Schedule {
+            entries: Vec::new(),
+            fade_minutes,
+            night: Some((22 * 60, 6 * 60)),
+            night_min_utilization: 0.10,
+            night_lit: false,
+            night_gate: None,
+        }
+    }
+
+    #[test]
+    fn night_rule_fades_with_hysteresis() {
+        let midnight = 0.0;
+        let mut schedule = night_schedule(2.0);
+
+        // The first frame starts at the target instead of fading in from nothing
+        assert_eq!(schedule.level_with(midnight, 0.01, 0.0), 0.0);
+
+        // Crossing the threshold ramps up over the fade time
+        assert_eq!(schedule.level_with(midnight, 0.12, 60.0), 0.5);
+
+        // Dipping just under the threshold keeps it on, well under turns it off
+        assert_eq!(schedule.level_with(midnight, 0.09, 30.0), 0.75);
+        assert_eq!(schedule.level_with(midnight, 0.07, 30.0), 0.5);
+        assert_eq!(schedule.level_with(midnight, 0.09, 60.0), 0.0);
+        assert_eq!(schedule.level_with(midnight, 0.09, 60.0), 0.0);
+
+        // Daytime ignores the threshold
+        assert_eq!(schedule.level_with(12.0 * 60.0, 0.0, 120.0), 1.0);
+
+        // Without a fade the gate switches on the frame the threshold is crossed
+        let mut schedule = night_schedule(0.0);
+        assert_eq!(schedule.level_with(midnight, 0.0, 0.0), 0.0);
+        assert_eq!(schedule.level_with(midnight, 0.10, 0.016), 1.0);
+        assert_eq!(schedule.level_with(midnight, 0.085, 0.016), 1.0);
+        assert_eq!(schedule.level_with(midnight, 0.075, 0.016), 0.0);
+    }
+
+    #[test]
+    fn interface_names_with_shell_syntax_are_rejected() {
+        for name in ["eth0", "enp3s0f1", "br-lan", "eth0.100", "wg_home", "veth1@if2", "bond0:1"] {
+            assert!(is_valid_interface_name(name), "{name}");
+        }
+        for name in ["", "eth0;rm -rf ~", "$(reboot)", "eth0 eth1", "`id`", "eth0|cat", "../eth0/x", "eth'0"] {
+            assert!(!is_valid_interface_name(name), "{name}");
         }
 
-        // Small sleep to avoid busy-waiting CPU at 100%
-        // Renderer runs in separate thread, so main loop can sleep longer
-        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert!(validate_interface_list("eth0, wlan0,br-lan").is_ok());
+        let error = validate_interface_list("eth0,eth1;reboot").unwrap_err();
+        assert_eq!(error.to_string(), r#"Invalid interface name: "eth1;reboot""#);
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn shell_sources_refuse_interface_names_with_shell_syntax() {
+        let args = Args::parse_from(["bandwidth_meter", "--host", "router"]);
+        let config = BandwidthConfig {
+            interface: "eth0$(reboot)".to_string(),
+            ..BandwidthConfig::default()
+        };
+        // Fails before any ssh process is started
+        let error = create_bandwidth_source(&args, &config).await.err().unwrap();
+        assert_eq!(error.to_string(), r#"Invalid interface name: "eth0$(reboot)""#);
+    }
 }